@@ -0,0 +1,546 @@
+use std::cmp;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use image::{self, ImageError, RgbaImage};
+use ron;
+
+use renderer::{Filtering, Renderer, RendererError, TextureId};
+use sprite::{Sprite, UvRect};
+use ty::WorldRect;
+
+#[derive(Debug)]
+pub enum AtlasError {
+    Io(PathBuf, io::Error),
+    Image(PathBuf, ImageError),
+    Manifest(PathBuf, String),
+
+    // Source image does not fit on a single page, even when empty
+    TooLarge(String),
+
+    // Source image has no pixels to pack
+    Empty(String),
+}
+
+impl fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AtlasError::Io(ref path, ref err) => write!(f, "{}: {}", path.display(), err),
+            AtlasError::Image(ref path, ref err) => write!(f, "{}: {}", path.display(), err),
+            AtlasError::Manifest(ref path, ref err) => write!(f, "{}: invalid manifest: {}", path.display(), err),
+            AtlasError::TooLarge(ref name) => write!(f, "Sprite {} is larger than an atlas page", name),
+            AtlasError::Empty(ref name) => write!(f, "Sprite {} has no pixels", name),
+        }
+    }
+}
+
+impl Error for AtlasError {
+    fn description(&self) -> &str {
+        match *self {
+            AtlasError::Io(..) => "io error",
+            AtlasError::Image(..) => "image error",
+            AtlasError::Manifest(..) => "invalid manifest",
+            AtlasError::TooLarge(_) => "sprite too large",
+            AtlasError::Empty(_) => "empty sprite",
+        }
+    }
+}
+
+// Location of one packed sprite
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct AtlasRegion {
+    pub page: usize,
+
+    // Pixel rectangle on the page, excluding padding and extrusion
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+
+    pub uv: UvRect,
+}
+
+// Written next to the page pngs. Page paths are relative to the manifest.
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    page_size: u32,
+    pages: Vec<String>,
+    sprites: BTreeMap<String, AtlasRegion>,
+}
+
+pub struct AtlasBuilder {
+    page_size: u32,
+    padding: u32,
+    extrude: u32,
+
+    images: Vec<(String, RgbaImage)>,
+}
+
+impl AtlasBuilder {
+    pub fn new(page_size: u32) -> AtlasBuilder {
+        AtlasBuilder {
+            page_size: page_size,
+            padding: 2,
+            extrude: 1,
+
+            images: Vec::new(),
+        }
+    }
+
+    // Empty pixels left between neighbouring sprites
+    pub fn padding(mut self, padding: u32) -> AtlasBuilder {
+        self.padding = padding;
+        self
+    }
+
+    // Number of times each sprite's edge pixels are repeated outwards, to
+    // stop filtering from bleeding in neighbouring texels
+    pub fn extrude(mut self, extrude: u32) -> AtlasBuilder {
+        self.extrude = extrude;
+        self
+    }
+
+    pub fn add<S: Into<String>>(&mut self, name: S, image: RgbaImage) {
+        self.images.push((name.into(), image));
+    }
+
+    // Adds every png in the directory, named by file stem
+    pub fn add_dir<P: AsRef<Path>>(&mut self, dir: P) -> Result<(), AtlasError> {
+        let dir = dir.as_ref();
+        let entries = fs::read_dir(dir).map_err(|err| AtlasError::Io(dir.to_owned(), err))?;
+
+        for entry in entries {
+            let path = entry.map_err(|err| AtlasError::Io(dir.to_owned(), err))?.path();
+
+            if path.extension().map(|ext| ext == "png").unwrap_or(false) {
+                let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+                let image = image::open(&path).map_err(|err| AtlasError::Image(path.clone(), err))?;
+                self.add(name, image.to_rgba());
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn pack(mut self) -> Result<Atlas, AtlasError> {
+        // Largest first packs tighter. Ties are broken by name so that the
+        // output does not depend on directory order.
+        self.images.sort_by(|&(ref a_name, ref a), &(ref b_name, ref b)| {
+            let a_size = cmp::max(a.width(), a.height());
+            let b_size = cmp::max(b.width(), b.height());
+            b_size.cmp(&a_size).then_with(|| a_name.cmp(b_name))
+        });
+
+        let border = self.extrude * 2 + self.padding;
+        let mut bins: Vec<MaxRects> = Vec::new();
+        let mut pages: Vec<RgbaImage> = Vec::new();
+        let mut sprites = BTreeMap::new();
+
+        for (name, image) in self.images {
+            let (w, h) = image.dimensions();
+            let (slot_w, slot_h) = (w + border, h + border);
+
+            if w == 0 || h == 0 {
+                return Err(AtlasError::Empty(name));
+            }
+
+            if slot_w > self.page_size || slot_h > self.page_size {
+                return Err(AtlasError::TooLarge(name));
+            }
+
+            let existing = bins.iter_mut()
+                .enumerate()
+                .filter_map(|(page, bin)| bin.insert(slot_w, slot_h).map(|slot| (page, slot)))
+                .next();
+
+            let (page, slot) = match existing {
+                Some(placed) => placed,
+                None => {
+                    let mut bin = MaxRects::new(self.page_size);
+                    let slot = bin.insert(slot_w, slot_h).unwrap();
+
+                    bins.push(bin);
+                    pages.push(RgbaImage::new(self.page_size, self.page_size));
+                    (pages.len() - 1, slot)
+                }
+            };
+
+            let (x, y) = (slot.x + self.extrude, slot.y + self.extrude);
+            blit_extruded(&mut pages[page], &image, x, y, self.extrude);
+
+            sprites.insert(name, AtlasRegion {
+                page: page,
+                x: x,
+                y: y,
+                w: w,
+                h: h,
+                uv: UvRect::from_pixels(x, y, w, h, [self.page_size, self.page_size]),
+            });
+        }
+
+        Ok(Atlas {
+            page_size: self.page_size,
+            pages: pages,
+            sprites: sprites,
+        })
+    }
+}
+
+pub struct Atlas {
+    pub page_size: u32,
+    pub pages: Vec<RgbaImage>,
+    pub sprites: BTreeMap<String, AtlasRegion>,
+}
+
+impl Atlas {
+    pub fn region(&self, name: &str) -> Option<AtlasRegion> {
+        self.sprites.get(name).cloned()
+    }
+
+    // Writes `<stem>_<page>.png` for each page and `<stem>.ron` as the
+    // manifest, where stem is the manifest path without its extension
+    pub fn save<P: AsRef<Path>>(&self, manifest_path: P) -> Result<(), AtlasError> {
+        let manifest_path = manifest_path.as_ref().with_extension("ron");
+        let stem = manifest_path.file_stem().unwrap().to_string_lossy().into_owned();
+
+        let mut page_names = Vec::new();
+
+        for (index, page) in self.pages.iter().enumerate() {
+            let name = format!("{}_{}.png", stem, index);
+            let path = manifest_path.with_file_name(&name);
+
+            page.save(&path).map_err(|err| AtlasError::Io(path.clone(), err))?;
+            page_names.push(name);
+        }
+
+        let manifest = Manifest {
+            page_size: self.page_size,
+            pages: page_names,
+            sprites: self.sprites.clone(),
+        };
+
+        let text = ron::ser::to_string_pretty(&manifest, Default::default())
+            .map_err(|err| AtlasError::Manifest(manifest_path.clone(), err.to_string()))?;
+
+        File::create(&manifest_path)
+            .and_then(|mut file| file.write_all(text.as_bytes()))
+            .map_err(|err| AtlasError::Io(manifest_path.clone(), err))
+    }
+
+    pub fn load<P: AsRef<Path>>(manifest_path: P) -> Result<Atlas, AtlasError> {
+        let manifest_path = manifest_path.as_ref();
+
+        let mut text = String::new();
+        File::open(manifest_path)
+            .and_then(|mut file| file.read_to_string(&mut text))
+            .map_err(|err| AtlasError::Io(manifest_path.to_owned(), err))?;
+
+        let manifest: Manifest = ron::de::from_str(&text)
+            .map_err(|err| AtlasError::Manifest(manifest_path.to_owned(), err.to_string()))?;
+
+        let mut pages = Vec::new();
+
+        for name in &manifest.pages {
+            let path = manifest_path.with_file_name(name);
+            let page = image::open(&path).map_err(|err| AtlasError::Image(path.clone(), err))?;
+            pages.push(page.to_rgba());
+        }
+
+        for (name, region) in &manifest.sprites {
+            let page = match pages.get(region.page) {
+                Some(page) => page,
+                None => {
+                    let err = format!("sprite {} refers to a missing page", name);
+                    return Err(AtlasError::Manifest(manifest_path.to_owned(), err));
+                }
+            };
+
+            // Widened so that a corrupt region can not wrap around
+            let right = region.x as u64 + region.w as u64;
+            let bottom = region.y as u64 + region.h as u64;
+
+            if right > page.width() as u64 || bottom > page.height() as u64 {
+                let err = format!("sprite {} lies outside its page", name);
+                return Err(AtlasError::Manifest(manifest_path.to_owned(), err));
+            }
+        }
+
+        Ok(Atlas {
+            page_size: manifest.page_size,
+            pages: pages,
+            sprites: manifest.sprites,
+        })
+    }
+
+    // Upload every page as a texture
+    pub fn upload(&self, renderer: &mut Renderer, filtering: Filtering) -> Result<AtlasTextures, RendererError> {
+        let pages = self.pages.iter()
+            .map(|page| renderer.create_texture(page, filtering))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(AtlasTextures {
+            pages: pages,
+            sprites: self.sprites.clone(),
+        })
+    }
+}
+
+// An atlas whose pages live on the gpu
+pub struct AtlasTextures {
+    pages: Vec<TextureId>,
+    sprites: BTreeMap<String, AtlasRegion>,
+}
+
+impl AtlasTextures {
+    pub fn lookup(&self, name: &str) -> Option<(TextureId, UvRect)> {
+        self.sprites.get(name).map(|region| (self.pages[region.page], region.uv))
+    }
+
+    pub fn sprite(&self, name: &str, rect: WorldRect) -> Option<Sprite> {
+        self.lookup(name).map(|(texture, uv)| Sprite::textured(rect, texture, uv))
+    }
+}
+
+// Copy `image` onto `page` at (x, y), repeating its edge pixels `extrude`
+// times in every direction
+fn blit_extruded(page: &mut RgbaImage, image: &RgbaImage, x: u32, y: u32, extrude: u32) {
+    let (w, h) = image.dimensions();
+    let extrude = extrude as i64;
+
+    for dy in -extrude .. h as i64 + extrude {
+        for dx in -extrude .. w as i64 + extrude {
+            let sx = cmp::min(cmp::max(dx, 0), w as i64 - 1) as u32;
+            let sy = cmp::min(cmp::max(dy, 0), h as i64 - 1) as u32;
+
+            let px = (x as i64 + dx) as u32;
+            let py = (y as i64 + dy) as u32;
+            page.put_pixel(px, py, *image.get_pixel(sx, sy));
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Rect {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+impl Rect {
+    fn right(&self) -> u32 {
+        self.x + self.w
+    }
+
+    fn bottom(&self) -> u32 {
+        self.y + self.h
+    }
+
+    fn intersects(&self, other: &Rect) -> bool {
+        self.x < other.right() && other.x < self.right() &&
+        self.y < other.bottom() && other.y < self.bottom()
+    }
+
+    fn contains(&self, other: &Rect) -> bool {
+        other.x >= self.x && other.right() <= self.right() &&
+        other.y >= self.y && other.bottom() <= self.bottom()
+    }
+}
+
+// Maximal rectangles bin packer using the best short side fit heuristic
+struct MaxRects {
+    free: Vec<Rect>,
+}
+
+impl MaxRects {
+    fn new(size: u32) -> MaxRects {
+        MaxRects {
+            free: vec![Rect { x: 0, y: 0, w: size, h: size }],
+        }
+    }
+
+    fn insert(&mut self, w: u32, h: u32) -> Option<Rect> {
+        let placed = self.free.iter()
+            .filter(|free| free.w >= w && free.h >= h)
+            .min_by_key(|free| {
+                let (dw, dh) = (free.w - w, free.h - h);
+                (cmp::min(dw, dh), cmp::max(dw, dh), free.y, free.x)
+            })
+            .map(|free| Rect { x: free.x, y: free.y, w: w, h: h })?;
+
+        let mut free = Vec::with_capacity(self.free.len() + 4);
+
+        for rect in self.free.drain(..) {
+            if !rect.intersects(&placed) {
+                free.push(rect);
+                continue;
+            }
+
+            // Keep whatever is left of the free rectangle on each side
+            if placed.x > rect.x {
+                free.push(Rect { x: rect.x, y: rect.y, w: placed.x - rect.x, h: rect.h });
+            }
+
+            if placed.right() < rect.right() {
+                free.push(Rect { x: placed.right(), y: rect.y, w: rect.right() - placed.right(), h: rect.h });
+            }
+
+            if placed.y > rect.y {
+                free.push(Rect { x: rect.x, y: rect.y, w: rect.w, h: placed.y - rect.y });
+            }
+
+            if placed.bottom() < rect.bottom() {
+                free.push(Rect { x: rect.x, y: placed.bottom(), w: rect.w, h: rect.bottom() - placed.bottom() });
+            }
+        }
+
+        // Drop free rectangles that are wholly inside another
+        let mut pruned: Vec<Rect> = Vec::with_capacity(free.len());
+        for (i, rect) in free.iter().enumerate() {
+            let redundant = free.iter().enumerate().any(|(j, other)| {
+                i != j && other.contains(rect) && (other != rect || j < i)
+            });
+
+            if !redundant {
+                pruned.push(*rect);
+            }
+        }
+
+        self.free = pruned;
+        Some(placed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use image::{Rgba, RgbaImage};
+
+    use rng::Rng;
+    use sprite::UvRect;
+    use super::{Atlas, AtlasBuilder, AtlasError, AtlasRegion};
+
+    fn solid(w: u32, h: u32) -> RgbaImage {
+        RgbaImage::from_pixel(w, h, Rgba([255, 255, 255, 255]))
+    }
+
+    #[test]
+    fn regions_keep_padding_and_extrusion_apart() {
+        let (padding, extrude) = (3, 2);
+        let mut rng = Rng::new(6);
+        let mut builder = AtlasBuilder::new(128).padding(padding).extrude(extrude);
+
+        for i in 0 .. 60 {
+            let (w, h) = (rng.range(1, 40) as u32, rng.range(1, 40) as u32);
+            builder.add(format!("sprite{}", i), solid(w, h));
+        }
+
+        let atlas = builder.pack().unwrap();
+        assert!(atlas.pages.len() > 1);
+
+        // The whole slot each sprite was given, extrusion and padding included
+        let slot = |region: &AtlasRegion| (
+            region.x - extrude,
+            region.y - extrude,
+            region.x + region.w + extrude + padding,
+            region.y + region.h + extrude + padding
+        );
+
+        let regions: Vec<AtlasRegion> = atlas.sprites.values().cloned().collect();
+
+        for (i, a) in regions.iter().enumerate() {
+            let (left, top, right, bottom) = slot(a);
+            assert!(right <= atlas.page_size && bottom <= atlas.page_size);
+
+            for b in &regions[i + 1 ..] {
+                let (other_left, other_top, other_right, other_bottom) = slot(b);
+                let overlap = left < other_right && other_left < right && top < other_bottom && other_top < bottom;
+
+                assert!(a.page != b.page || !overlap, "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn edge_pixels_are_extruded() {
+        let mut image = RgbaImage::new(2, 2);
+        image.put_pixel(0, 0, Rgba([1, 0, 0, 255]));
+        image.put_pixel(1, 0, Rgba([2, 0, 0, 255]));
+        image.put_pixel(0, 1, Rgba([3, 0, 0, 255]));
+        image.put_pixel(1, 1, Rgba([4, 0, 0, 255]));
+
+        let mut builder = AtlasBuilder::new(16).padding(1).extrude(2);
+        builder.add("corners", image.clone());
+
+        let atlas = builder.pack().unwrap();
+        let region = atlas.region("corners").unwrap();
+        let page = &atlas.pages[region.page];
+
+        assert_eq!(page.get_pixel(region.x - 2, region.y - 2), image.get_pixel(0, 0));
+        assert_eq!(page.get_pixel(region.x + 3, region.y - 1), image.get_pixel(1, 0));
+        assert_eq!(page.get_pixel(region.x - 1, region.y + 3), image.get_pixel(0, 1));
+        assert_eq!(page.get_pixel(region.x + 1, region.y + 1), image.get_pixel(1, 1));
+    }
+
+    #[test]
+    fn sprite_larger_than_a_page_is_rejected() {
+        // Fits on its own, but not once padding and extrusion are added
+        let mut builder = AtlasBuilder::new(32).padding(2).extrude(1);
+        builder.add("big", solid(30, 8));
+
+        match builder.pack() {
+            Err(AtlasError::TooLarge(ref name)) => assert_eq!(name, "big"),
+            _ => panic!("expected TooLarge")
+        }
+    }
+
+    #[test]
+    fn empty_sprite_is_rejected() {
+        for &(w, h) in &[(0, 4), (4, 0)] {
+            let mut builder = AtlasBuilder::new(32);
+            builder.add("empty", solid(w, h));
+
+            match builder.pack() {
+                Err(AtlasError::Empty(ref name)) => assert_eq!(name, "empty"),
+                _ => panic!("expected Empty for {}x{}", w, h)
+            }
+        }
+    }
+
+    #[test]
+    fn manifest_regions_outside_their_page_are_rejected() {
+        let dir = env::temp_dir().join("platformer_atlas_bounds");
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut atlas = Atlas {
+            page_size: 16,
+            pages: vec![solid(16, 16)],
+            sprites: Default::default(),
+        };
+
+        atlas.sprites.insert("outside".to_string(), AtlasRegion {
+            page: 0,
+            x: 12,
+            y: 0,
+            w: 8,
+            h: 8,
+            uv: UvRect::full(),
+        });
+
+        let manifest = dir.join("atlas.ron");
+        atlas.save(&manifest).unwrap();
+
+        match Atlas::load(&manifest) {
+            Err(AtlasError::Manifest(..)) => (),
+            _ => panic!("expected a manifest error")
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
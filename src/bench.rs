@@ -0,0 +1,145 @@
+use std::cmp;
+use std::f32;
+use std::time::{Duration, Instant};
+
+use vulkano::instance::{Instance, InstanceExtensions};
+
+use collision;
+use math::Vec2;
+use renderer::{BatchMode, Renderer, RendererConfig, RendererError};
+use sprite::Sprite;
+use terrain::{ray_edge, IndexedEdge, MeshError, TerrainIndex, TerrainMesh};
+use ty::{WorldCoords, WorldBounds, WorldRect};
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1000000.0
+}
+
+// Render `count` small sprites offscreen with each batch mode and report the
+// average frame time. Every frame is waited on so gpu time is included.
+pub fn sprites(count: usize, frames: u32, config: &RendererConfig) -> Result<(), RendererError> {
+    let instance = Instance::new(None, &InstanceExtensions::none(), None)?;
+
+    let mut renderer = Renderer::headless(instance, 800, 600, config)?;
+
+    // Scatter sprites deterministically so runs are comparable
+    let sprites = (0 .. count).map(|i| {
+        Sprite::new(WorldRect {
+            position: WorldCoords((i * 7919 % 800) as i32, (i * 104729 % 600) as i32),
+            bounds: WorldBounds(8, 8)
+        })
+    }).collect::<Vec<_>>();
+
+    for &mode in &[BatchMode::PerSprite, BatchMode::Instanced] {
+        renderer.set_batch_mode(mode);
+
+        // Warm up so buffer allocation is not measured
+        renderer.render(&sprites)?;
+        renderer.wait_idle()?;
+
+        let start = Instant::now();
+
+        for _ in 0 .. frames {
+            renderer.render(&sprites)?;
+            renderer.wait_idle()?;
+        }
+
+        let per_frame = millis(start.elapsed()) / frames as f64;
+        println!("{:?}: {} sprites, {:.3} ms/frame", mode, count, per_frame);
+    }
+
+    Ok(())
+}
+
+// Build a wavy strip of ground with `count` surface vertices and time
+// queries against it through the spatial index and by walking every edge or
+// triangle, then time keeping the index up to date as holes are carved.
+pub fn terrain(count: usize, queries: usize) -> Result<(), MeshError> {
+    let count = cmp::max(count, 4);
+    let width = count as i32 * 8;
+
+    let mut outline: Vec<WorldCoords> = (0 .. count as i32 - 2)
+        .map(|i| WorldCoords(i * 8, 400 + (i as i64 * 7919 % 61) as i32 - (i as i64 * 104729 % 37) as i32))
+        .collect();
+    outline.push(WorldCoords(width, 1000));
+    outline.push(WorldCoords(0, 1000));
+
+    let mut terrain = TerrainMesh::from_outlines(vec![outline])?;
+
+    let start = Instant::now();
+    let mut index = TerrainIndex::new(&terrain);
+    println!("Index of {} edges and {} triangles built in {:.3} ms", index.edge_count(), index.triangle_count(), millis(start.elapsed()));
+
+    let edges: Vec<IndexedEdge> = collision::surface_segments(&terrain).iter()
+        .map(|segment| IndexedEdge { start: segment.start, end: segment.end, normal: segment.normal })
+        .collect();
+    let triangles = terrain.triangles();
+
+    // Spread queries deterministically over the terrain so runs are
+    // comparable
+    let points: Vec<Vec2> = (0 .. queries)
+        .map(|i| Vec2((i * 7919 % width as usize) as f32, (300 + i * 104729 % 400) as f32))
+        .collect();
+
+    let time = |name: &str, indexed: &Fn(&Vec2) -> usize, linear: &Fn(&Vec2) -> usize| {
+        let start = Instant::now();
+        let found: usize = points.iter().map(indexed).sum();
+        let indexed_time = millis(start.elapsed());
+
+        let start = Instant::now();
+        let expected: usize = points.iter().map(linear).sum();
+        let linear_time = millis(start.elapsed());
+
+        println!("{}: {} queries, indexed {:.3} ms, linear {:.3} ms{}", name, queries, indexed_time, linear_time,
+            if found == expected { "" } else { " (results differ)" });
+    };
+
+    time("Box", &|point| {
+        index.edges_in(*point - Vec2(16.0, 16.0), *point + Vec2(16.0, 16.0)).len()
+    }, &|point| {
+        edges.iter().filter(|edge| {
+            edge.start.0.min(edge.end.0) <= point.0 + 16.0 && point.0 - 16.0 <= edge.start.0.max(edge.end.0) &&
+            edge.start.1.min(edge.end.1) <= point.1 + 16.0 && point.1 - 16.0 <= edge.start.1.max(edge.end.1)
+        }).count()
+    });
+
+    time("Point", &|point| index.contains(*point) as usize, &|point| {
+        triangles.iter().any(|triangle| {
+            let side = |a: WorldCoords, b: WorldCoords| {
+                (b.to_render() - a.to_render()).cross(*point - a.to_render())
+            };
+            let sides = [side(triangle[0], triangle[1]), side(triangle[1], triangle[2]), side(triangle[2], triangle[0])];
+
+            sides.iter().all(|&s| s >= 0.0) || sides.iter().all(|&s| s <= 0.0)
+        }) as usize
+    });
+
+    time("Ray", &|point| index.raycast(*point, Vec2(0.0, 1.0), 1000.0).is_some() as usize, &|point| {
+        let nearest = edges.iter()
+            .filter_map(|edge| ray_edge(*point, Vec2(0.0, 1.0), edge))
+            .fold(f32::INFINITY, f32::min);
+
+        (nearest <= 1000.0) as usize
+    });
+
+    // Carve a row of holes along the surface. Both ways of catching up read
+    // the whole mesh, the update only saves refilling the untouched cells.
+    let holes = cmp::min(queries, 100);
+    let (mut updated, mut rebuilt) = (0.0, 0.0);
+
+    for i in 0 .. holes {
+        terrain.subtract_circle(WorldCoords((i * width as usize / holes) as i32, 420), 24);
+
+        let start = Instant::now();
+        index.update(&terrain);
+        updated += millis(start.elapsed());
+
+        let start = Instant::now();
+        TerrainIndex::new(&terrain);
+        rebuilt += millis(start.elapsed());
+    }
+
+    println!("Updates: {} holes, update {:.3} ms, new index {:.3} ms", holes, updated, rebuilt);
+
+    Ok(())
+}
@@ -0,0 +1,71 @@
+// Little endian primitives shared by the binary file formats
+
+use std::io::{self, Read, Write};
+
+// Longest string accepted when reading, so a corrupt length can not ask for
+// an absurd allocation
+const MAX_STRING: usize = 1 << 24;
+
+pub fn write_u8<W: Write>(writer: &mut W, value: u8) -> io::Result<()> {
+    writer.write_all(&[value])
+}
+
+pub fn write_u32<W: Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    writer.write_all(&[value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8])
+}
+
+pub fn write_u64<W: Write>(writer: &mut W, value: u64) -> io::Result<()> {
+    write_u32(writer, value as u32)?;
+    write_u32(writer, (value >> 32) as u32)
+}
+
+pub fn write_i32<W: Write>(writer: &mut W, value: i32) -> io::Result<()> {
+    write_u32(writer, value as u32)
+}
+
+pub fn write_f32<W: Write>(writer: &mut W, value: f32) -> io::Result<()> {
+    write_u32(writer, value.to_bits())
+}
+
+pub fn write_str<W: Write>(writer: &mut W, value: &str) -> io::Result<()> {
+    write_u32(writer, value.len() as u32)?;
+    writer.write_all(value.as_bytes())
+}
+
+pub fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut bytes = [0];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+pub fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24)
+}
+
+pub fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let low = read_u32(reader)? as u64;
+    let high = read_u32(reader)? as u64;
+    Ok(low | high << 32)
+}
+
+pub fn read_i32<R: Read>(reader: &mut R) -> io::Result<i32> {
+    read_u32(reader).map(|value| value as i32)
+}
+
+pub fn read_f32<R: Read>(reader: &mut R) -> io::Result<f32> {
+    read_u32(reader).map(f32::from_bits)
+}
+
+pub fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
+    let len = read_u32(reader)? as usize;
+    if len > MAX_STRING {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "String too long"));
+    }
+
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+
+    String::from_utf8(bytes).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "String is not utf-8"))
+}
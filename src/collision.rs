@@ -0,0 +1,454 @@
+use std::cmp::Ordering;
+use std::f32;
+
+use math::Vec2;
+use terrain::{IndexedEdge, TerrainIndex, TerrainMesh};
+use ty::{WorldCoords, WorldRect};
+
+// Number of slide steps taken when resolving one movement
+const MAX_ITERATIONS: usize = 4;
+
+// Distance kept between a moving rect and the surface it hits
+const SKIN: f32 = 0.01;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Aabb {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl Aabb {
+    pub fn centre(&self) -> Vec2 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn translate(&mut self, delta: Vec2) {
+        self.min += delta;
+        self.max += delta;
+    }
+
+    // Interval covered along an axis
+    fn project(&self, axis: Vec2) -> (f32, f32) {
+        let centre = self.centre().dot(axis);
+        let extent = (self.max.0 - self.min.0) / 2.0 * axis.0.abs()
+                   + (self.max.1 - self.min.1) / 2.0 * axis.1.abs();
+
+        (centre - extent, centre + extent)
+    }
+}
+
+impl<'a> From<&'a WorldRect> for Aabb {
+    fn from(rect: &'a WorldRect) -> Aabb {
+        Aabb {
+            min: rect.min().to_render(),
+            max: rect.max().to_render(),
+        }
+    }
+}
+
+// One edge of the terrain surface. The normal is unit length and points out
+// of the terrain.
+#[derive(Copy, Clone, Debug)]
+pub struct Segment {
+    pub start: Vec2,
+    pub end: Vec2,
+    pub normal: Vec2,
+}
+
+impl Segment {
+    fn project(&self, axis: Vec2) -> (f32, f32) {
+        let (a, b) = (self.start.dot(axis), self.end.dot(axis));
+        if a < b { (a, b) } else { (b, a) }
+    }
+
+    pub fn closest_point(&self, point: Vec2) -> Vec2 {
+        let edge = self.end - self.start;
+        let length = edge.length_squared();
+
+        if length == 0.0 {
+            return self.start;
+        }
+
+        let t = ((point - self.start).dot(edge) / length).max(0.0).min(1.0);
+        self.start + edge * t
+    }
+}
+
+// Overlap between a rect and the terrain. The normal points out of the
+// terrain, moving the rect `depth` along it separates them.
+#[derive(Copy, Clone, Debug)]
+pub struct Contact {
+    pub point: Vec2,
+    pub normal: Vec2,
+    pub depth: f32,
+}
+
+// First contact of a moving rect, `time` is the fraction of the movement
+// completed before touching
+#[derive(Copy, Clone, Debug)]
+pub struct Hit {
+    pub time: f32,
+    pub point: Vec2,
+    pub normal: Vec2,
+}
+
+#[derive(Clone, Debug)]
+pub struct Movement {
+    pub position: WorldCoords,
+
+    // Unrounded position, for carrying sub-unit movement between ticks
+    pub exact_position: Vec2,
+
+    pub hits: Vec<Hit>,
+    pub contacts: Vec<Contact>,
+}
+
+impl<'a> From<&'a IndexedEdge> for Segment {
+    fn from(edge: &'a IndexedEdge) -> Segment {
+        Segment {
+            start: edge.start,
+            end: edge.end,
+            normal: edge.normal,
+        }
+    }
+}
+
+pub fn surface_segments(terrain: &TerrainMesh) -> Vec<Segment> {
+    terrain.surface_chains().iter().flat_map(|chain| {
+        (0 .. chain.edge_count()).map(move |edge| {
+            let (start, end) = chain.edge(edge);
+
+            Segment {
+                start: start,
+                end: end,
+                normal: chain.edge_normal(edge),
+            }
+        })
+    }).collect()
+}
+
+pub fn overlap(aabb: &Aabb, segment: &Segment) -> Option<Contact> {
+    let mut best: Option<(Vec2, f32)> = None;
+
+    for &axis in &[Vec2(1.0, 0.0), Vec2(0.0, 1.0)] {
+        let (a0, a1) = aabb.project(axis);
+        let (b0, b1) = segment.project(axis);
+
+        // Segments along an axis project to a single point, which still
+        // overlaps if it is strictly inside the rect
+        if a1 <= b0 || b1 <= a0 {
+            return None;
+        }
+
+        // Push whichever way is shortest
+        let (normal, depth) = if a1 - b0 < b1 - a0 {
+            (-axis, a1 - b0)
+        } else {
+            (axis, b1 - a0)
+        };
+
+        if best.map(|(_, best_depth)| depth < best_depth).unwrap_or(true) {
+            best = Some((normal, depth));
+        }
+    }
+
+    // The surface is one sided, so along its normal always push outwards
+    let (a0, a1) = aabb.project(segment.normal);
+    let level = segment.start.dot(segment.normal);
+
+    if a1 <= level || a0 >= level {
+        return None;
+    }
+
+    let depth = level - a0;
+    if best.map(|(_, best_depth)| depth < best_depth).unwrap_or(true) {
+        best = Some((segment.normal, depth));
+    }
+
+    best.map(|(normal, depth)| Contact {
+        point: segment.closest_point(aabb.centre()),
+        normal: normal,
+        depth: depth,
+    })
+}
+
+// Separating axis sweep of a moving rect against one segment. Only hits
+// made while moving into the surface count, so rects can always leave.
+pub fn sweep(aabb: &Aabb, delta: Vec2, segment: &Segment) -> Option<Hit> {
+    if delta.dot(segment.normal) >= 0.0 {
+        return None;
+    }
+
+    let mut enter = f32::NEG_INFINITY;
+    let mut exit = f32::INFINITY;
+    let mut normal = segment.normal;
+    let mut surface_enter = f32::NEG_INFINITY;
+
+    for &axis in &[Vec2(1.0, 0.0), Vec2(0.0, 1.0), segment.normal] {
+        let (a0, a1) = aabb.project(axis);
+        let (b0, b1) = segment.project(axis);
+        let v = delta.dot(axis);
+
+        if v == 0.0 {
+            if a1 <= b0 || b1 <= a0 {
+                return None;
+            }
+
+            continue;
+        }
+
+        let t0 = (b0 - a1) / v;
+        let t1 = (b1 - a0) / v;
+        let (axis_enter, axis_exit) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+
+        if axis_enter > enter {
+            enter = axis_enter;
+            normal = if v > 0.0 { -axis } else { axis };
+        }
+
+        if axis == segment.normal {
+            surface_enter = axis_enter;
+        }
+
+        exit = exit.min(axis_exit);
+    }
+
+    // A box corner reaching the end of a segment enters on a box axis and on
+    // the segment normal at the same time, give or take rounding. The
+    // segment wins, so sliding onto a slope climbs it rather than stopping
+    // as if at a wall.
+    if (enter - surface_enter) * delta.length() < SKIN {
+        normal = segment.normal;
+    }
+
+    if enter > exit || enter < 0.0 || enter > 1.0 {
+        return None;
+    }
+
+    let mut moved = *aabb;
+    moved.translate(delta * enter);
+
+    Some(Hit {
+        time: enter,
+        point: segment.closest_point(moved.centre()),
+        normal: normal,
+    })
+}
+
+pub fn contacts(aabb: &Aabb, segments: &[Segment]) -> Vec<Contact> {
+    segments.iter().filter_map(|segment| overlap(aabb, segment)).collect()
+}
+
+// Move a rect by `delta` against the terrain surface, sliding along anything
+// it hits and then pushing it out of any remaining overlap
+pub fn move_rect(rect: &WorldRect, delta: Vec2, terrain: &TerrainIndex) -> Movement {
+    travel(rect, delta, terrain, true)
+}
+
+// Like `move_rect`, but stop at the first hit rather than sliding along it
+pub fn cast_rect(rect: &WorldRect, delta: Vec2, terrain: &TerrainIndex) -> Movement {
+    travel(rect, delta, terrain, false)
+}
+
+fn travel(rect: &WorldRect, delta: Vec2, terrain: &TerrainIndex, slide: bool) -> Movement {
+    let mut aabb = Aabb::from(rect);
+
+    // Only edges the rect could reach matter. Sliding never takes it further
+    // than the whole movement, and each push out of an overlap moves it less
+    // than its own size.
+    let size = (aabb.max.0 - aabb.min.0).max(aabb.max.1 - aabb.min.1);
+    let reach = delta.length() + size * MAX_ITERATIONS as f32 + SKIN;
+    let segments: Vec<Segment> = terrain.edges_in(aabb.min - Vec2(reach, reach), aabb.max + Vec2(reach, reach)).iter()
+        .map(Segment::from)
+        .collect();
+
+    let mut remaining = delta;
+    let mut hits = Vec::new();
+
+    for _ in 0 .. MAX_ITERATIONS {
+        let distance = remaining.length();
+        if distance < SKIN {
+            break;
+        }
+
+        let hit = segments.iter()
+            .filter_map(|segment| sweep(&aabb, remaining, segment))
+            .min_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(Ordering::Equal));
+
+        let hit = match hit {
+            Some(hit) => hit,
+            None => {
+                aabb.translate(remaining);
+                break;
+            }
+        };
+
+        let t = (hit.time - SKIN / distance).max(0.0);
+        aabb.translate(remaining * t);
+        remaining = remaining * (1.0 - t);
+
+        if !slide {
+            hits.push(hit);
+            break;
+        }
+
+        // Slide by dropping the part of the movement going into the surface
+        let into = remaining.dot(hit.normal);
+        if into < 0.0 {
+            remaining -= hit.normal * into;
+        }
+
+        hits.push(hit);
+    }
+
+    let mut resolved = Vec::new();
+
+    for _ in 0 .. MAX_ITERATIONS {
+        let deepest = contacts(&aabb, &segments).into_iter()
+            .max_by(|a, b| a.depth.partial_cmp(&b.depth).unwrap_or(Ordering::Equal));
+
+        match deepest {
+            Some(contact) => {
+                aabb.translate(contact.normal * (contact.depth + SKIN));
+                resolved.push(contact);
+            },
+
+            None => break
+        }
+    }
+
+    Movement {
+        position: WorldCoords::from_render(aabb.min),
+        exact_position: aabb.min,
+        hits: hits,
+        contacts: resolved,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use math::Vec2;
+    use terrain::{TerrainIndex, TerrainMesh};
+    use ty::{WorldBounds, WorldCoords, WorldRect};
+    use super::{cast_rect, move_rect, overlap, sweep, Aabb, Segment};
+
+    // Top of solid ground at y = 100, facing up
+    fn floor() -> Segment {
+        Segment { start: Vec2(0.0, 100.0), end: Vec2(200.0, 100.0), normal: Vec2(0.0, -1.0) }
+    }
+
+    // A platform two units thick
+    fn platform() -> TerrainIndex {
+        let outline = vec![WorldCoords(0, 100), WorldCoords(200, 100), WorldCoords(200, 102), WorldCoords(0, 102)];
+        TerrainIndex::new(&TerrainMesh::from_outlines(vec![outline]).unwrap())
+    }
+
+    // Rising 45 degrees to the right from (0, 200) up to (200, 0)
+    fn slope() -> TerrainIndex {
+        let outline = vec![WorldCoords(0, 200), WorldCoords(200, 0), WorldCoords(200, 200)];
+        TerrainIndex::new(&TerrainMesh::from_outlines(vec![outline]).unwrap())
+    }
+
+    fn rect(x: i32, y: i32) -> WorldRect {
+        WorldRect::new(WorldCoords(x, y), WorldBounds(10, 10))
+    }
+
+    fn close(a: Vec2, b: Vec2) -> bool {
+        (a - b).length() < 1e-4
+    }
+
+    #[test]
+    fn overlap_pushes_out_of_the_surface() {
+        let aabb = Aabb { min: Vec2(10.0, 92.0), max: Vec2(20.0, 102.0) };
+        let contact = overlap(&aabb, &floor()).unwrap();
+
+        assert_eq!(contact.normal, Vec2(0.0, -1.0));
+        assert_eq!(contact.depth, 2.0);
+        assert!(close(contact.point, Vec2(15.0, 100.0)));
+    }
+
+    #[test]
+    fn overlap_needs_the_rect_to_cross_the_surface() {
+        let above = Aabb { min: Vec2(10.0, 80.0), max: Vec2(20.0, 100.0) };
+        let beside = Aabb { min: Vec2(210.0, 95.0), max: Vec2(220.0, 105.0) };
+        let behind = Aabb { min: Vec2(10.0, 110.0), max: Vec2(20.0, 120.0) };
+
+        assert!(overlap(&above, &floor()).is_none());
+        assert!(overlap(&beside, &floor()).is_none());
+        assert!(overlap(&behind, &floor()).is_none());
+    }
+
+    #[test]
+    fn sweep_finds_the_time_of_impact() {
+        let aabb = Aabb { min: Vec2(10.0, 80.0), max: Vec2(20.0, 90.0) };
+        let hit = sweep(&aabb, Vec2(0.0, 20.0), &floor()).unwrap();
+
+        assert_eq!(hit.time, 0.5);
+        assert_eq!(hit.normal, Vec2(0.0, -1.0));
+        assert!(close(hit.point, Vec2(15.0, 100.0)));
+    }
+
+    #[test]
+    fn sweep_ignores_moving_away_short_or_past() {
+        let aabb = Aabb { min: Vec2(10.0, 80.0), max: Vec2(20.0, 90.0) };
+
+        assert!(sweep(&aabb, Vec2(0.0, -20.0), &floor()).is_none());
+        assert!(sweep(&aabb, Vec2(0.0, 5.0), &floor()).is_none());
+        assert!(sweep(&aabb, Vec2(300.0, 0.0), &floor()).is_none());
+    }
+
+    #[test]
+    fn sweep_into_the_foot_of_a_slope_hits_the_slope() {
+        // Up 30 degrees, with the box's corner reaching its foot
+        let aabb = Aabb { min: Vec2(270.0, 170.0), max: Vec2(290.0, 200.0) };
+        let slope = Segment { start: Vec2(300.0, 200.0), end: Vec2(600.0, 27.0), normal: Vec2(-173.0, -300.0).normalize() };
+        let hit = sweep(&aabb, Vec2(20.0, 0.0), &slope).unwrap();
+
+        assert!((hit.time - 0.5).abs() < 1e-4);
+        assert_eq!(hit.normal, slope.normal);
+    }
+
+    #[test]
+    fn sweep_against_a_degenerate_segment_does_not_hit() {
+        let aabb = Aabb { min: Vec2(10.0, 80.0), max: Vec2(20.0, 90.0) };
+        let point = Segment { start: Vec2(15.0, 100.0), end: Vec2(15.0, 100.0), normal: Vec2(0.0, 0.0) };
+
+        assert!(sweep(&aabb, Vec2(0.0, 20.0), &point).is_none());
+        assert!(overlap(&aabb, &point).is_none());
+    }
+
+    #[test]
+    fn move_rect_does_not_tunnel_through_thin_terrain() {
+        let movement = move_rect(&rect(50, 0), Vec2(0.0, 1000.0), &platform());
+
+        assert_eq!(movement.position, WorldCoords(50, 90));
+        assert!(movement.exact_position.1 <= 90.0);
+        assert_eq!(movement.hits[0].normal, Vec2(0.0, -1.0));
+    }
+
+    #[test]
+    fn move_rect_slides_along_the_surface() {
+        let movement = move_rect(&rect(50, 90), Vec2(30.0, 10.0), &platform());
+
+        assert_eq!(movement.position, WorldCoords(80, 90));
+        assert!(movement.contacts.is_empty());
+    }
+
+    #[test]
+    fn cast_rect_stops_where_it_lands() {
+        let cast = cast_rect(&rect(95, 0), Vec2(0.0, 200.0), &slope());
+        let moved = move_rect(&rect(95, 0), Vec2(0.0, 200.0), &slope());
+
+        assert!(close(cast.exact_position, Vec2(95.0, 85.0 - 0.01)));
+        assert_eq!(cast.hits.len(), 1);
+        assert!(moved.exact_position.0 < 90.0);
+    }
+
+    #[test]
+    fn move_rect_pushes_out_of_overlap() {
+        let movement = move_rect(&rect(50, 93), Vec2(0.0, 0.0), &platform());
+
+        assert_eq!(movement.position, WorldCoords(50, 90));
+        assert_eq!(movement.contacts[0].normal, Vec2(0.0, -1.0));
+    }
+}
@@ -0,0 +1,67 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use ron;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Io(ref path, ref err) => write!(f, "{}: {}", path.display(), err),
+            ConfigError::Parse(ref path, ref err) => write!(f, "{}: {}", path.display(), err),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn description(&self) -> &str {
+        match *self {
+            ConfigError::Io(..) => "io error",
+            ConfigError::Parse(..) => "parse error",
+        }
+    }
+}
+
+pub fn load<T: DeserializeOwned, P: AsRef<Path>>(path: P) -> Result<T, ConfigError> {
+    let path = path.as_ref();
+
+    let mut text = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut text))
+        .map_err(|err| ConfigError::Io(path.to_owned(), err))?;
+
+    ron::de::from_str(&text)
+        .map_err(|err| ConfigError::Parse(path.to_owned(), err.to_string()))
+}
+
+pub fn save<T: Serialize, P: AsRef<Path>>(value: &T, path: P) -> Result<(), ConfigError> {
+    let path = path.as_ref();
+
+    let text = ron::ser::to_string_pretty(value, Default::default())
+        .map_err(|err| ConfigError::Parse(path.to_owned(), err.to_string()))?;
+
+    File::create(path)
+        .and_then(|mut file| file.write_all(text.as_bytes()))
+        .map_err(|err| ConfigError::Io(path.to_owned(), err))
+}
+
+// Load a config file, falling back to defaults if it is missing or broken
+pub fn load_or_default<T: DeserializeOwned + Default, P: AsRef<Path>>(path: P) -> T {
+    match load(path) {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("Using defaults: {}", err);
+            T::default()
+        }
+    }
+}
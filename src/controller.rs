@@ -0,0 +1,449 @@
+use collision;
+use math::Vec2;
+use terrain::TerrainIndex;
+use ty::WorldRect;
+
+// Feel of the character, in world units and seconds. Loaded from a config
+// file so it can be tweaked without recompiling, missing fields keep their
+// defaults.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControllerTuning {
+    pub gravity: f32,
+    pub max_fall_speed: f32,
+
+    pub max_run_speed: f32,
+    pub ground_acceleration: f32,
+    pub ground_friction: f32,
+    pub air_acceleration: f32,
+    pub air_friction: f32,
+
+    // Acceleration is scaled by `1 - (speed / max_run_speed) ^ curve`, so
+    // higher values keep full acceleration for longer
+    pub acceleration_curve: f32,
+
+    // Friction is scaled by `(speed / max_run_speed) ^ curve`, zero gives a
+    // constant deceleration
+    pub friction_curve: f32,
+
+    pub jump_speed: f32,
+
+    // Upwards speed is multiplied by this when jump is released early
+    pub jump_cut: f32,
+
+    // Time after leaving the ground that a jump is still allowed
+    pub coyote_time: f32,
+
+    // Time before landing that a jump press is remembered
+    pub jump_buffer: f32,
+
+    // Steepest walkable surface, in degrees
+    pub max_slope: f32,
+
+    // How far down to look for ground when walking off a slope
+    pub snap_distance: f32,
+}
+
+impl Default for ControllerTuning {
+    fn default() -> ControllerTuning {
+        ControllerTuning {
+            gravity: 2400.0,
+            max_fall_speed: 1200.0,
+
+            max_run_speed: 400.0,
+            ground_acceleration: 3000.0,
+            ground_friction: 2500.0,
+            air_acceleration: 1500.0,
+            air_friction: 300.0,
+
+            acceleration_curve: 2.0,
+            friction_curve: 0.0,
+
+            jump_speed: 900.0,
+            jump_cut: 0.5,
+
+            coyote_time: 0.1,
+            jump_buffer: 0.1,
+
+            max_slope: 50.0,
+            snap_distance: 16.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ControllerInput {
+    // -1 for left through 1 for right
+    pub move_x: f32,
+
+    pub jump_held: bool,
+
+    // Only on the tick the button went down
+    pub jump_pressed: bool,
+}
+
+pub struct CharacterController {
+    pub tuning: ControllerTuning,
+
+    // Horizontal speed is along the ground while grounded
+    pub velocity: Vec2,
+    pub grounded: bool,
+    pub ground_normal: Vec2,
+
+    coyote_timer: f32,
+    jump_buffer_timer: f32,
+    jumping: bool,
+
+    // Sub-unit movement not yet applied to the integer rect position
+    remainder: Vec2,
+}
+
+impl CharacterController {
+    pub fn new(tuning: ControllerTuning) -> CharacterController {
+        CharacterController {
+            tuning: tuning,
+
+            velocity: Vec2(0.0, 0.0),
+            grounded: false,
+            ground_normal: Vec2(0.0, -1.0),
+
+            coyote_timer: 0.0,
+            jump_buffer_timer: 0.0,
+            jumping: false,
+
+            remainder: Vec2(0.0, 0.0),
+        }
+    }
+
+    fn walkable(&self, normal: Vec2) -> bool {
+        // Up is negative y
+        -normal.1 >= self.tuning.max_slope.to_radians().cos()
+    }
+
+    pub fn update(&mut self, input: &ControllerInput, rect: &mut WorldRect, terrain: &TerrainIndex, dt: f32) {
+        // Timers
+        if input.jump_pressed {
+            self.jump_buffer_timer = self.tuning.jump_buffer;
+        } else {
+            self.jump_buffer_timer -= dt;
+        }
+
+        if self.grounded {
+            self.coyote_timer = self.tuning.coyote_time;
+        } else {
+            self.coyote_timer -= dt;
+        }
+
+        // Horizontal
+        let (acceleration, friction) = if self.grounded {
+            (self.tuning.ground_acceleration, self.tuning.ground_friction)
+        } else {
+            (self.tuning.air_acceleration, self.tuning.air_friction)
+        };
+
+        let max = self.tuning.max_run_speed;
+        let vx = self.velocity.0;
+        let speed = (vx.abs() / max).min(1.0);
+        let move_x = input.move_x.max(-1.0).min(1.0);
+
+        if move_x != 0.0 && (vx == 0.0 || move_x.signum() == vx.signum()) {
+            let falloff = 1.0 - speed.powf(self.tuning.acceleration_curve);
+            self.velocity.0 = (vx + move_x * acceleration * falloff.max(0.0) * dt).max(-max).min(max);
+        } else {
+            // Turning around brakes at the full rate as well as accelerating
+            let decel = friction * speed.powf(self.tuning.friction_curve) * dt
+                      + if move_x != 0.0 { acceleration * dt } else { 0.0 };
+
+            self.velocity.0 = if vx.abs() <= decel {
+                move_x * acceleration * dt
+            } else {
+                vx - vx.signum() * decel
+            };
+        }
+
+        // Vertical
+        if self.grounded {
+            self.velocity.1 = 0.0;
+        } else {
+            self.velocity.1 = (self.velocity.1 + self.tuning.gravity * dt).min(self.tuning.max_fall_speed);
+        }
+
+        if self.jump_buffer_timer > 0.0 && self.coyote_timer > 0.0 {
+            self.velocity.1 = -self.tuning.jump_speed;
+            self.grounded = false;
+            self.jumping = true;
+            self.coyote_timer = 0.0;
+            self.jump_buffer_timer = 0.0;
+        }
+
+        if self.jumping && (self.velocity.1 >= 0.0 || !input.jump_held) {
+            if self.velocity.1 < 0.0 {
+                self.velocity.1 *= self.tuning.jump_cut;
+            }
+
+            self.jumping = false;
+        }
+
+        // Walk along the ground, otherwise fly freely
+        let delta = if self.grounded {
+            self.ground_normal.perpendicular() * self.velocity.0 * dt
+        } else {
+            self.velocity * dt
+        };
+
+        let delta = delta + self.remainder;
+        let movement = collision::move_rect(rect, delta, terrain);
+        self.apply(rect, &movement);
+
+        let was_grounded = self.grounded;
+        self.grounded = false;
+
+        for normal in movement.hits.iter().map(|hit| hit.normal).chain(movement.contacts.iter().map(|contact| contact.normal)) {
+            if self.walkable(normal) {
+                self.land(normal);
+            } else if normal.1 > 0.5 && self.velocity.1 < 0.0 {
+                // Bumped a ceiling
+                self.velocity.1 = 0.0;
+                self.jumping = false;
+            } else if normal.0 * self.velocity.0 < 0.0 && normal.1.abs() < 0.5 {
+                // Walked into a wall
+                self.velocity.0 = 0.0;
+            }
+        }
+
+        // Look for ground below, further if we were on it so that walking
+        // down slopes sticks rather than launching off. The probe stops where
+        // it lands instead of sliding on down the slope.
+        if !self.grounded && self.velocity.1 >= 0.0 {
+            let distance = if was_grounded { self.tuning.snap_distance } else { 1.0 };
+            let probe = collision::cast_rect(rect, Vec2(0.0, distance) + self.remainder, terrain);
+
+            let ground = probe.hits.iter().map(|hit| hit.normal)
+                .chain(probe.contacts.iter().map(|contact| contact.normal))
+                .find(|&normal| self.walkable(normal));
+
+            if let Some(normal) = ground {
+                self.apply(rect, &probe);
+                self.land(normal);
+            }
+        }
+    }
+
+    fn apply(&mut self, rect: &mut WorldRect, movement: &collision::Movement) {
+        rect.position = movement.position;
+        self.remainder = movement.exact_position - movement.position.to_render();
+    }
+
+    fn land(&mut self, normal: Vec2) {
+        if !self.grounded {
+            self.velocity.1 = 0.0;
+        }
+
+        self.grounded = true;
+        self.jumping = false;
+        self.ground_normal = normal;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use terrain::{TerrainIndex, TerrainMesh};
+    use ty::{WorldBounds, WorldCoords, WorldRect};
+
+    use super::*;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    const IDLE: ControllerInput = ControllerInput { move_x: 0.0, jump_held: false, jump_pressed: false };
+    const RIGHT: ControllerInput = ControllerInput { move_x: 1.0, jump_held: false, jump_pressed: false };
+    const JUMP: ControllerInput = ControllerInput { move_x: 0.0, jump_held: true, jump_pressed: true };
+    const HOLD: ControllerInput = ControllerInput { move_x: 0.0, jump_held: true, jump_pressed: false };
+
+    fn terrain(outlines: Vec<Vec<(i32, i32)>>) -> TerrainIndex {
+        let outlines = outlines.into_iter()
+            .map(|outline| outline.into_iter().map(|(x, y)| WorldCoords(x, y)).collect())
+            .collect();
+
+        TerrainIndex::new(&TerrainMesh::from_outlines(outlines).unwrap())
+    }
+
+    // Ground from x = 0 to 200 with its top at y = 100
+    fn platform() -> TerrainIndex {
+        terrain(vec![vec![(0, 100), (200, 100), (200, 200), (0, 200)]])
+    }
+
+    fn player(x: i32, y: i32) -> WorldRect {
+        WorldRect::new(WorldCoords(x, y), WorldBounds(20, 30))
+    }
+
+    // A controller standing on the platform
+    fn standing(rect: &mut WorldRect, terrain: &TerrainIndex) -> CharacterController {
+        let mut controller = CharacterController::new(ControllerTuning::default());
+        controller.update(&IDLE, rect, terrain, DT);
+
+        assert!(controller.grounded);
+        controller
+    }
+
+    // Steps until the controller lands, returning how many it took
+    fn fall(controller: &mut CharacterController, rect: &mut WorldRect, terrain: &TerrainIndex) -> usize {
+        for step in 1 .. 120 {
+            controller.update(&IDLE, rect, terrain, DT);
+
+            if controller.grounded {
+                return step;
+            }
+        }
+
+        panic!("never landed");
+    }
+
+    // Highest the top of the rect gets while the jump rises
+    fn apex(controller: &mut CharacterController, rect: &mut WorldRect, terrain: &TerrainIndex, held_for: usize) -> i32 {
+        controller.update(&JUMP, rect, terrain, DT);
+
+        let mut top = rect.position.1;
+        for step in 0 .. 120 {
+            controller.update(if step < held_for { &HOLD } else { &IDLE }, rect, terrain, DT);
+            top = top.min(rect.position.1);
+
+            if controller.velocity.1 >= 0.0 {
+                break;
+            }
+        }
+
+        top
+    }
+
+    #[test]
+    fn falls_onto_the_ground() {
+        let terrain = platform();
+        let mut rect = player(50, -100);
+        let mut controller = CharacterController::new(ControllerTuning::default());
+
+        fall(&mut controller, &mut rect, &terrain);
+
+        assert_eq!(rect.max().1, 100);
+        assert_eq!(controller.velocity.1, 0.0);
+        assert_eq!(controller.ground_normal, Vec2(0.0, -1.0));
+    }
+
+    #[test]
+    fn coyote_time_allows_a_late_jump() {
+        let terrain = platform();
+
+        for &(delay, jumps) in &[(3, true), (8, false)] {
+            let mut rect = player(50, 70);
+            let mut controller = standing(&mut rect, &terrain);
+
+            // Step off the edge, out of reach of the ground
+            rect.position.0 = 300;
+
+            for _ in 0 .. delay {
+                controller.update(&IDLE, &mut rect, &terrain, DT);
+                assert!(!controller.grounded);
+            }
+
+            controller.update(&JUMP, &mut rect, &terrain, DT);
+            assert_eq!(controller.velocity.1 < 0.0, jumps, "jump {} steps after leaving the ground", delay);
+        }
+    }
+
+    #[test]
+    fn early_presses_are_buffered_until_landing() {
+        let terrain = platform();
+
+        // Time a plain fall first
+        let mut rect = player(50, -100);
+        let landing = fall(&mut CharacterController::new(ControllerTuning::default()), &mut rect, &terrain);
+
+        for &(early, jumps) in &[(3, true), (10, false)] {
+            let mut rect = player(50, -100);
+            let mut controller = CharacterController::new(ControllerTuning::default());
+
+            for step in 1 .. landing + 2 {
+                let input = if step == landing - early { JUMP } else { HOLD };
+                controller.update(&input, &mut rect, &terrain, DT);
+            }
+
+            assert_eq!(controller.velocity.1 < 0.0, jumps, "jump pressed {} steps before landing", early);
+        }
+    }
+
+    #[test]
+    fn releasing_jump_early_cuts_it_short() {
+        let terrain = platform();
+
+        let mut rect = player(50, 70);
+        let mut controller = standing(&mut rect, &terrain);
+        let full = apex(&mut controller, &mut rect, &terrain, 120);
+
+        let mut rect = player(50, 70);
+        let mut controller = standing(&mut rect, &terrain);
+        let cut = apex(&mut controller, &mut rect, &terrain, 3);
+
+        // Both start from 70, and y grows downwards
+        assert!(full < cut && cut < 70);
+
+        // The cut happens once, the step jump is let go
+        let mut rect = player(50, 70);
+        let mut controller = standing(&mut rect, &terrain);
+        controller.update(&JUMP, &mut rect, &terrain, DT);
+        let before = controller.velocity.1;
+        controller.update(&IDLE, &mut rect, &terrain, DT);
+
+        assert_eq!(controller.velocity.1, (before + controller.tuning.gravity * DT) * controller.tuning.jump_cut);
+    }
+
+    #[test]
+    fn shallow_slopes_are_walked_up() {
+        // Flat until x = 300, then up 30 degrees
+        let terrain = terrain(vec![vec![(0, 200), (300, 200), (600, 27), (600, 300), (0, 300)]]);
+        let mut rect = player(250, 170);
+        let mut controller = standing(&mut rect, &terrain);
+
+        for _ in 0 .. 60 {
+            controller.update(&RIGHT, &mut rect, &terrain, DT);
+        }
+
+        assert!(controller.grounded);
+        assert!(rect.position.0 > 350 && rect.max().1 < 170);
+        assert!((controller.ground_normal.1 + 30.0f32.to_radians().cos()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn steep_slopes_block_the_way() {
+        // Flat until x = 300, then up 70 degrees
+        let terrain = terrain(vec![vec![(0, 200), (300, 200), (400, -75), (400, 300), (0, 300)]]);
+        let mut rect = player(250, 170);
+        let mut controller = standing(&mut rect, &terrain);
+
+        for _ in 0 .. 60 {
+            controller.update(&RIGHT, &mut rect, &terrain, DT);
+        }
+
+        assert!(rect.max().0 <= 312 && rect.max().1 >= 195);
+        assert!(!controller.walkable(Vec2(70.0f32.to_radians().sin(), -70.0f32.to_radians().cos())));
+    }
+
+    #[test]
+    fn walking_over_a_crest_snaps_down_the_slope() {
+        // Flat until x = 300, then down 30 degrees
+        let terrain = terrain(vec![vec![(0, 200), (300, 200), (600, 373), (600, 500), (0, 500)]]);
+
+        for &(snap_distance, always_grounded) in &[(16.0, true), (0.0, false)] {
+            let mut rect = player(250, 170);
+            let mut controller = standing(&mut rect, &terrain);
+            controller.tuning.snap_distance = snap_distance;
+
+            let mut grounded = true;
+            for _ in 0 .. 40 {
+                controller.update(&RIGHT, &mut rect, &terrain, DT);
+                grounded &= controller.grounded;
+            }
+
+            assert!(rect.position.0 > 320);
+            assert_eq!(grounded, always_grounded, "snapping {} units", snap_distance);
+        }
+    }
+}
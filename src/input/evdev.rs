@@ -0,0 +1,140 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::mem;
+use std::os::raw::{c_int, c_ulong};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::ptr;
+
+use super::gamepad::{self, GamepadDevice, RawEvent};
+
+const O_NONBLOCK: i32 = 0o4000;
+
+const JOYSTICK_DIR: &str = "/dev/input/by-id";
+
+extern "C" {
+    fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+}
+
+// struct input_event
+#[repr(C)]
+struct InputEvent {
+    tv_sec: isize,
+    tv_usec: isize,
+    kind: u16,
+    code: u16,
+    value: i32,
+}
+
+// struct input_absinfo
+#[repr(C)]
+#[derive(Default)]
+struct AbsInfo {
+    value: i32,
+    minimum: i32,
+    maximum: i32,
+    fuzz: i32,
+    flat: i32,
+    resolution: i32,
+}
+
+// _IOR('E', nr, size)
+fn eviocg(nr: u32, size: usize) -> c_ulong {
+    ((2 << 30) | ((size as u32) << 16) | ((b'E' as u32) << 8) | nr) as c_ulong
+}
+
+// Gamepad read straight from a linux event device
+pub struct EvdevDevice {
+    file: File,
+    name: String,
+    ranges: [(i32, i32); 6],
+}
+
+impl EvdevDevice {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<EvdevDevice> {
+        let file = OpenOptions::new()
+            .read(true)
+            .custom_flags(O_NONBLOCK)
+            .open(path.as_ref())?;
+
+        let fd = file.as_raw_fd();
+
+        let mut name = [0u8; 256];
+        let name = match unsafe { ioctl(fd, eviocg(0x06, name.len()), name.as_mut_ptr()) } {
+            len if len > 0 => String::from_utf8_lossy(&name[.. len as usize - 1]).into_owned(),
+            _ => path.as_ref().display().to_string()
+        };
+
+        // Fall back to the usual stick range if the device will not say
+        let mut ranges = [(-32768, 32767); 6];
+        for (code, range) in ranges.iter_mut().enumerate() {
+            let mut info = AbsInfo::default();
+
+            if unsafe { ioctl(fd, eviocg(0x40 + code as u32, mem::size_of::<AbsInfo>()), &mut info as *mut AbsInfo) } >= 0 {
+                *range = (info.minimum, info.maximum);
+            }
+        }
+
+        Ok(EvdevDevice {
+            file: file,
+            name: name,
+            ranges: ranges,
+        })
+    }
+
+    // Every joystick the system lists by id. Devices that fail to open are
+    // reported and skipped.
+    pub fn open_all() -> Vec<EvdevDevice> {
+        let entries = match fs::read_dir(JOYSTICK_DIR) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new()
+        };
+
+        entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.to_string_lossy().ends_with("-event-joystick"))
+            .filter_map(|path| match EvdevDevice::open(&path) {
+                Ok(device) => Some(device),
+                Err(err) => {
+                    eprintln!("Failed to open gamepad {}: {}", path.display(), err);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+impl GamepadDevice for EvdevDevice {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn next_event(&mut self) -> io::Result<Option<RawEvent>> {
+        let mut buffer = [0u8; 64];
+        let size = mem::size_of::<InputEvent>();
+
+        loop {
+            match self.file.read(&mut buffer[.. size]) {
+                Ok(read) if read == size => (),
+                Ok(_) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Short read from event device")),
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(err) => return Err(err)
+            }
+
+            let event = unsafe { ptr::read_unaligned(buffer.as_ptr() as *const InputEvent) };
+
+            // Skip sync and anything else that is not input
+            match event.kind {
+                gamepad::EV_KEY => return Ok(Some(RawEvent::Button(event.code, event.value != 0))),
+                gamepad::EV_ABS => return Ok(Some(RawEvent::Axis(event.code, event.value))),
+                _ => ()
+            }
+        }
+    }
+
+    fn axis_range(&self, code: u16) -> (i32, i32) {
+        self.ranges.get(code as usize).cloned().unwrap_or((-32768, 32767))
+    }
+}
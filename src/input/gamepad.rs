@@ -0,0 +1,498 @@
+use std::collections::VecDeque;
+use std::io;
+
+use super::GamepadButton;
+
+// Event types and codes as used by the linux evdev interface
+pub const EV_KEY: u16 = 0x01;
+pub const EV_ABS: u16 = 0x03;
+
+pub const BTN_SOUTH: u16 = 0x130;
+pub const BTN_EAST: u16 = 0x131;
+pub const BTN_NORTH: u16 = 0x133;
+pub const BTN_WEST: u16 = 0x134;
+pub const BTN_TL: u16 = 0x136;
+pub const BTN_TR: u16 = 0x137;
+pub const BTN_SELECT: u16 = 0x13a;
+pub const BTN_START: u16 = 0x13b;
+pub const BTN_THUMBL: u16 = 0x13d;
+pub const BTN_THUMBR: u16 = 0x13e;
+pub const BTN_DPAD_UP: u16 = 0x220;
+pub const BTN_DPAD_DOWN: u16 = 0x221;
+pub const BTN_DPAD_LEFT: u16 = 0x222;
+pub const BTN_DPAD_RIGHT: u16 = 0x223;
+
+pub const ABS_X: u16 = 0x00;
+pub const ABS_Y: u16 = 0x01;
+pub const ABS_Z: u16 = 0x02;
+pub const ABS_RX: u16 = 0x03;
+pub const ABS_RY: u16 = 0x04;
+pub const ABS_RZ: u16 = 0x05;
+pub const ABS_HAT0X: u16 = 0x10;
+pub const ABS_HAT0Y: u16 = 0x11;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GamepadAxis {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+impl GamepadAxis {
+    pub const ALL: [GamepadAxis; 6] = [
+        GamepadAxis::LeftX, GamepadAxis::LeftY,
+        GamepadAxis::RightX, GamepadAxis::RightY,
+        GamepadAxis::LeftTrigger, GamepadAxis::RightTrigger,
+    ];
+
+    fn from_code(code: u16) -> Option<GamepadAxis> {
+        match code {
+            ABS_X => Some(GamepadAxis::LeftX),
+            ABS_Y => Some(GamepadAxis::LeftY),
+            ABS_RX => Some(GamepadAxis::RightX),
+            ABS_RY => Some(GamepadAxis::RightY),
+            ABS_Z => Some(GamepadAxis::LeftTrigger),
+            ABS_RZ => Some(GamepadAxis::RightTrigger),
+            _ => None
+        }
+    }
+
+    fn is_trigger(self) -> bool {
+        self == GamepadAxis::LeftTrigger || self == GamepadAxis::RightTrigger
+    }
+}
+
+impl GamepadButton {
+    fn from_code(code: u16) -> Option<GamepadButton> {
+        match code {
+            BTN_SOUTH => Some(GamepadButton::South),
+            BTN_EAST => Some(GamepadButton::East),
+            BTN_NORTH => Some(GamepadButton::North),
+            BTN_WEST => Some(GamepadButton::West),
+            BTN_TL => Some(GamepadButton::LeftBumper),
+            BTN_TR => Some(GamepadButton::RightBumper),
+            BTN_SELECT => Some(GamepadButton::Select),
+            BTN_START => Some(GamepadButton::Start),
+            BTN_THUMBL => Some(GamepadButton::LeftStick),
+            BTN_THUMBR => Some(GamepadButton::RightStick),
+            BTN_DPAD_UP => Some(GamepadButton::DPadUp),
+            BTN_DPAD_DOWN => Some(GamepadButton::DPadDown),
+            BTN_DPAD_LEFT => Some(GamepadButton::DPadLeft),
+            BTN_DPAD_RIGHT => Some(GamepadButton::DPadRight),
+            _ => None
+        }
+    }
+}
+
+// Device event before any mapping, values are as the hardware reports them
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RawEvent {
+    Button(u16, bool),
+    Axis(u16, i32),
+}
+
+// Source of raw gamepad events
+pub trait GamepadDevice {
+    fn name(&self) -> &str;
+
+    // Next pending event, or None if there are none right now. Errors mean
+    // the device is gone.
+    fn next_event(&mut self) -> io::Result<Option<RawEvent>>;
+
+    // Lowest and highest values an axis reports
+    fn axis_range(&self, code: u16) -> (i32, i32);
+}
+
+// Device fed by hand, for running without hardware
+pub struct FakeDevice {
+    events: VecDeque<RawEvent>,
+    stick_range: (i32, i32),
+    trigger_range: (i32, i32),
+    disconnected: bool,
+}
+
+impl FakeDevice {
+    pub fn new() -> FakeDevice {
+        FakeDevice {
+            events: VecDeque::new(),
+            stick_range: (-32768, 32767),
+            trigger_range: (0, 255),
+            disconnected: false,
+        }
+    }
+
+    pub fn push(&mut self, event: RawEvent) {
+        self.events.push_back(event);
+    }
+
+    pub fn button(&mut self, code: u16, down: bool) {
+        self.push(RawEvent::Button(code, down));
+    }
+
+    pub fn axis(&mut self, code: u16, value: i32) {
+        self.push(RawEvent::Axis(code, value));
+    }
+
+    // Fail the next poll as an unplugged device would
+    pub fn disconnect(&mut self) {
+        self.disconnected = true;
+    }
+}
+
+impl GamepadDevice for FakeDevice {
+    fn name(&self) -> &str {
+        "Fake gamepad"
+    }
+
+    fn next_event(&mut self) -> io::Result<Option<RawEvent>> {
+        if self.disconnected {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "Fake gamepad disconnected"));
+        }
+
+        Ok(self.events.pop_front())
+    }
+
+    fn axis_range(&self, code: u16) -> (i32, i32) {
+        match code {
+            ABS_Z | ABS_RZ => self.trigger_range,
+            _ => self.stick_range
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GamepadSettings {
+    // Fraction of stick travel from the centre that reads as zero
+    pub stick_dead_zone: f32,
+
+    // Fraction of trigger travel that reads as zero
+    pub trigger_dead_zone: f32,
+
+    // Trigger value past which it counts as a button press
+    pub trigger_threshold: f32,
+}
+
+impl Default for GamepadSettings {
+    fn default() -> GamepadSettings {
+        GamepadSettings {
+            stick_dead_zone: 0.2,
+            trigger_dead_zone: 0.1,
+            trigger_threshold: 0.5,
+        }
+    }
+}
+
+// Zero inside the dead zone, then rescaled so that output still covers the
+// full range. Applied to the stick as a whole so diagonals are not clipped.
+pub fn radial_dead_zone(stick: [f32; 2], dead_zone: f32) -> [f32; 2] {
+    let length = (stick[0] * stick[0] + stick[1] * stick[1]).sqrt();
+    if length <= dead_zone || dead_zone >= 1.0 {
+        return [0.0, 0.0];
+    }
+
+    let scaled = ((length - dead_zone) / (1.0 - dead_zone)).min(1.0);
+    [stick[0] / length * scaled, stick[1] / length * scaled]
+}
+
+pub fn linear_dead_zone(value: f32, dead_zone: f32) -> f32 {
+    if value.abs() <= dead_zone || dead_zone >= 1.0 {
+        return 0.0;
+    }
+
+    value.signum() * ((value.abs() - dead_zone) / (1.0 - dead_zone)).min(1.0)
+}
+
+// Map the raw range to -1 to 1, or 0 to 1 for triggers
+fn normalise(value: i32, (min, max): (i32, i32), trigger: bool) -> f32 {
+    if max <= min {
+        return 0.0;
+    }
+
+    let t = ((value - min) as f32 / (max - min) as f32).max(0.0).min(1.0);
+    if trigger { t } else { t * 2.0 - 1.0 }
+}
+
+// One connected pad, turning raw device events into buttons and dead zoned
+// axis values
+pub struct Gamepad {
+    device: Box<GamepadDevice>,
+
+    // Normalised, before dead zones
+    axes: [f32; 6],
+
+    // D-pads reported as hat axes rather than buttons
+    hat: [i32; 2],
+
+    triggers_down: [bool; 2],
+}
+
+impl Gamepad {
+    pub fn new(device: Box<GamepadDevice>) -> Gamepad {
+        Gamepad {
+            device: device,
+            axes: [0.0; 6],
+            hat: [0, 0],
+            triggers_down: [false, false],
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.device.name()
+    }
+
+    // Drain pending device events, returning button changes in order
+    pub fn poll(&mut self, settings: &GamepadSettings) -> io::Result<Vec<(GamepadButton, bool)>> {
+        let mut changes = Vec::new();
+
+        while let Some(event) = self.device.next_event()? {
+            match event {
+                RawEvent::Button(code, down) => if let Some(button) = GamepadButton::from_code(code) {
+                    changes.push((button, down));
+                },
+
+                RawEvent::Axis(ABS_HAT0X, value) => self.hat(0, value.signum(), GamepadButton::DPadLeft, GamepadButton::DPadRight, &mut changes),
+                RawEvent::Axis(ABS_HAT0Y, value) => self.hat(1, value.signum(), GamepadButton::DPadUp, GamepadButton::DPadDown, &mut changes),
+
+                RawEvent::Axis(code, value) => if let Some(axis) = GamepadAxis::from_code(code) {
+                    self.axes[axis as usize] = normalise(value, self.device.axis_range(code), axis.is_trigger());
+                }
+            }
+        }
+
+        for (i, &(axis, button)) in [(GamepadAxis::LeftTrigger, GamepadButton::LeftTrigger), (GamepadAxis::RightTrigger, GamepadButton::RightTrigger)].iter().enumerate() {
+            let down = self.axis(axis, settings) > settings.trigger_threshold;
+
+            if down != self.triggers_down[i] {
+                self.triggers_down[i] = down;
+                changes.push((button, down));
+            }
+        }
+
+        Ok(changes)
+    }
+
+    fn hat(&mut self, index: usize, value: i32, negative: GamepadButton, positive: GamepadButton, changes: &mut Vec<(GamepadButton, bool)>) {
+        let previous = self.hat[index];
+        if previous == value {
+            return;
+        }
+
+        match previous {
+            -1 => changes.push((negative, false)),
+            1 => changes.push((positive, false)),
+            _ => ()
+        }
+
+        match value {
+            -1 => changes.push((negative, true)),
+            1 => changes.push((positive, true)),
+            _ => ()
+        }
+
+        self.hat[index] = value;
+    }
+
+    // Current value with dead zones applied
+    pub fn axis(&self, axis: GamepadAxis, settings: &GamepadSettings) -> f32 {
+        let stick = |x: GamepadAxis, y: GamepadAxis| radial_dead_zone([self.axes[x as usize], self.axes[y as usize]], settings.stick_dead_zone);
+
+        match axis {
+            GamepadAxis::LeftX => stick(GamepadAxis::LeftX, GamepadAxis::LeftY)[0],
+            GamepadAxis::LeftY => stick(GamepadAxis::LeftX, GamepadAxis::LeftY)[1],
+            GamepadAxis::RightX => stick(GamepadAxis::RightX, GamepadAxis::RightY)[0],
+            GamepadAxis::RightY => stick(GamepadAxis::RightX, GamepadAxis::RightY)[1],
+            GamepadAxis::LeftTrigger | GamepadAxis::RightTrigger => linear_dead_zone(self.axes[axis as usize], settings.trigger_dead_zone),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io;
+    use std::rc::Rc;
+
+    use input::GamepadButton;
+    use super::*;
+
+    // Lets a test keep feeding a device after a gamepad owns it
+    struct Shared(Rc<RefCell<FakeDevice>>);
+
+    impl GamepadDevice for Shared {
+        fn name(&self) -> &str {
+            "Shared fake gamepad"
+        }
+
+        fn next_event(&mut self) -> io::Result<Option<RawEvent>> {
+            self.0.borrow_mut().next_event()
+        }
+
+        fn axis_range(&self, code: u16) -> (i32, i32) {
+            self.0.borrow().axis_range(code)
+        }
+    }
+
+    fn shared() -> (Rc<RefCell<FakeDevice>>, Gamepad) {
+        let device = Rc::new(RefCell::new(FakeDevice::new()));
+        let gamepad = Gamepad::new(Box::new(Shared(device.clone())));
+        (device, gamepad)
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn buttons_are_mapped_by_position() {
+        let (device, mut gamepad) = shared();
+        device.borrow_mut().button(BTN_SOUTH, true);
+        device.borrow_mut().button(BTN_NORTH, true);
+        device.borrow_mut().button(BTN_START, true);
+        device.borrow_mut().button(0x2ff, true);
+        device.borrow_mut().button(BTN_SOUTH, false);
+
+        assert_eq!(gamepad.poll(&GamepadSettings::default()).unwrap(), vec![
+            (GamepadButton::South, true),
+            (GamepadButton::North, true),
+            (GamepadButton::Start, true),
+            (GamepadButton::South, false),
+        ]);
+
+        device.borrow_mut().button(BTN_DPAD_LEFT, true);
+        device.borrow_mut().button(BTN_TR, true);
+
+        assert_eq!(gamepad.poll(&GamepadSettings::default()).unwrap(), vec![
+            (GamepadButton::DPadLeft, true),
+            (GamepadButton::RightBumper, true),
+        ]);
+    }
+
+    #[test]
+    fn hat_axes_press_the_dpad() {
+        let (device, mut gamepad) = shared();
+        device.borrow_mut().axis(ABS_HAT0X, -1);
+        device.borrow_mut().axis(ABS_HAT0X, 1);
+        device.borrow_mut().axis(ABS_HAT0X, 0);
+        device.borrow_mut().axis(ABS_HAT0Y, -1);
+        device.borrow_mut().axis(ABS_HAT0Y, -1);
+
+        assert_eq!(gamepad.poll(&GamepadSettings::default()).unwrap(), vec![
+            (GamepadButton::DPadLeft, true),
+            (GamepadButton::DPadLeft, false),
+            (GamepadButton::DPadRight, true),
+            (GamepadButton::DPadRight, false),
+            (GamepadButton::DPadUp, true),
+        ]);
+
+        device.borrow_mut().axis(ABS_HAT0Y, 1);
+
+        assert_eq!(gamepad.poll(&GamepadSettings::default()).unwrap(), vec![
+            (GamepadButton::DPadUp, false),
+            (GamepadButton::DPadDown, true),
+        ]);
+    }
+
+    #[test]
+    fn triggers_press_past_the_threshold() {
+        let settings = GamepadSettings::default();
+        let (device, mut gamepad) = shared();
+
+        // Half way on the 0 to 255 range is under the threshold once the
+        // dead zone is taken off
+        device.borrow_mut().axis(ABS_Z, 128);
+        assert_eq!(gamepad.poll(&settings).unwrap(), vec![]);
+        assert!(gamepad.axis(GamepadAxis::LeftTrigger, &settings) < settings.trigger_threshold);
+
+        device.borrow_mut().axis(ABS_Z, 255);
+        device.borrow_mut().axis(ABS_RZ, 255);
+        assert_eq!(gamepad.poll(&settings).unwrap(), vec![(GamepadButton::LeftTrigger, true), (GamepadButton::RightTrigger, true)]);
+        assert!(close(gamepad.axis(GamepadAxis::LeftTrigger, &settings), 1.0));
+
+        // Held triggers are not pressed again
+        device.borrow_mut().axis(ABS_Z, 250);
+        assert_eq!(gamepad.poll(&settings).unwrap(), vec![]);
+
+        device.borrow_mut().axis(ABS_Z, 0);
+        assert_eq!(gamepad.poll(&settings).unwrap(), vec![(GamepadButton::LeftTrigger, false)]);
+        assert_eq!(gamepad.axis(GamepadAxis::LeftTrigger, &settings), 0.0);
+    }
+
+    #[test]
+    fn sticks_are_normalised_and_dead_zoned() {
+        let settings = GamepadSettings::default();
+        let (device, mut gamepad) = shared();
+
+        device.borrow_mut().axis(ABS_X, 32767);
+        device.borrow_mut().axis(ABS_Y, 0);
+        device.borrow_mut().axis(ABS_RX, -32768);
+        device.borrow_mut().axis(ABS_RY, 0);
+        gamepad.poll(&settings).unwrap();
+
+        assert!(close(gamepad.axis(GamepadAxis::LeftX, &settings), 1.0));
+        assert!(close(gamepad.axis(GamepadAxis::LeftY, &settings), 0.0));
+        assert!(close(gamepad.axis(GamepadAxis::RightX, &settings), -1.0));
+
+        // Back in the centre, off by a little as real sticks are
+        device.borrow_mut().axis(ABS_X, 1200);
+        device.borrow_mut().axis(ABS_RX, -900);
+        device.borrow_mut().axis(ABS_RY, 3000);
+        gamepad.poll(&settings).unwrap();
+
+        assert_eq!(gamepad.axis(GamepadAxis::LeftX, &settings), 0.0);
+        assert_eq!(gamepad.axis(GamepadAxis::RightX, &settings), 0.0);
+        assert_eq!(gamepad.axis(GamepadAxis::RightY, &settings), 0.0);
+    }
+
+    #[test]
+    fn disconnected_device_fails_to_poll() {
+        let mut device = FakeDevice::new();
+        device.button(BTN_SOUTH, true);
+        device.disconnect();
+
+        assert!(Gamepad::new(Box::new(device)).poll(&GamepadSettings::default()).is_err());
+    }
+
+    #[test]
+    fn radial_dead_zone_rescales_from_the_edge() {
+        let dead_zone = 0.2;
+
+        assert_eq!(radial_dead_zone([0.0, 0.0], dead_zone), [0.0, 0.0]);
+        assert_eq!(radial_dead_zone([0.2, 0.0], dead_zone), [0.0, 0.0]);
+        assert_eq!(radial_dead_zone([0.0, -0.2], dead_zone), [0.0, 0.0]);
+        assert_eq!(radial_dead_zone([1.0, 0.0], dead_zone), [1.0, 0.0]);
+        assert_eq!(radial_dead_zone([0.0, -1.0], dead_zone), [0.0, -1.0]);
+
+        // Half way between the dead zone and the edge
+        let half = radial_dead_zone([0.6, 0.0], dead_zone);
+        assert!(close(half[0], 0.5) && half[1] == 0.0);
+
+        // Diagonals keep their direction and are not clipped
+        let diagonal = radial_dead_zone([0.6, 0.8], dead_zone);
+        assert!(close(diagonal[0], 0.6) && close(diagonal[1], 0.8));
+
+        let small = radial_dead_zone([0.15, 0.15], dead_zone);
+        assert!(small[0] > 0.0 && close(small[0], small[1]));
+    }
+
+    #[test]
+    fn linear_dead_zone_rescales_from_the_edge() {
+        let dead_zone = 0.1;
+
+        assert_eq!(linear_dead_zone(0.0, dead_zone), 0.0);
+        assert_eq!(linear_dead_zone(0.1, dead_zone), 0.0);
+        assert_eq!(linear_dead_zone(-0.1, dead_zone), 0.0);
+        assert_eq!(linear_dead_zone(1.0, dead_zone), 1.0);
+        assert_eq!(linear_dead_zone(-1.0, dead_zone), -1.0);
+        assert!(close(linear_dead_zone(0.55, dead_zone), 0.5));
+    }
+
+    #[test]
+    fn full_dead_zone_reads_zero() {
+        assert_eq!(radial_dead_zone([1.0, 0.0], 1.0), [0.0, 0.0]);
+        assert_eq!(linear_dead_zone(1.0, 1.0), 0.0);
+    }
+}
@@ -0,0 +1,41 @@
+use winit::VirtualKeyCode;
+
+macro_rules! key_names {
+    ($($key:ident),* $(,)*) => {
+        // Name used for a key in binding files, the same as its variant
+        pub fn key_name(key: VirtualKeyCode) -> Option<&'static str> {
+            match key {
+                $(VirtualKeyCode::$key => Some(stringify!($key)),)*
+                _ => None
+            }
+        }
+
+        pub fn key_from_name(name: &str) -> Option<VirtualKeyCode> {
+            match name {
+                $(stringify!($key) => Some(VirtualKeyCode::$key),)*
+                _ => None
+            }
+        }
+    }
+}
+
+// Keys that can be bound
+key_names! {
+    A, B, C, D, E, F, G, H, I, J, K, L, M,
+    N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+
+    Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+
+    Escape, Space, Return, Tab, Back,
+    Insert, Delete, Home, End, PageUp, PageDown,
+    Left, Right, Up, Down,
+
+    LShift, RShift, LControl, RControl, LAlt, RAlt,
+
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4,
+    Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+
+    Comma, Period, Slash, Semicolon, Apostrophe, LBracket, RBracket, Minus, Equals, Grave,
+}
@@ -0,0 +1,536 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de;
+
+use stateloop::app::Event;
+use winit::{self, ElementState, VirtualKeyCode};
+
+use config::{self, ConfigError};
+
+pub use self::gamepad::{Gamepad, GamepadAxis, GamepadDevice, GamepadSettings, FakeDevice, RawEvent};
+#[cfg(target_os = "linux")]
+pub use self::evdev::EvdevDevice;
+
+#[cfg(target_os = "linux")]
+mod evdev;
+mod gamepad;
+mod keys;
+
+// Keyboard key, written by name in binding files
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Key(pub VirtualKeyCode);
+
+impl Serialize for Key {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match keys::key_name(self.0) {
+            Some(name) => serializer.serialize_str(name),
+            None => Err(::serde::ser::Error::custom(format!("Unbindable key {:?}", self.0)))
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Key, D::Error> {
+        let name = String::deserialize(deserializer)?;
+
+        keys::key_from_name(&name)
+            .map(Key)
+            .ok_or_else(|| de::Error::custom(format!("Unknown key {}", name)))
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Other(u8),
+}
+
+impl From<winit::MouseButton> for MouseButton {
+    fn from(button: winit::MouseButton) -> MouseButton {
+        match button {
+            winit::MouseButton::Left => MouseButton::Left,
+            winit::MouseButton::Right => MouseButton::Right,
+            winit::MouseButton::Middle => MouseButton::Middle,
+            winit::MouseButton::Other(n) => MouseButton::Other(n),
+        }
+    }
+}
+
+// Buttons by position, so that layouts from different vendors bind alike
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GamepadButton {
+    South,
+    East,
+    West,
+    North,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+// Anything that can be bound to an action
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Source {
+    Key(Key),
+    Mouse(MouseButton),
+    Gamepad(GamepadButton),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Source::Key(Key(key)) => write!(f, "{:?} key", key),
+            Source::Mouse(button) => write!(f, "{:?} mouse button", button),
+            Source::Gamepad(button) => write!(f, "{:?} gamepad button", button),
+        }
+    }
+}
+
+// Axis built from two actions, -1 while only `negative` is down and 1 while
+// only `positive` is, plus an optional analog axis
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AxisBinding {
+    pub negative: String,
+    pub positive: String,
+
+    #[serde(default)]
+    pub analog: Option<GamepadAxis>,
+}
+
+// What a player has bound, saved to and loaded from the input config file
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bindings {
+    pub actions: BTreeMap<String, Vec<Source>>,
+    pub axes: BTreeMap<String, AxisBinding>,
+    pub gamepad: GamepadSettings,
+}
+
+impl Default for Bindings {
+    fn default() -> Bindings {
+        let key = |key| Source::Key(Key(key));
+
+        let mut actions = BTreeMap::new();
+        actions.insert("move_left".to_owned(), vec![key(VirtualKeyCode::A), key(VirtualKeyCode::Left), Source::Gamepad(GamepadButton::DPadLeft)]);
+        actions.insert("move_right".to_owned(), vec![key(VirtualKeyCode::D), key(VirtualKeyCode::Right), Source::Gamepad(GamepadButton::DPadRight)]);
+        actions.insert("jump".to_owned(), vec![key(VirtualKeyCode::W), key(VirtualKeyCode::Space), Source::Gamepad(GamepadButton::South)]);
+        actions.insert("dig".to_owned(), vec![key(VirtualKeyCode::S), key(VirtualKeyCode::Down), Source::Gamepad(GamepadButton::West)]);
+        actions.insert("toggle_stats".to_owned(), vec![key(VirtualKeyCode::F3)]);
+        actions.insert("toggle_wireframe".to_owned(), vec![key(VirtualKeyCode::F4)]);
+
+        let mut axes = BTreeMap::new();
+        axes.insert("move_x".to_owned(), AxisBinding {
+            negative: "move_left".to_owned(),
+            positive: "move_right".to_owned(),
+            analog: Some(GamepadAxis::LeftX),
+        });
+
+        Bindings {
+            actions: actions,
+            axes: axes,
+            gamepad: GamepadSettings::default(),
+        }
+    }
+}
+
+// Action and analog state seen by one tick, what recordings store. Actions
+// are in binding order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InputFrame {
+    pub actions: Vec<bool>,
+    pub analog: [f32; 6],
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct ActionState {
+    down: bool,
+    was_down: bool,
+}
+
+// Turns raw input into named actions. Source state is collected from events
+// as they arrive and actions are updated from it once per tick, so edges
+// last exactly one tick.
+pub struct InputMap {
+    bindings: Bindings,
+
+    held: HashSet<Source>,
+
+    // Pressed since the last update, so a press and release between two
+    // ticks is still seen
+    tapped: HashSet<Source>,
+
+    actions: HashMap<String, ActionState>,
+
+    gamepads: Vec<Gamepad>,
+
+    // Strongest value of each axis over all gamepads
+    analog: HashMap<GamepadAxis, f32>,
+}
+
+impl InputMap {
+    pub fn new(bindings: Bindings) -> InputMap {
+        InputMap {
+            bindings: bindings,
+            held: HashSet::new(),
+            tapped: HashSet::new(),
+            actions: HashMap::new(),
+            gamepads: Vec::new(),
+            analog: HashMap::new(),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> InputMap {
+        InputMap::new(config::load_or_default(path))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfigError> {
+        config::save(&self.bindings, path)
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    // Add a source to an action, creating the action if needed
+    pub fn bind(&mut self, action: &str, source: Source) {
+        let sources = self.bindings.actions.entry(action.to_owned()).or_insert_with(Vec::new);
+
+        if !sources.contains(&source) {
+            sources.push(source);
+        }
+    }
+
+    pub fn unbind(&mut self, action: &str, source: Source) {
+        if let Some(sources) = self.bindings.actions.get_mut(action) {
+            sources.retain(|&bound| bound != source);
+        }
+    }
+
+    // Replace every binding of an action with a single source
+    pub fn rebind(&mut self, action: &str, source: Source) {
+        self.bindings.actions.insert(action.to_owned(), vec![source]);
+    }
+
+    pub fn press(&mut self, source: Source) {
+        if self.held.insert(source) {
+            self.tapped.insert(source);
+        }
+    }
+
+    pub fn release(&mut self, source: Source) {
+        self.held.remove(&source);
+    }
+
+    pub fn add_gamepad(&mut self, device: Box<GamepadDevice>) {
+        println!("Using gamepad: {}", device.name());
+        self.gamepads.push(Gamepad::new(device));
+    }
+
+    // Read pending gamepad events, dropping any pad that has gone away
+    pub fn poll_gamepads(&mut self) {
+        let mut changes = Vec::new();
+        let settings = self.bindings.gamepad.clone();
+
+        let mut index = 0;
+        while index < self.gamepads.len() {
+            match self.gamepads[index].poll(&settings) {
+                Ok(mut buttons) => {
+                    changes.append(&mut buttons);
+                    index += 1;
+                },
+
+                Err(err) => {
+                    eprintln!("Lost gamepad {}: {}", self.gamepads[index].name(), err);
+                    self.gamepads.remove(index);
+                }
+            }
+        }
+
+        for (button, down) in changes {
+            if down {
+                self.press(Source::Gamepad(button));
+            } else {
+                self.release(Source::Gamepad(button));
+            }
+        }
+
+        for &axis in GamepadAxis::ALL.iter() {
+            let value = self.gamepads.iter()
+                .map(|gamepad| gamepad.axis(axis, &settings))
+                .fold(0.0, |strongest: f32, value| if value.abs() > strongest.abs() { value } else { strongest });
+
+            self.analog.insert(axis, value);
+        }
+    }
+
+    // Returns true if the event was input
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        let (state, source) = match *event {
+            Event::KeyboardInput(state, _, Some(key), _) => (state, Source::Key(Key(key))),
+            Event::MouseInput(state, button) => (state, Source::Mouse(button.into())),
+            _ => return false
+        };
+
+        match state {
+            ElementState::Pressed => self.press(source),
+            ElementState::Released => self.release(source),
+        }
+
+        true
+    }
+
+    // Recompute actions from the current sources, call once per tick
+    pub fn update(&mut self) {
+        for (name, sources) in self.bindings.actions.iter() {
+            let down = sources.iter().any(|source| self.held.contains(source) || self.tapped.contains(source));
+            let state = self.actions.entry(name.clone()).or_insert_with(ActionState::default);
+
+            state.was_down = state.down;
+            state.down = down;
+        }
+
+        self.tapped.clear();
+    }
+
+    pub fn frame(&self) -> InputFrame {
+        let mut analog = [0.0; 6];
+        for (value, &axis) in analog.iter_mut().zip(GamepadAxis::ALL.iter()) {
+            *value = self.analog(axis);
+        }
+
+        InputFrame {
+            actions: self.bindings.actions.keys().map(|action| self.pressed(action)).collect(),
+            analog: analog,
+        }
+    }
+
+    // Update actions from a recorded frame in place of the live sources
+    pub fn apply_frame(&mut self, frame: &InputFrame) {
+        for (name, &down) in self.bindings.actions.keys().zip(frame.actions.iter()) {
+            let state = self.actions.entry(name.clone()).or_insert_with(ActionState::default);
+
+            state.was_down = state.down;
+            state.down = down;
+        }
+
+        for (&axis, &value) in GamepadAxis::ALL.iter().zip(frame.analog.iter()) {
+            self.analog.insert(axis, value);
+        }
+
+        self.tapped.clear();
+    }
+
+    fn state(&self, action: &str) -> ActionState {
+        self.actions.get(action).cloned().unwrap_or_default()
+    }
+
+    pub fn pressed(&self, action: &str) -> bool {
+        self.state(action).down
+    }
+
+    // Only on the tick the action went down
+    pub fn just_pressed(&self, action: &str) -> bool {
+        let state = self.state(action);
+        state.down && !state.was_down
+    }
+
+    pub fn just_released(&self, action: &str) -> bool {
+        let state = self.state(action);
+        !state.down && state.was_down
+    }
+
+    // Dead zoned value of a gamepad axis, strongest over all pads
+    pub fn analog(&self, axis: GamepadAxis) -> f32 {
+        self.analog.get(&axis).cloned().unwrap_or(0.0)
+    }
+
+    // -1 through 1, zero for unknown axes
+    pub fn axis(&self, axis: &str) -> f32 {
+        match self.bindings.axes.get(axis) {
+            Some(binding) => {
+                let value = |action: &str| if self.pressed(action) { 1.0 } else { 0.0 };
+                let digital = value(&binding.positive) - value(&binding.negative);
+                let analog = binding.analog.map(|axis| self.analog(axis)).unwrap_or(0.0);
+
+                (digital + analog).max(-1.0).min(1.0)
+            },
+
+            None => 0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::VirtualKeyCode;
+
+    use super::gamepad::ABS_X;
+    use super::*;
+
+    fn key(key: VirtualKeyCode) -> Source {
+        Source::Key(Key(key))
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    // Default bindings with a gamepad whose left stick is held at `x`
+    fn with_stick(x: i32) -> InputMap {
+        let mut device = FakeDevice::new();
+        device.axis(ABS_X, x);
+
+        let mut input = InputMap::new(Bindings::default());
+        input.add_gamepad(Box::new(device));
+        input.poll_gamepads();
+        input
+    }
+
+    #[test]
+    fn edges_last_one_update() {
+        let mut input = InputMap::new(Bindings::default());
+
+        input.press(key(VirtualKeyCode::Space));
+        assert!(!input.pressed("jump"));
+
+        input.update();
+        assert!(input.pressed("jump") && input.just_pressed("jump") && !input.just_released("jump"));
+
+        input.update();
+        assert!(input.pressed("jump") && !input.just_pressed("jump"));
+
+        input.release(key(VirtualKeyCode::Space));
+        input.update();
+        assert!(!input.pressed("jump") && input.just_released("jump"));
+
+        input.update();
+        assert!(!input.pressed("jump") && !input.just_released("jump"));
+    }
+
+    #[test]
+    fn taps_between_updates_still_register() {
+        let mut input = InputMap::new(Bindings::default());
+
+        input.press(key(VirtualKeyCode::W));
+        input.release(key(VirtualKeyCode::W));
+
+        input.update();
+        assert!(input.just_pressed("jump"));
+
+        input.update();
+        assert!(input.just_released("jump"));
+    }
+
+    #[test]
+    fn actions_stay_down_while_any_source_is() {
+        let mut input = InputMap::new(Bindings::default());
+
+        input.press(key(VirtualKeyCode::W));
+        input.update();
+        input.press(key(VirtualKeyCode::Space));
+        input.release(key(VirtualKeyCode::W));
+        input.update();
+
+        assert!(input.pressed("jump") && !input.just_pressed("jump") && !input.just_released("jump"));
+    }
+
+    #[test]
+    fn axes_combine_keys_and_sticks_within_one() {
+        let mut input = with_stick(32767);
+        input.update();
+        assert!(close(input.axis("move_x"), 1.0));
+
+        // Opposite keys cancel out, leaving the stick
+        input.press(key(VirtualKeyCode::A));
+        input.press(key(VirtualKeyCode::D));
+        input.update();
+        assert!(close(input.axis("move_x"), 1.0));
+
+        // Key and stick the same way is still only 1
+        input.release(key(VirtualKeyCode::A));
+        input.update();
+        assert_eq!(input.axis("move_x"), 1.0);
+
+        // And opposite ways cancel
+        input.release(key(VirtualKeyCode::D));
+        input.press(key(VirtualKeyCode::A));
+        input.update();
+        assert!(close(input.axis("move_x"), 0.0));
+
+        let mut input = with_stick(-32767);
+        input.press(key(VirtualKeyCode::A));
+        input.update();
+        assert_eq!(input.axis("move_x"), -1.0);
+    }
+
+    #[test]
+    fn unknown_actions_and_axes_are_idle() {
+        let mut input = InputMap::new(Bindings::default());
+        input.update();
+
+        assert!(!input.pressed("fly"));
+        assert_eq!(input.axis("move_z"), 0.0);
+    }
+
+    #[test]
+    fn binding_adds_sources_once() {
+        let mut input = InputMap::new(Bindings::default());
+
+        input.bind("jump", Source::Mouse(MouseButton::Left));
+        input.bind("jump", Source::Mouse(MouseButton::Left));
+        assert_eq!(input.bindings().actions["jump"].len(), 4);
+
+        input.press(Source::Mouse(MouseButton::Left));
+        input.update();
+        assert!(input.pressed("jump"));
+
+        // Binding a new action creates it
+        input.bind("shoot", Source::Mouse(MouseButton::Left));
+        input.update();
+        assert!(input.pressed("shoot") && input.just_pressed("shoot"));
+    }
+
+    #[test]
+    fn unbinding_removes_one_source() {
+        let mut input = InputMap::new(Bindings::default());
+        input.unbind("jump", key(VirtualKeyCode::W));
+
+        input.press(key(VirtualKeyCode::W));
+        input.update();
+        assert!(!input.pressed("jump"));
+
+        input.press(key(VirtualKeyCode::Space));
+        input.update();
+        assert!(input.pressed("jump"));
+    }
+
+    #[test]
+    fn rebinding_replaces_every_source() {
+        let mut input = InputMap::new(Bindings::default());
+        input.rebind("jump", key(VirtualKeyCode::J));
+
+        assert_eq!(input.bindings().actions["jump"], vec![key(VirtualKeyCode::J)]);
+
+        input.press(key(VirtualKeyCode::Space));
+        input.update();
+        assert!(!input.pressed("jump"));
+
+        input.press(key(VirtualKeyCode::J));
+        input.update();
+        assert!(input.pressed("jump"));
+    }
+}
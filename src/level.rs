@@ -0,0 +1,732 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use ron;
+
+use atlas::AtlasTextures;
+use binary::{read_u8, read_u32, read_i32, read_f32, read_string, write_u8, write_u32, write_i32, write_f32, write_str};
+use renderer::TextureId;
+use sprite::{Sprite, UvRect};
+use terrain::{self, TerrainMesh, TerrainVertex};
+use ty::{WorldCoords, WorldBounds, WorldRect};
+
+// Bumped whenever the layout of either format changes
+pub const VERSION: u32 = 2;
+
+// Binary levels start with this, then the version as a u32
+const MAGIC: &[u8; 4] = b"PLVL";
+
+// Coordinates past this lose precision once they reach the gpu as floats
+const WORLD_LIMIT: i32 = 1 << 24;
+
+// Something wrong with a level, and where
+#[derive(Clone, Debug)]
+pub struct Problem {
+    // For example "sprites[2].tint"
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, String),
+
+    // Only .ron and .lvl files are understood
+    UnknownFormat(PathBuf),
+
+    UnsupportedVersion(PathBuf, u32),
+    Invalid(PathBuf, Vec<Problem>),
+    UnknownTexture(String),
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LevelError::Io(ref path, ref err) => write!(f, "{}: {}", path.display(), err),
+            LevelError::Parse(ref path, ref err) => write!(f, "{}: {}", path.display(), err),
+            LevelError::UnknownFormat(ref path) => write!(f, "{}: levels must be .ron or .lvl files", path.display()),
+            LevelError::UnsupportedVersion(ref path, version) => write!(f, "{}: level version {} is not supported, expected {}", path.display(), version, VERSION),
+
+            LevelError::Invalid(ref path, ref problems) => {
+                write!(f, "{}: invalid level", path.display())?;
+
+                for problem in problems {
+                    write!(f, "\n  {}", problem)?;
+                }
+
+                Ok(())
+            },
+
+            LevelError::UnknownTexture(ref name) => write!(f, "No atlas sprite named {}", name),
+        }
+    }
+}
+
+impl Error for LevelError {
+    fn description(&self) -> &str {
+        match *self {
+            LevelError::Io(..) => "io error",
+            LevelError::Parse(..) => "parse error",
+            LevelError::UnknownFormat(..) => "unknown level format",
+            LevelError::UnsupportedVersion(..) => "unsupported level version",
+            LevelError::Invalid(..) => "invalid level",
+            LevelError::UnknownTexture(..) => "unknown texture",
+        }
+    }
+}
+
+fn default_tint() -> [f32; 4] {
+    [1.0, 1.0, 1.0, 1.0]
+}
+
+// A sprite as authored. Textures are atlas sprite names, resolved when the
+// level is loaded into the game.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpriteDef {
+    pub rect: WorldRect,
+
+    // Untextured if missing
+    #[serde(default)]
+    pub texture: Option<String>,
+
+    #[serde(default = "default_tint")]
+    pub tint: [f32; 4],
+
+    #[serde(default)]
+    pub layer: i32,
+}
+
+impl SpriteDef {
+    pub fn new(rect: WorldRect) -> SpriteDef {
+        SpriteDef {
+            rect: rect,
+            texture: None,
+            tint: default_tint(),
+            layer: 0,
+        }
+    }
+
+    fn sprite(&self, texture: TextureId, uv: UvRect) -> Sprite {
+        let mut sprite = Sprite::textured(self.rect.clone(), texture, uv);
+        sprite.tint = self.tint;
+        sprite.layer = self.layer;
+        sprite
+    }
+}
+
+// Only the version, so that levels from other versions can be told apart
+// from broken ones
+#[derive(Deserialize)]
+struct VersionProbe {
+    version: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Level {
+    pub version: u32,
+
+    // Either fans or closed outlines, the other left empty
+    #[serde(default)]
+    pub terrain: Vec<TerrainVertex>,
+
+    #[serde(default)]
+    pub outlines: Vec<Vec<WorldCoords>>,
+
+    pub sprites: Vec<SpriteDef>,
+}
+
+impl Level {
+    pub fn new(terrain: &TerrainMesh, sprites: Vec<SpriteDef>) -> Level {
+        let (vertices, outlines) = match terrain.polygons() {
+            Some(polygons) => (Vec::new(), polygons.iter().flat_map(|polygon| polygon.loops()).map(|outline| outline.to_vec()).collect()),
+            None => (terrain.vertices().to_vec(), Vec::new())
+        };
+
+        Level {
+            version: VERSION,
+            terrain: vertices,
+            outlines: outlines,
+            sprites: sprites,
+        }
+    }
+
+    // The level used when none is given
+    pub fn builtin() -> Level {
+        let mut terrain = vec![TerrainVertex::Inner(WorldCoords(800, 700))];
+        for deg in 0 .. 360 {
+            let rad = (deg as f64).to_radians();
+            terrain.push(TerrainVertex::Surface(WorldCoords(800 + (600.0 * rad.sin()) as i32, 700 + (600.0 * rad.cos()) as i32)));
+        }
+
+        Level {
+            version: VERSION,
+            terrain: terrain,
+            outlines: Vec::new(),
+            sprites: vec![
+                SpriteDef::new(WorldRect {
+                    position: WorldCoords(600, 200),
+                    bounds: WorldBounds(700, 600)
+                }),
+
+                SpriteDef::new(WorldRect {
+                    position: WorldCoords(300, 800),
+                    bounds: WorldBounds(200, 300)
+                }),
+            ],
+        }
+    }
+
+    // Read a .ron or .lvl level and check it
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Level, LevelError> {
+        let path = path.as_ref();
+
+        let file = File::open(path).map_err(|err| LevelError::Io(path.to_owned(), err))?;
+        let mut reader = BufReader::new(file);
+
+        let level = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("ron") => {
+                let mut text = String::new();
+                reader.read_to_string(&mut text).map_err(|err| LevelError::Io(path.to_owned(), err))?;
+
+                Level::from_ron(&text, path)?
+            },
+
+            Some("lvl") => {
+                let mut magic = [0; 4];
+                reader.read_exact(&mut magic).map_err(|err| LevelError::Parse(path.to_owned(), err.to_string()))?;
+
+                if &magic != MAGIC {
+                    return Err(LevelError::Parse(path.to_owned(), "not a binary level".to_owned()));
+                }
+
+                let version = read_u32(&mut reader).map_err(|err| LevelError::Parse(path.to_owned(), err.to_string()))?;
+                if version != VERSION {
+                    return Err(LevelError::UnsupportedVersion(path.to_owned(), version));
+                }
+
+                Level::read_from(&mut reader).map_err(|err| LevelError::Parse(path.to_owned(), err.to_string()))?
+            },
+
+            _ => return Err(LevelError::UnknownFormat(path.to_owned()))
+        };
+
+        let problems = level.validate();
+        if !problems.is_empty() {
+            return Err(LevelError::Invalid(path.to_owned(), problems));
+        }
+
+        Ok(level)
+    }
+
+    fn from_ron(text: &str, path: &Path) -> Result<Level, LevelError> {
+        match ron::de::from_str::<Level>(text) {
+            Ok(level) if level.version != VERSION => Err(LevelError::UnsupportedVersion(path.to_owned(), level.version)),
+            Ok(level) => Ok(level),
+
+            // Report the version over the parse error if that is the cause
+            Err(err) => match ron::de::from_str::<VersionProbe>(text) {
+                Ok(probe) if probe.version != VERSION => Err(LevelError::UnsupportedVersion(path.to_owned(), probe.version)),
+                _ => Err(LevelError::Parse(path.to_owned(), err.to_string()))
+            }
+        }
+    }
+
+    // Write as RON or binary depending on the extension
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), LevelError> {
+        let path = path.as_ref();
+
+        let write = |file: File| -> io::Result<()> {
+            let mut writer = BufWriter::new(file);
+
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some("ron") => {
+                    let text = ron::ser::to_string_pretty(self, Default::default())
+                        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+
+                    writer.write_all(text.as_bytes())?;
+                },
+
+                _ => {
+                    writer.write_all(MAGIC)?;
+                    write_u32(&mut writer, VERSION)?;
+                    self.write_to(&mut writer)?;
+                }
+            }
+
+            writer.flush()
+        };
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("ron") | Some("lvl") => (),
+            _ => return Err(LevelError::UnknownFormat(path.to_owned()))
+        }
+
+        File::create(path)
+            .and_then(write)
+            .map_err(|err| LevelError::Io(path.to_owned(), err))
+    }
+
+    // Binary body, without the magic and version
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_u32(writer, self.terrain.len() as u32)?;
+
+        for vertex in &self.terrain {
+            let (kind, coords) = match *vertex {
+                TerrainVertex::Inner(coords) => (0, coords),
+                TerrainVertex::Surface(coords) => (1, coords),
+            };
+
+            write_u8(writer, kind)?;
+            write_i32(writer, coords.0)?;
+            write_i32(writer, coords.1)?;
+        }
+
+        write_u32(writer, self.outlines.len() as u32)?;
+
+        for outline in &self.outlines {
+            write_u32(writer, outline.len() as u32)?;
+
+            for coords in outline {
+                write_i32(writer, coords.0)?;
+                write_i32(writer, coords.1)?;
+            }
+        }
+
+        write_u32(writer, self.sprites.len() as u32)?;
+
+        for sprite in &self.sprites {
+            write_i32(writer, sprite.rect.position.0)?;
+            write_i32(writer, sprite.rect.position.1)?;
+            write_u32(writer, sprite.rect.bounds.0)?;
+            write_u32(writer, sprite.rect.bounds.1)?;
+
+            match sprite.texture {
+                Some(ref name) => {
+                    write_u8(writer, 1)?;
+                    write_str(writer, name)?;
+                },
+
+                None => write_u8(writer, 0)?
+            }
+
+            for &component in sprite.tint.iter() {
+                write_f32(writer, component)?;
+            }
+
+            write_i32(writer, sprite.layer)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Level> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let mut terrain = Vec::new();
+        for i in 0 .. read_u32(reader)? {
+            let kind = read_u8(reader)?;
+            let coords = WorldCoords(read_i32(reader)?, read_i32(reader)?);
+
+            terrain.push(match kind {
+                0 => TerrainVertex::Inner(coords),
+                1 => TerrainVertex::Surface(coords),
+                _ => return Err(invalid(format!("terrain[{}]: unknown vertex kind {}", i, kind)))
+            });
+        }
+
+        let mut outlines = Vec::new();
+        for _ in 0 .. read_u32(reader)? {
+            let mut outline = Vec::new();
+            for _ in 0 .. read_u32(reader)? {
+                outline.push(WorldCoords(read_i32(reader)?, read_i32(reader)?));
+            }
+
+            outlines.push(outline);
+        }
+
+        let mut sprites = Vec::new();
+        for i in 0 .. read_u32(reader)? {
+            let rect = WorldRect {
+                position: WorldCoords(read_i32(reader)?, read_i32(reader)?),
+                bounds: WorldBounds(read_u32(reader)?, read_u32(reader)?),
+            };
+
+            let texture = match read_u8(reader)? {
+                0 => None,
+                1 => Some(read_string(reader)?),
+                flag => return Err(invalid(format!("sprites[{}]: bad texture flag {}", i, flag)))
+            };
+
+            let mut tint = [0.0; 4];
+            for component in tint.iter_mut() {
+                *component = read_f32(reader)?;
+            }
+
+            sprites.push(SpriteDef {
+                rect: rect,
+                texture: texture,
+                tint: tint,
+                layer: read_i32(reader)?,
+            });
+        }
+
+        Ok(Level {
+            version: VERSION,
+            terrain: terrain,
+            outlines: outlines,
+            sprites: sprites,
+        })
+    }
+
+    // Everything wrong with the level, empty if it is usable
+    pub fn validate(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+
+        {
+            let mut problem = |location: String, message: &str| problems.push(Problem {
+                location: location,
+                message: message.to_owned(),
+            });
+
+            if self.outlines.is_empty() {
+                for mesh_problem in self.terrain_mesh().validate() {
+                    let vertices = mesh_problem.vertices();
+
+                    let location = match vertices.first() {
+                        Some(&index) => format!("terrain[{}]", index),
+                        None => "terrain".to_owned()
+                    };
+
+                    problem(location, &mesh_problem.to_string());
+                }
+            } else {
+                if !self.terrain.is_empty() {
+                    problem("terrain".to_owned(), "must be empty when outlines are given");
+                }
+
+                // Problems count vertices through all outlines
+                let locate = |mut index: usize| {
+                    for (i, outline) in self.outlines.iter().enumerate() {
+                        if index < outline.len() {
+                            return format!("outlines[{}][{}]", i, index);
+                        }
+
+                        index -= outline.len();
+                    }
+
+                    "outlines".to_owned()
+                };
+
+                for mesh_problem in terrain::outline_problems(&self.outlines) {
+                    let location = match mesh_problem.vertices().first() {
+                        Some(&index) => locate(index),
+                        None => "outlines".to_owned()
+                    };
+
+                    problem(location, &mesh_problem.to_string());
+                }
+
+                for (i, outline) in self.outlines.iter().enumerate() {
+                    for (j, coords) in outline.iter().enumerate() {
+                        if coords.0.abs() >= WORLD_LIMIT || coords.1.abs() >= WORLD_LIMIT {
+                            problem(format!("outlines[{}][{}]", i, j), "is outside the world limits");
+                        }
+                    }
+                }
+            }
+
+            for (i, vertex) in self.terrain.iter().enumerate() {
+                let coords = vertex.coords();
+                if coords.0.abs() >= WORLD_LIMIT || coords.1.abs() >= WORLD_LIMIT {
+                    problem(format!("terrain[{}]", i), "is outside the world limits");
+                }
+            }
+
+            if self.sprites.is_empty() {
+                problem("sprites".to_owned(), "must have at least one sprite, the first is the player");
+            }
+
+            for (i, sprite) in self.sprites.iter().enumerate() {
+                let position = sprite.rect.position;
+                if position.0.abs() >= WORLD_LIMIT || position.1.abs() >= WORLD_LIMIT {
+                    problem(format!("sprites[{}].rect.position", i), "is outside the world limits");
+                }
+
+                if sprite.rect.bounds.0 == 0 || sprite.rect.bounds.1 == 0 {
+                    problem(format!("sprites[{}].rect.bounds", i), "has zero size");
+                }
+
+                if sprite.tint.iter().any(|&component| !(component >= 0.0 && component <= 1.0)) {
+                    problem(format!("sprites[{}].tint", i), "components must be between 0 and 1");
+                }
+
+                if sprite.texture.as_ref().map(|name| name.is_empty()).unwrap_or(false) {
+                    problem(format!("sprites[{}].texture", i), "is empty, leave it out for no texture");
+                }
+            }
+        }
+
+        problems
+    }
+
+    pub fn terrain_mesh(&self) -> TerrainMesh {
+        if self.outlines.is_empty() {
+            TerrainMesh::new(self.terrain.clone())
+        } else {
+            TerrainMesh::from_polygons(terrain::sort_outlines(self.outlines.clone()))
+        }
+    }
+
+    // Sprites ready to draw, with textures looked up in the atlas
+    pub fn sprites(&self, atlas: &AtlasTextures) -> Result<Vec<Sprite>, LevelError> {
+        self.sprites.iter().map(|def| {
+            let (texture, uv) = match def.texture {
+                Some(ref name) => atlas.lookup(name).ok_or_else(|| LevelError::UnknownTexture(name.clone()))?,
+                None => (TextureId::MISSING, UvRect::full())
+            };
+
+            Ok(def.sprite(texture, uv))
+        }).collect()
+    }
+
+    // Sprites with textures ignored, which is enough for simulation
+    pub fn untextured_sprites(&self) -> Vec<Sprite> {
+        self.sprites.iter().map(|def| def.sprite(TextureId::MISSING, UvRect::full())).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::File;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use binary::{write_i32, write_u8, write_u32};
+    use terrain::TerrainVertex;
+    use ty::{WorldBounds, WorldCoords, WorldRect};
+
+    use super::*;
+
+    fn sprite() -> SpriteDef {
+        SpriteDef::new(WorldRect {
+            position: WorldCoords(0, -50),
+            bounds: WorldBounds(20, 30),
+        })
+    }
+
+    fn fan_level() -> Level {
+        Level {
+            version: VERSION,
+            terrain: vec![
+                TerrainVertex::Inner(WorldCoords(0, 100)),
+                TerrainVertex::Surface(WorldCoords(-100, 0)),
+                TerrainVertex::Surface(WorldCoords(0, -20)),
+                TerrainVertex::Surface(WorldCoords(100, 0)),
+            ],
+            outlines: Vec::new(),
+            sprites: vec![sprite()],
+        }
+    }
+
+    fn outline_level() -> Level {
+        Level {
+            version: VERSION,
+            terrain: Vec::new(),
+            outlines: vec![vec![WorldCoords(0, 0), WorldCoords(100, 0), WorldCoords(100, 100), WorldCoords(0, 100)]],
+            sprites: vec![sprite()],
+        }
+    }
+
+    fn locations(level: &Level) -> Vec<String> {
+        level.validate().into_iter().map(|problem| problem.location).collect()
+    }
+
+    fn write(name: &str, contents: &[u8]) -> PathBuf {
+        let path = env::temp_dir().join(name);
+        File::create(&path).unwrap().write_all(contents).unwrap();
+        path
+    }
+
+    fn binary(version: u32, body: &[u8]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        write_u32(&mut bytes, version).unwrap();
+        bytes.extend_from_slice(body);
+        bytes
+    }
+
+    #[test]
+    fn small_levels_are_valid() {
+        assert!(fan_level().validate().is_empty());
+        assert!(outline_level().validate().is_empty());
+        assert!(Level::builtin().validate().is_empty());
+    }
+
+    #[test]
+    fn fan_problems_point_at_the_vertex() {
+        let mut level = fan_level();
+        level.terrain.push(TerrainVertex::Inner(WorldCoords(0, 100)));
+
+        assert_eq!(locations(&level), vec!["terrain[4]"]);
+    }
+
+    #[test]
+    fn fans_and_outlines_can_not_be_mixed() {
+        let mut level = outline_level();
+        level.terrain = fan_level().terrain;
+
+        assert_eq!(locations(&level), vec!["terrain"]);
+    }
+
+    #[test]
+    fn outline_problems_point_into_their_outline() {
+        let mut level = outline_level();
+        level.outlines.push(vec![WorldCoords(200, 0), WorldCoords(300, 0)]);
+
+        assert_eq!(locations(&level), vec!["outlines[1][0]"]);
+    }
+
+    #[test]
+    fn far_outlines_are_outside_the_world() {
+        let mut level = outline_level();
+        level.outlines[0][1] = WorldCoords(WORLD_LIMIT, 0);
+
+        assert_eq!(locations(&level), vec!["outlines[0][1]"]);
+    }
+
+    #[test]
+    fn far_terrain_is_outside_the_world() {
+        let mut level = fan_level();
+        level.terrain[3] = TerrainVertex::Surface(WorldCoords(WORLD_LIMIT, 0));
+
+        assert_eq!(locations(&level), vec!["terrain[3]"]);
+    }
+
+    #[test]
+    fn bad_sprites_are_reported_by_field() {
+        let mut level = fan_level();
+        let mut far = sprite();
+        far.rect.position = WorldCoords(0, -WORLD_LIMIT);
+
+        let mut empty = sprite();
+        empty.rect.bounds = WorldBounds(20, 0);
+
+        let mut bright = sprite();
+        bright.tint = [1.0, 1.5, 1.0, 1.0];
+
+        let mut unnamed = sprite();
+        unnamed.texture = Some(String::new());
+
+        level.sprites.extend(vec![far, empty, bright, unnamed]);
+
+        assert_eq!(locations(&level), vec![
+            "sprites[1].rect.position",
+            "sprites[2].rect.bounds",
+            "sprites[3].tint",
+            "sprites[4].texture",
+        ]);
+    }
+
+    #[test]
+    fn levels_need_a_player() {
+        let mut level = fan_level();
+        level.sprites.clear();
+
+        assert_eq!(locations(&level), vec!["sprites"]);
+
+        let path = write("level_no_player.ron", b"(version: 2, outlines: [[(0, 0), (10, 0), (0, 10)]], sprites: [])");
+        match Level::load(&path) {
+            Err(LevelError::Invalid(_, ref problems)) if problems.len() == 1 => (),
+            other => panic!("expected an invalid level, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn other_ron_versions_are_unsupported() {
+        // Same layout, and one that no longer parses as this version
+        let same = write("level_old.ron", b"(version: 1, terrain: [], sprites: [])");
+        let changed = write("level_new.ron", b"(version: 3, ground: 5)");
+
+        match Level::load(&same) {
+            Err(LevelError::UnsupportedVersion(_, 1)) => (),
+            other => panic!("expected version 1 to be unsupported, got {:?}", other)
+        }
+
+        match Level::load(&changed) {
+            Err(LevelError::UnsupportedVersion(_, 3)) => (),
+            other => panic!("expected version 3 to be unsupported, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn other_binary_versions_are_unsupported() {
+        let path = write("level_old.lvl", &binary(1, &[]));
+
+        match Level::load(&path) {
+            Err(LevelError::UnsupportedVersion(_, 1)) => (),
+            other => panic!("expected version 1 to be unsupported, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn binary_levels_need_the_magic() {
+        let mut bytes = binary(VERSION, &[0; 12]);
+        bytes[0 .. 4].copy_from_slice(b"PNG ");
+
+        let path = write("level_magic.lvl", &bytes);
+
+        match Level::load(&path) {
+            Err(LevelError::Parse(_, ref message)) => assert_eq!(message, "not a binary level"),
+            other => panic!("expected a parse error, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn unknown_texture_flags_are_parse_errors() {
+        let mut body = Vec::new();
+        write_u32(&mut body, 0).unwrap();
+        write_u32(&mut body, 0).unwrap();
+        write_u32(&mut body, 1).unwrap();
+
+        for &value in &[0, 0, 20, 30] {
+            write_i32(&mut body, value).unwrap();
+        }
+
+        write_u8(&mut body, 2).unwrap();
+
+        let path = write("level_texture_flag.lvl", &binary(VERSION, &body));
+
+        match Level::load(&path) {
+            Err(LevelError::Parse(_, ref message)) => assert_eq!(message, "sprites[0]: bad texture flag 2"),
+            other => panic!("expected a parse error, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn levels_survive_ron_to_binary_and_back() {
+        for name in &["example", "outlines"] {
+            let mut level = Level::load(format!("levels/{}.ron", name)).unwrap();
+            level.sprites[0].texture = Some("player".to_owned());
+
+            let binary = env::temp_dir().join(format!("level_{}.lvl", name));
+            let text = env::temp_dir().join(format!("level_{}.ron", name));
+
+            level.save(&binary).unwrap();
+            let from_binary = Level::load(&binary).unwrap();
+            assert_eq!(from_binary, level);
+
+            from_binary.save(&text).unwrap();
+            assert_eq!(Level::load(&text).unwrap(), level);
+        }
+    }
+}
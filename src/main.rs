@@ -3,28 +3,55 @@
 #[macro_use] extern crate stateloop;
 #[macro_use] extern crate vulkano;
 #[macro_use] extern crate vulkano_shader_derive;
+#[macro_use] extern crate serde_derive;
 
 extern crate vulkano_win;
 extern crate winit;
+extern crate image;
+extern crate serde;
+extern crate ron;
 
-use std::time::{Duration, Instant};
+use std::cell::RefCell;
+use std::process;
+use std::time::Instant;
 use stateloop::app::{App, Data, Event, Window};
 use stateloop::state::Action;
 
-use vulkano::instance::Instance;
-
-use winit::{VirtualKeyCode, ElementState};
-
-use renderer::Renderer;
-use ty::{WorldCoords, WorldBounds, WorldRect};
-use sprite::Sprite;
-use terrain::{TerrainMesh, TerrainVertex};
-
+use vulkano::instance::{Instance, InstanceExtensions};
+
+use renderer::{Renderer, RendererConfig, RendererError};
+use options::Options;
+use input::InputMap;
+use level::Level;
+use replay::{Header, Recorder, Replay, ReplayError};
+use stats::{FrameStats, Timing};
+use atlas::AtlasBuilder;
+use controller::ControllerTuning;
+use timestep::FixedTimestep;
+use world::World;
+
+mod atlas;
+mod bench;
+mod binary;
+mod collision;
+mod config;
+mod controller;
+mod input;
+mod level;
+mod math;
+mod options;
+mod overlay;
+mod query;
 mod shaders;
 mod renderer;
+mod replay;
+mod rng;
 mod ty;
 mod sprite;
+mod stats;
 mod terrain;
+mod timestep;
+mod world;
 
 states! {
     State {
@@ -35,13 +62,109 @@ states! {
 pub struct D {
     renderer: Renderer,
 
-    key_states: Vec<ElementState>,
-    sprites: Vec<Sprite>,
-    terrain: TerrainMesh,
+    input: InputMap,
+    world: World,
+    timestep: FixedTimestep,
 
+    recorder: Option<Recorder>,
+    replay: Option<Replay>,
 
-    frame: u32,
-    start_time: Instant
+    // Rendering only gets shared access
+    stats: RefCell<FrameStats>,
+    show_stats: bool
+}
+
+impl D {
+    fn tick(&mut self) {
+        self.input.poll_gamepads();
+
+        let steps = self.timestep.advance();
+        if steps == 0 {
+            return;
+        }
+
+        // Input is only sampled on ticks that step the simulation, so that
+        // presses are kept until a step can see them
+        let steps = match self.next_replay_tick() {
+            Some(steps) => steps,
+            None => {
+                self.input.update();
+
+                if let Some(ref mut recorder) = self.recorder {
+                    if let Err(err) = recorder.record(steps, &self.input.frame()) {
+                        eprintln!("Failed to record input: {}", err);
+                    }
+                }
+
+                steps
+            }
+        };
+
+        if self.input.just_pressed("toggle_stats") {
+            self.show_stats = !self.show_stats;
+
+            if self.show_stats {
+                println!("The profiler only shows cpu time, gpu drawing time is not measured");
+            }
+        }
+
+        if self.input.just_pressed("toggle_wireframe") {
+            let wireframe = self.renderer.terrain_wireframe();
+            self.renderer.set_terrain_wireframe(!wireframe);
+        }
+
+        self.world.tick(&self.input, steps, self.timestep.dt());
+
+        if let Err(err) = self.renderer.load_terrain(&self.world.terrain) {
+            eprintln!("Failed to load terrain: {}", err);
+        }
+
+        if let Err(err) = self.renderer.set_camera(&self.world.camera) {
+            eprintln!("Failed to update camera: {}", err);
+        }
+    }
+
+    // Apply the next recorded tick's input and return its step count. None
+    // without a replay, or once it has finished and live input takes over.
+    fn next_replay_tick(&mut self) -> Option<u32> {
+        let next = match self.replay {
+            Some(ref mut replay) => replay.next(),
+            None => return None
+        };
+
+        match next {
+            Ok(Some((steps, frame))) => {
+                self.input.apply_frame(&frame);
+                return Some(steps);
+            },
+
+            Ok(None) => match self.replay.as_ref().unwrap().check(&self.world.sprites) {
+                Ok(()) => println!("Replay finished, final positions match"),
+                Err(err) => eprintln!("Replay finished: {}", err)
+            },
+
+            Err(err) => eprintln!("Replay failed: {}", err)
+        }
+
+        self.replay = None;
+        None
+    }
+
+    // Write the final positions so replays can be checked against them
+    fn finish_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            if let Err(err) = recorder.finish(&self.world.sprites) {
+                eprintln!("Failed to finish recording: {}", err);
+            }
+        }
+    }
+}
+
+// Catches any way out of the loop that did not finish the recording itself
+impl Drop for D {
+    fn drop(&mut self) {
+        self.finish_recording();
+    }
 }
 
 impl MainHandler for Data<D> {
@@ -49,69 +172,229 @@ impl MainHandler for Data<D> {
         let mut d = self.data_mut();
 
         match event {
-            Event::Closed => return Action::Quit,
-
-            Event::Resized(w, h) => {
-                d.renderer.update_display_uniforms(w, h);
-                Action::Continue
+            Event::Closed => {
+                d.finish_recording();
+                return Action::Quit;
             },
 
-            Event::KeyboardInput(state, _, Some(key), _) => {
-                d.key_states[key as usize] = state;
-                Action::Continue
+            Event::Resized(w, h) => match d.renderer.resize(w, h) {
+                Err(ref err) if !err.is_recoverable() => {
+                    eprintln!("Renderer failed: {}", err);
+                    d.finish_recording();
+                    Action::Quit
+                },
+
+                _ => Action::Continue
             },
 
-            _ => Action::Continue
+            event => {
+                d.input.handle_event(&event);
+                Action::Continue
+            }
         }
     }
 
     fn handle_tick(&mut self) {
         let mut d = self.data_mut();
 
-        d.frame += 1;
-        let time = Instant::now();
-        let duration = time - d.start_time;
-        println!("{} ({})", 1000000000 / (duration / d.frame).subsec_nanos(), (duration / d.frame).subsec_nanos());
+        let start = Instant::now();
+        d.tick();
+        d.stats.get_mut().record(Timing::Tick, start.elapsed());
+    }
 
-        if d.key_states[VirtualKeyCode::W as usize] == ElementState::Pressed {
-            d.sprites[0].rect.position.1 -= 5;
+    fn handle_render(&self) {
+        // Failed frames are dropped, the next one will try again
+        let d = self.data();
+        let mut stats = d.stats.borrow_mut();
+
+        let overlay = if d.show_stats {
+            overlay::frame_graph(&stats, d.renderer.display_size())
+        } else {
+            Vec::new()
+        };
+
+        if let Err(err) = d.renderer.render_with_overlay(&d.world.sprites, d.timestep.alpha(), &overlay) {
+            eprintln!("Failed to render frame: {}", err);
         }
 
-        if d.key_states[VirtualKeyCode::S as usize] == ElementState::Pressed {
-            d.sprites[0].rect.position.1 += 5;
+        let timings = d.renderer.last_timings();
+        stats.record(Timing::CommandBuild, timings.command_build);
+        stats.record(Timing::Blocked, timings.blocked);
+
+        if let Err(err) = stats.end_frame() {
+            eprintln!("Failed to write frame stats: {}", err);
         }
+    }
+}
+
+// Render a single frame of the level offscreen and write it to a png
+fn capture(path: &str, level: &Level, config: &RendererConfig) -> Result<(), RendererError> {
+    let instance = Instance::new(None, &InstanceExtensions::none(), None)?;
+
+    let world = World::from_level(level, ControllerTuning::default(), 0);
+
+    let mut renderer = Renderer::headless(instance, 800, 600, config)?;
+    renderer.load_terrain(&world.terrain)?;
+    renderer.render(&world.sprites)?;
+
+    renderer.save_png(path)
+}
+
+// Run a recording without a window as fast as possible and check that it
+// ends where it did when recorded
+fn replay_headless(path: &str) -> Result<(), ReplayError> {
+    let mut replay = Replay::open(path)?;
 
-        if d.key_states[VirtualKeyCode::A as usize] == ElementState::Pressed {
-            d.sprites[0].rect.position.0 -= 5;
+    let mut world = World::from_level(&replay.header.level, replay.header.tuning.clone(), replay.header.seed);
+    let mut input = InputMap::new(replay.header.bindings.clone());
+    let dt = FixedTimestep::new(replay.header.sim_rate, 5).dt();
+
+    while let Some((steps, frame)) = replay.next()? {
+        input.apply_frame(&frame);
+        world.tick(&input, steps, dt);
+    }
+
+    replay.check(&world.sprites)?;
+    println!("Replay matched after {} steps", world.steps);
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn open_gamepads(input: &mut InputMap) {
+    for device in input::EvdevDevice::open_all() {
+        input.add_gamepad(Box::new(device));
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_gamepads(_input: &mut InputMap) {
+}
+
+// Pack every png in `dir` into atlas pages saved alongside `manifest`
+fn pack_atlas(dir: &str, manifest: &str) -> Result<(), atlas::AtlasError> {
+    let mut builder = AtlasBuilder::new(1024);
+    builder.add_dir(dir)?;
+
+    let atlas = builder.pack()?;
+    println!("Packed {} sprites into {} pages", atlas.sprites.len(), atlas.pages.len());
+
+    atlas.save(manifest)
+}
+
+fn main() {
+    let options = match Options::from_args() {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
         }
+    };
 
-        if d.key_states[VirtualKeyCode::D as usize] == ElementState::Pressed {
-            d.sprites[0].rect.position.0 += 5;
+    if let Some((ref dir, ref manifest)) = options.pack_atlas {
+        if let Err(err) = pack_atlas(dir, manifest) {
+            eprintln!("Failed to pack atlas: {}", err);
+            process::exit(1);
         }
 
-        let diff = 1.0f64.to_radians() / 5.0;
+        return;
+    }
 
-        let mut coords = vec![TerrainVertex::Inner(WorldCoords(800, 700))];
-        for deg in 0 .. 271 {
-            let rad = (deg as u32 as f64 + (diff * d.frame as f64 % 5.0)).to_radians() * 4.0 / 3.0;
-            coords.push(TerrainVertex::Surface(WorldCoords(800 + (600.0 * rad.sin()) as i32, 700 + (600.0 * rad.cos()) as i32)));
+    if let Some(count) = options.bench_sprites {
+        if let Err(err) = bench::sprites(count, 100, &options.renderer) {
+            eprintln!("Benchmark failed: {}", err);
+            process::exit(1);
         }
 
-        d.terrain = TerrainMesh::new(coords);
-        d.renderer.load_terrain(&d.terrain);
+        return;
     }
 
-    fn handle_render(&self) {
-        self.data().renderer.render(&self.data().sprites);
+    if let Some(count) = options.bench_terrain {
+        if let Err(err) = bench::terrain(count, 10000) {
+            eprintln!("Benchmark failed: {}", err);
+            process::exit(1);
+        }
+
+        return;
     }
-}
 
-fn main() {
+    let level = match options.level {
+        Some(ref path) => match Level::load(path) {
+            Ok(level) => level,
+            Err(err) => {
+                eprintln!("Failed to load level: {}", err);
+                process::exit(1);
+            }
+        },
+
+        None => Level::builtin()
+    };
+
+    if let Some(ref path) = options.export_level {
+        if let Err(err) = level.save(path) {
+            eprintln!("Failed to export level: {}", err);
+            process::exit(1);
+        }
+
+        return;
+    }
+
+    if let Some(ref path) = options.capture {
+        if let Err(err) = capture(path, &level, &options.renderer) {
+            eprintln!("Failed to capture frame: {}", err);
+            process::exit(1);
+        }
+
+        return;
+    }
+
+    if let (&Some(ref path), true) = (&options.replay, options.headless) {
+        if let Err(err) = replay_headless(path) {
+            eprintln!("Replay failed: {}", err);
+            process::exit(1);
+        }
+
+        return;
+    }
+
+    // A replay brings the settings it was recorded with
+    let replay = options.replay.as_ref().map(|path| match Replay::open(path) {
+        Ok(replay) => replay,
+        Err(err) => {
+            eprintln!("Failed to open replay {}: {}", path, err);
+            process::exit(1);
+        }
+    });
+
+    let header = match replay {
+        Some(ref replay) => replay.header.clone(),
+        None => Header {
+            seed: options.seed,
+            sim_rate: options.sim_rate,
+            tuning: config::load_or_default(&options.controller),
+            bindings: config::load_or_default(&options.input),
+            level: level,
+        }
+    };
+
+    let recorder = options.record.as_ref().map(|path| match Recorder::create(path, &header) {
+        Ok(recorder) => recorder,
+        Err(err) => {
+            eprintln!("Failed to start recording {}: {}", path, err);
+            process::exit(1);
+        }
+    });
+
     let instance = {
         let extensions = vulkano_win::required_extensions();
 
-        Instance::new(None, &extensions, None)
-            .unwrap()
+        match Instance::new(None, &extensions, None) {
+            Ok(instance) => instance,
+            Err(err) => {
+                eprintln!("{}", RendererError::from(err));
+                process::exit(1);
+            }
+        }
     };
 
     App::new(
@@ -122,36 +405,40 @@ fn main() {
             .with_dimensions(800, 600),
 
         |window| {
-            let mut coords = vec![TerrainVertex::Inner(WorldCoords(800, 700))];
-            for deg in 0 .. 360 {
-                let rad = (deg as f64).to_radians();
-                coords.push(TerrainVertex::Surface(WorldCoords(800 + (600.0 * rad.sin()) as i32, 700 + (600.0 * rad.cos()) as i32)));
-            }
+            let renderer = match Renderer::new(instance, window, &options.renderer) {
+                Ok(renderer) => renderer,
+                Err(err) => {
+                    eprintln!("Failed to initialise renderer: {}", err);
+                    process::exit(1);
+                }
+            };
 
             let mut d = D {
-                renderer: Renderer::new(instance, window),
+                renderer: renderer,
 
-                key_states: vec![ElementState::Released; VirtualKeyCode::Yen as usize],
+                input: InputMap::new(header.bindings.clone()),
+                world: World::from_level(&header.level, header.tuning.clone(), header.seed),
+                timestep: FixedTimestep::new(header.sim_rate, 5),
 
-                sprites: vec![
-                    Sprite::new(WorldRect {
-                        position: WorldCoords(600, 200),
-                        bounds: WorldBounds(700, 600)
-                    }),
+                recorder: recorder,
+                replay: replay,
 
-                    Sprite::new(WorldRect {
-                        position: WorldCoords(300, 800),
-                        bounds: WorldBounds(200, 300)
-                    }),
-                ],
+                stats: RefCell::new(FrameStats::new(240)),
+                show_stats: false
+            };
 
-                terrain: TerrainMesh::new(coords),
+            open_gamepads(&mut d.input);
 
-                frame: 0,
-                start_time: Instant::now()
-            };
+            if let Some(ref path) = options.stats_csv {
+                if let Err(err) = d.stats.get_mut().write_csv(path) {
+                    eprintln!("Failed to create {}: {}", path, err);
+                }
+            }
+
+            if let Err(err) = d.renderer.load_terrain(&d.world.terrain) {
+                eprintln!("Failed to load terrain: {}", err);
+            }
 
-            d.renderer.load_terrain(&d.terrain);
             d
         }
     )
@@ -0,0 +1,329 @@
+use std::ops::{Add, AddAssign, Div, Index, IndexMut, Mul, Neg, Sub, SubAssign};
+
+// Position or direction in render units, which match world units but are
+// not snapped to whole ones
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Vec2(pub f32, pub f32);
+
+impl Vec2 {
+    pub fn dot(self, other: Vec2) -> f32 {
+        self.0 * other.0 + self.1 * other.1
+    }
+
+    // Z of the 3d cross product, positive when `other` is clockwise of this
+    // on screen, as y points down
+    pub fn cross(self, other: Vec2) -> f32 {
+        self.0 * other.1 - self.1 * other.0
+    }
+
+    pub fn length_squared(self) -> f32 {
+        self.dot(self)
+    }
+
+    pub fn length(self) -> f32 {
+        self.length_squared().sqrt()
+    }
+
+    // Unit length in the same direction, zero stays zero
+    pub fn normalize(self) -> Vec2 {
+        let length = self.length();
+        if length == 0.0 { self } else { self / length }
+    }
+
+    // A quarter turn, the same way as from +x to +y
+    pub fn perpendicular(self) -> Vec2 {
+        Vec2(-self.1, self.0)
+    }
+
+    pub fn lerp(self, other: Vec2, t: f32) -> Vec2 {
+        self + (other - self) * t
+    }
+
+    pub fn distance(self, other: Vec2) -> f32 {
+        (other - self).length()
+    }
+
+    pub fn min(self, other: Vec2) -> Vec2 {
+        Vec2(self.0.min(other.0), self.1.min(other.1))
+    }
+
+    pub fn max(self, other: Vec2) -> Vec2 {
+        Vec2(self.0.max(other.0), self.1.max(other.1))
+    }
+}
+
+impl From<[f32; 2]> for Vec2 {
+    fn from(array: [f32; 2]) -> Vec2 {
+        Vec2(array[0], array[1])
+    }
+}
+
+impl From<Vec2> for [f32; 2] {
+    fn from(vector: Vec2) -> [f32; 2] {
+        [vector.0, vector.1]
+    }
+}
+
+// 0 is x and 1 is y, for code that works through both axes in turn
+impl Index<usize> for Vec2 {
+    type Output = f32;
+
+    fn index(&self, axis: usize) -> &f32 {
+        match axis {
+            0 => &self.0,
+            1 => &self.1,
+            _ => panic!("Vec2 has no axis {}", axis)
+        }
+    }
+}
+
+impl IndexMut<usize> for Vec2 {
+    fn index_mut(&mut self, axis: usize) -> &mut f32 {
+        match axis {
+            0 => &mut self.0,
+            1 => &mut self.1,
+            _ => panic!("Vec2 has no axis {}", axis)
+        }
+    }
+}
+
+impl Add for Vec2 {
+    type Output = Vec2;
+
+    fn add(self, other: Vec2) -> Vec2 {
+        Vec2(self.0 + other.0, self.1 + other.1)
+    }
+}
+
+impl Sub for Vec2 {
+    type Output = Vec2;
+
+    fn sub(self, other: Vec2) -> Vec2 {
+        Vec2(self.0 - other.0, self.1 - other.1)
+    }
+}
+
+impl Neg for Vec2 {
+    type Output = Vec2;
+
+    fn neg(self) -> Vec2 {
+        Vec2(-self.0, -self.1)
+    }
+}
+
+impl Mul<f32> for Vec2 {
+    type Output = Vec2;
+
+    fn mul(self, scale: f32) -> Vec2 {
+        Vec2(self.0 * scale, self.1 * scale)
+    }
+}
+
+impl Div<f32> for Vec2 {
+    type Output = Vec2;
+
+    fn div(self, scale: f32) -> Vec2 {
+        Vec2(self.0 / scale, self.1 / scale)
+    }
+}
+
+impl AddAssign for Vec2 {
+    fn add_assign(&mut self, other: Vec2) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Vec2 {
+    fn sub_assign(&mut self, other: Vec2) {
+        *self = *self - other;
+    }
+}
+
+// 2d affine transform. A point maps to x_axis * x + y_axis * y + translation.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub x_axis: Vec2,
+    pub y_axis: Vec2,
+    pub translation: Vec2,
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform {
+            x_axis: Vec2(1.0, 0.0),
+            y_axis: Vec2(0.0, 1.0),
+            translation: Vec2(0.0, 0.0),
+        }
+    }
+
+    pub fn translate(offset: Vec2) -> Transform {
+        Transform { translation: offset, ..Transform::identity() }
+    }
+
+    pub fn scale(scale: Vec2) -> Transform {
+        Transform {
+            x_axis: Vec2(scale.0, 0.0),
+            y_axis: Vec2(0.0, scale.1),
+            translation: Vec2(0.0, 0.0),
+        }
+    }
+
+    // Turns +x towards +y, which is clockwise on screen
+    pub fn rotate(radians: f32) -> Transform {
+        let (sin, cos) = radians.sin_cos();
+
+        Transform {
+            x_axis: Vec2(cos, sin),
+            y_axis: Vec2(-sin, cos),
+            translation: Vec2(0.0, 0.0),
+        }
+    }
+
+    // This transform followed by another
+    pub fn then(&self, next: &Transform) -> Transform {
+        Transform {
+            x_axis: next.apply_vector(self.x_axis),
+            y_axis: next.apply_vector(self.y_axis),
+            translation: next.apply(self.translation),
+        }
+    }
+
+    pub fn apply(&self, point: Vec2) -> Vec2 {
+        self.apply_vector(point) + self.translation
+    }
+
+    // Without the translation, for directions and sizes
+    pub fn apply_vector(&self, vector: Vec2) -> Vec2 {
+        self.x_axis * vector.0 + self.y_axis * vector.1
+    }
+
+    // Column major, for shaders working in 3d with z left alone
+    pub fn to_matrix(&self) -> [[f32; 4]; 4] {
+        [
+            [self.x_axis.0, self.x_axis.1, 0.0, 0.0],
+            [self.y_axis.0, self.y_axis.1, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [self.translation.0, self.translation.1, 0.0, 1.0],
+        ]
+    }
+
+    // None if it squashes everything onto a line or point
+    pub fn inverse(&self) -> Option<Transform> {
+        let determinant = self.x_axis.cross(self.y_axis);
+        if determinant == 0.0 {
+            return None;
+        }
+
+        let x_axis = Vec2(self.y_axis.1, -self.x_axis.1) / determinant;
+        let y_axis = Vec2(-self.y_axis.0, self.x_axis.0) / determinant;
+
+        let linear = Transform { x_axis: x_axis, y_axis: y_axis, translation: Vec2(0.0, 0.0) };
+
+        Some(Transform { translation: -linear.apply_vector(self.translation), ..linear })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use rng::Rng;
+    use super::{Transform, Vec2};
+
+    const CASES: usize = 1000;
+
+    fn float(rng: &mut Rng, min: f32, max: f32) -> f32 {
+        min + rng.next_f32() * (max - min)
+    }
+
+    fn point(rng: &mut Rng) -> Vec2 {
+        Vec2(float(rng, -1000.0, 1000.0), float(rng, -1000.0, 1000.0))
+    }
+
+    // Any mix of scale, rotation and translation that can be undone
+    fn transform(rng: &mut Rng) -> Transform {
+        let factor = |rng: &mut Rng| {
+            let size = float(rng, 0.25, 4.0);
+            if rng.next_u32() % 2 == 0 { size } else { -size }
+        };
+
+        let scale = Vec2(factor(rng), factor(rng));
+
+        Transform::scale(scale)
+            .then(&Transform::rotate(float(rng, -PI, PI)))
+            .then(&Transform::translate(point(rng)))
+    }
+
+    fn close(a: Vec2, b: Vec2) -> bool {
+        let tolerance = 1e-3 * (1.0 + a.length().max(b.length()));
+        (a - b).length() <= tolerance
+    }
+
+    fn close_transform(a: &Transform, b: &Transform) -> bool {
+        close(a.x_axis, b.x_axis) && close(a.y_axis, b.y_axis) && close(a.translation, b.translation)
+    }
+
+    #[test]
+    fn then_applies_in_order() {
+        let mut rng = Rng::new(1);
+
+        for _ in 0 .. CASES {
+            let (a, b, p) = (transform(&mut rng), transform(&mut rng), point(&mut rng));
+            assert!(close(a.then(&b).apply(p), b.apply(a.apply(p))));
+        }
+    }
+
+    #[test]
+    fn inverse_undoes_the_transform() {
+        let mut rng = Rng::new(2);
+
+        for _ in 0 .. CASES {
+            let (a, p) = (transform(&mut rng), point(&mut rng));
+            let inverse = a.inverse().unwrap();
+
+            assert!(close_transform(&a.then(&inverse), &Transform::identity()));
+            assert!(close_transform(&inverse.then(&a), &Transform::identity()));
+            assert!(close(inverse.apply(a.apply(p)), p));
+        }
+    }
+
+    #[test]
+    fn squashed_transforms_have_no_inverse() {
+        assert!(Transform::scale(Vec2(0.0, 2.0)).inverse().is_none());
+
+        let line = Transform { x_axis: Vec2(1.0, 2.0), y_axis: Vec2(2.0, 4.0), translation: Vec2(5.0, 5.0) };
+        assert!(line.inverse().is_none());
+    }
+
+    #[test]
+    fn matrix_maps_points_like_apply() {
+        let mut rng = Rng::new(3);
+
+        for _ in 0 .. CASES {
+            let (a, p) = (transform(&mut rng), point(&mut rng));
+            let m = a.to_matrix();
+
+            let mapped = Vec2(
+                m[0][0] * p.0 + m[1][0] * p.1 + m[3][0],
+                m[0][1] * p.0 + m[1][1] * p.1 + m[3][1],
+            );
+
+            assert!(close(mapped, a.apply(p)));
+        }
+    }
+
+    #[test]
+    fn vectors_normalize_and_turn() {
+        let mut rng = Rng::new(4);
+
+        for _ in 0 .. CASES {
+            let v = point(&mut rng);
+
+            assert!((v.normalize().length() - 1.0).abs() < 1e-5);
+            assert!(v.dot(v.perpendicular()).abs() <= 1e-3 * v.length_squared());
+            assert_eq!(Vec2(v[0], v[1]), v);
+        }
+
+        assert_eq!(Vec2(0.0, 0.0).normalize(), Vec2(0.0, 0.0));
+    }
+}
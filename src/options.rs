@@ -0,0 +1,107 @@
+use std::env;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use renderer::RendererConfig;
+
+pub struct Options {
+    // Render one frame offscreen to this png and exit
+    pub capture: Option<String>,
+
+    // Pack a directory of pngs into an atlas and exit
+    pub pack_atlas: Option<(String, String)>,
+
+    // Time rendering this many sprites offscreen and exit
+    pub bench_sprites: Option<usize>,
+
+    // Time terrain queries against a mesh with this many vertices and exit
+    pub bench_terrain: Option<usize>,
+
+    // Level file to play, the built in level if missing
+    pub level: Option<String>,
+
+    // Save the level to this .ron or .lvl file and exit
+    pub export_level: Option<String>,
+
+    pub renderer: RendererConfig,
+
+    // Character controller tuning file
+    pub controller: String,
+
+    // Action bindings file
+    pub input: String,
+
+    // Simulation steps per second
+    pub sim_rate: u32,
+
+    // Write per-frame timings to this csv file
+    pub stats_csv: Option<String>,
+
+    // Seed for the simulation's random numbers
+    pub seed: u64,
+
+    // Save every tick's input to this file
+    pub record: Option<String>,
+
+    // Play back input from this file instead of reading devices
+    pub replay: Option<String>,
+
+    // Run a replay without a window and exit
+    pub headless: bool,
+}
+
+impl Options {
+    pub fn from_args() -> Result<Options, String> {
+        let mut options = Options {
+            capture: None,
+            pack_atlas: None,
+            bench_sprites: None,
+            bench_terrain: None,
+            level: None,
+            export_level: None,
+            renderer: RendererConfig::default(),
+            controller: "config/controller.ron".to_owned(),
+            input: "config/input.ron".to_owned(),
+            sim_rate: 60,
+            stats_csv: None,
+            seed: SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0),
+            record: None,
+            replay: None,
+            headless: false,
+        };
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("Missing value for {}", arg));
+
+            match arg.as_str() {
+                "--capture" => options.capture = Some(value()?),
+                "--pack-atlas" => options.pack_atlas = Some((value()?, value()?)),
+                "--bench-sprites" => options.bench_sprites = Some(value()?.parse().map_err(|_| "Invalid sprite count")?),
+                "--bench-terrain" => options.bench_terrain = Some(value()?.parse().map_err(|_| "Invalid vertex count")?),
+                "--level" => options.level = Some(value()?),
+                "--export-level" => options.export_level = Some(value()?),
+                "--device" => options.renderer.device = value()?.parse()?,
+                "--present" => options.renderer.present_mode = value()?.parse()?,
+                "--linear" => options.renderer.srgb = false,
+                "--controller" => options.controller = value()?,
+                "--input" => options.input = value()?,
+                "--sim-rate" => options.sim_rate = match value()?.parse() {
+                    Ok(0) | Err(_) => return Err("Invalid simulation rate".to_owned()),
+                    Ok(rate) => rate
+                },
+                "--stats-csv" => options.stats_csv = Some(value()?),
+                "--seed" => options.seed = value()?.parse().map_err(|_| "Invalid seed")?,
+                "--record" => options.record = Some(value()?),
+                "--replay" => options.replay = Some(value()?),
+                "--headless" => options.headless = true,
+                _ => return Err(format!("Unknown argument: {}", arg))
+            }
+        }
+
+        if options.headless && options.replay.is_none() {
+            return Err("--headless needs --replay".to_owned());
+        }
+
+        Ok(options)
+    }
+}
@@ -0,0 +1,80 @@
+use renderer::TextureId;
+use sprite::{Sprite, UvRect};
+use stats::{FrameStats, Timing};
+use ty::{WorldCoords, WorldBounds, WorldRect};
+
+// Pixels per sample and per millisecond
+const BAR_WIDTH: i32 = 2;
+const MS_HEIGHT: f32 = 4.0;
+
+const MARGIN: i32 = 10;
+
+// Frame budget at 60Hz
+const TARGET_MS: f32 = 1000.0 / 60.0;
+
+fn rect(x: i32, y: i32, w: i32, h: i32, tint: [f32; 4]) -> Sprite {
+    let mut sprite = Sprite::textured(
+        WorldRect {
+            position: WorldCoords(x, y),
+            bounds: WorldBounds(w as u32, h as u32)
+        },
+        TextureId::WHITE,
+        UvRect::full()
+    );
+
+    sprite.tint = tint;
+    sprite
+}
+
+fn tint(timing: Timing) -> [f32; 4] {
+    match timing {
+        Timing::Frame => [0.4, 0.4, 0.4, 0.6],
+        Timing::Tick => [0.2, 0.6, 1.0, 0.8],
+        Timing::CommandBuild => [1.0, 0.8, 0.2, 0.8],
+        Timing::Blocked => [0.3, 0.9, 0.3, 0.8],
+    }
+}
+
+// Graph of recent frame times in the bottom left of the screen, in screen
+// pixels. Each column is a frame, its tick, command build and blocked
+// times stacked in front of the total frame time. Lines mark the 60Hz
+// budget in white, the average frame in blue and the 99th percentile in red.
+// Only cpu time is graphed, time spent drawing on the gpu is not measured.
+pub fn frame_graph(stats: &FrameStats, viewport: [u32; 2]) -> Vec<Sprite> {
+    let base = viewport[1] as i32 - MARGIN;
+    let height = |ms: f32| (ms * MS_HEIGHT).round() as i32;
+
+    let frames = stats.history(Timing::Frame).collect::<Vec<_>>();
+    let width = frames.len() as i32 * BAR_WIDTH;
+
+    let mut sprites = vec![rect(MARGIN, base - height(TARGET_MS * 2.0), width, height(TARGET_MS * 2.0), [0.0, 0.0, 0.0, 0.5])];
+
+    for (i, &ms) in frames.iter().enumerate() {
+        let h = height(ms);
+        sprites.push(rect(MARGIN + i as i32 * BAR_WIDTH, base - h, BAR_WIDTH, h, tint(Timing::Frame)));
+    }
+
+    let parts = [Timing::Tick, Timing::CommandBuild, Timing::Blocked];
+    let mut stacked = vec![0; frames.len()];
+
+    for &timing in &parts {
+        for (i, ms) in stats.history(timing).enumerate() {
+            let h = height(ms);
+            sprites.push(rect(MARGIN + i as i32 * BAR_WIDTH, base - stacked[i] - h, BAR_WIDTH, h, tint(timing)));
+            stacked[i] += h;
+        }
+    }
+
+    let mut lines = vec![(TARGET_MS, [1.0, 1.0, 1.0, 0.8])];
+
+    if let Some(summary) = stats.summary(Timing::Frame) {
+        lines.push((summary.avg, [0.3, 0.5, 1.0, 1.0]));
+        lines.push((summary.p99, [1.0, 0.2, 0.2, 1.0]));
+    }
+
+    for (ms, colour) in lines {
+        sprites.push(rect(MARGIN, base - height(ms), width, 1, colour));
+    }
+
+    sprites
+}
@@ -0,0 +1,288 @@
+use std::f32;
+
+use collision::{self, Aabb, Segment};
+use math::Vec2;
+use sprite::Sprite;
+use terrain::TerrainIndex;
+use ty::{WorldCoords, WorldRect};
+
+// What a ray or swept rect ran into. Sprites are given by their index.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HitTarget {
+    Terrain,
+    Sprite(usize),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct RayHit {
+    pub target: HitTarget,
+    pub point: Vec2,
+
+    // Unit length, facing back the way the ray came
+    pub normal: Vec2,
+
+    // Travelled before the hit, in world units
+    pub distance: f32,
+}
+
+// First terrain edge or sprite along a ray. Sprites the ray starts inside
+// are passed through, so casting from a sprite's own centre does not hit it.
+pub fn raycast(terrain: &TerrainIndex, sprites: &[Sprite], origin: WorldCoords, direction: Vec2, max_distance: f32) -> Option<RayHit> {
+    let length = direction.length();
+    if length == 0.0 {
+        return None;
+    }
+
+    let origin = origin.to_render();
+    let direction = direction / length;
+
+    let mut best = terrain.raycast(origin, direction, max_distance).map(|hit| {
+        // Edges can be hit from inside the terrain too
+        let normal = if hit.edge.normal.dot(direction) > 0.0 { -hit.edge.normal } else { hit.edge.normal };
+
+        RayHit {
+            target: HitTarget::Terrain,
+            point: hit.point,
+            normal: normal,
+            distance: hit.distance,
+        }
+    });
+
+    for (index, sprite) in sprites.iter().enumerate() {
+        let limit = best.map_or(max_distance, |best| best.distance);
+
+        if let Some((distance, normal)) = ray_rect(origin, direction, &Aabb::from(&sprite.rect)) {
+            if distance <= limit {
+                best = Some(RayHit {
+                    target: HitTarget::Sprite(index),
+                    point: origin + direction * distance,
+                    normal: normal,
+                    distance: distance,
+                });
+            }
+        }
+    }
+
+    best
+}
+
+// Whether nothing but open space lies between two points. Only the terrain
+// blocks sight.
+pub fn line_of_sight(terrain: &TerrainIndex, a: WorldCoords, b: WorldCoords) -> bool {
+    let from = a.to_render();
+    let delta = b.to_render() - from;
+    let distance = delta.length();
+
+    distance == 0.0 || terrain.raycast(from, delta / distance, distance).is_none()
+}
+
+// First thing a rect would run into moving by `delta`. The distance is how
+// far the rect gets before touching. Like the controller's movement, only
+// surfaces moved into count, and sprites the rect already overlaps (such as
+// itself) are ignored.
+pub fn shape_cast(terrain: &TerrainIndex, sprites: &[Sprite], rect: &WorldRect, delta: Vec2) -> Option<RayHit> {
+    let aabb = Aabb::from(rect);
+    let length = delta.length();
+
+    if length == 0.0 {
+        return None;
+    }
+
+    let swept_min = aabb.min + delta.min(Vec2(0.0, 0.0));
+    let swept_max = aabb.max + delta.max(Vec2(0.0, 0.0));
+
+    let mut best: Option<(f32, RayHit)> = None;
+
+    {
+        let mut consider = |time: f32, target: HitTarget, hit: collision::Hit| {
+            if best.map_or(true, |(best_time, _)| time < best_time) {
+                best = Some((time, RayHit {
+                    target: target,
+                    point: hit.point,
+                    normal: hit.normal,
+                    distance: time * length,
+                }));
+            }
+        };
+
+        for edge in terrain.edges_in(swept_min, swept_max) {
+            if let Some(hit) = collision::sweep(&aabb, delta, &Segment::from(&edge)) {
+                consider(hit.time, HitTarget::Terrain, hit);
+            }
+        }
+
+        for (index, sprite) in sprites.iter().enumerate() {
+            let other = Aabb::from(&sprite.rect);
+
+            let overlapping = aabb.min.0 < other.max.0 && other.min.0 < aabb.max.0 && aabb.min.1 < other.max.1 && other.min.1 < aabb.max.1;
+            let reachable = swept_min.0 <= other.max.0 && other.min.0 <= swept_max.0 && swept_min.1 <= other.max.1 && other.min.1 <= swept_max.1;
+
+            if overlapping || !reachable {
+                continue;
+            }
+
+            for segment in &rect_segments(&other) {
+                if let Some(hit) = collision::sweep(&aabb, delta, segment) {
+                    consider(hit.time, HitTarget::Sprite(index), hit);
+                }
+            }
+        }
+    }
+
+    best.map(|(_, hit)| hit)
+}
+
+// Slab test of a ray against a rect, giving the distance and the normal of
+// the side it enters through. Misses rects it starts inside.
+fn ray_rect(origin: Vec2, direction: Vec2, rect: &Aabb) -> Option<(f32, Vec2)> {
+    let mut enter = f32::NEG_INFINITY;
+    let mut exit = f32::INFINITY;
+    let mut normal = Vec2(0.0, 0.0);
+
+    for axis in 0 .. 2 {
+        if direction[axis] == 0.0 {
+            if origin[axis] < rect.min[axis] || origin[axis] > rect.max[axis] {
+                return None;
+            }
+
+            continue;
+        }
+
+        let a = (rect.min[axis] - origin[axis]) / direction[axis];
+        let b = (rect.max[axis] - origin[axis]) / direction[axis];
+        let (near, far) = if a < b { (a, b) } else { (b, a) };
+
+        if near > enter {
+            enter = near;
+            normal = Vec2(0.0, 0.0);
+            normal[axis] = -direction[axis].signum();
+        }
+
+        exit = exit.min(far);
+    }
+
+    if enter < 0.0 || enter > exit {
+        return None;
+    }
+
+    Some((enter, normal))
+}
+
+// The sides of a rect as segments facing out
+fn rect_segments(rect: &Aabb) -> [Segment; 4] {
+    let (min, max) = (rect.min, rect.max);
+
+    [
+        Segment { start: Vec2(min.0, min.1), end: Vec2(max.0, min.1), normal: Vec2(0.0, -1.0) },
+        Segment { start: Vec2(max.0, min.1), end: Vec2(max.0, max.1), normal: Vec2(1.0, 0.0) },
+        Segment { start: Vec2(max.0, max.1), end: Vec2(min.0, max.1), normal: Vec2(0.0, 1.0) },
+        Segment { start: Vec2(min.0, max.1), end: Vec2(min.0, min.1), normal: Vec2(-1.0, 0.0) },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use math::Vec2;
+    use sprite::Sprite;
+    use terrain::{TerrainIndex, TerrainMesh};
+    use ty::{WorldBounds, WorldCoords, WorldRect};
+    use super::{line_of_sight, raycast, shape_cast, HitTarget};
+
+    // A thin ledge with its top at y = 60 above solid ground from y = 100
+    // to 150, both from x = 0 to 200
+    fn terrain() -> TerrainIndex {
+        let ledge = vec![WorldCoords(0, 60), WorldCoords(200, 60), WorldCoords(200, 62), WorldCoords(0, 62)];
+        let ground = vec![WorldCoords(0, 100), WorldCoords(200, 100), WorldCoords(200, 150), WorldCoords(0, 150)];
+
+        TerrainIndex::new(&TerrainMesh::from_outlines(vec![ledge, ground]).unwrap())
+    }
+
+    fn sprite(x: i32, y: i32, w: u32, h: u32) -> Sprite {
+        Sprite::new(WorldRect::new(WorldCoords(x, y), WorldBounds(w, h)))
+    }
+
+    fn close(a: Vec2, b: Vec2) -> bool {
+        (a - b).length() < 1e-4
+    }
+
+    #[test]
+    fn ray_hits_the_first_edge() {
+        let hit = raycast(&terrain(), &[], WorldCoords(50, 20), Vec2(0.0, 2.0), 500.0).unwrap();
+
+        assert_eq!(hit.target, HitTarget::Terrain);
+        assert!((hit.distance - 40.0).abs() < 1e-4);
+        assert!(close(hit.point, Vec2(50.0, 60.0)));
+        assert!(close(hit.normal, Vec2(0.0, -1.0)));
+    }
+
+    #[test]
+    fn ray_from_inside_the_terrain_faces_back() {
+        let hit = raycast(&terrain(), &[], WorldCoords(50, 120), Vec2(0.0, 1.0), 500.0).unwrap();
+
+        assert!((hit.distance - 30.0).abs() < 1e-4);
+        assert!(close(hit.normal, Vec2(0.0, -1.0)));
+    }
+
+    #[test]
+    fn ray_stops_at_its_distance() {
+        assert!(raycast(&terrain(), &[], WorldCoords(50, 20), Vec2(0.0, 1.0), 39.0).is_none());
+        assert!(raycast(&terrain(), &[], WorldCoords(50, 20), Vec2(0.0, 0.0), 500.0).is_none());
+    }
+
+    #[test]
+    fn ray_hits_sprites_before_terrain() {
+        let sprites = [sprite(40, 30, 20, 10)];
+        let hit = raycast(&terrain(), &sprites, WorldCoords(50, 20), Vec2(0.0, 1.0), 500.0).unwrap();
+
+        assert_eq!(hit.target, HitTarget::Sprite(0));
+        assert!((hit.distance - 10.0).abs() < 1e-4);
+        assert!(close(hit.point, Vec2(50.0, 30.0)));
+        assert!(close(hit.normal, Vec2(0.0, -1.0)));
+    }
+
+    #[test]
+    fn ray_skips_the_sprite_it_starts_inside() {
+        let sprites = [sprite(40, 10, 20, 20), sprite(40, 40, 20, 10)];
+        let hit = raycast(&terrain(), &sprites, WorldCoords(50, 20), Vec2(0.0, 1.0), 500.0).unwrap();
+
+        assert_eq!(hit.target, HitTarget::Sprite(1));
+        assert!((hit.distance - 20.0).abs() < 1e-4);
+
+        let hit = raycast(&terrain(), &sprites[.. 1], WorldCoords(50, 20), Vec2(0.0, 1.0), 500.0).unwrap();
+        assert_eq!(hit.target, HitTarget::Terrain);
+    }
+
+    #[test]
+    fn sight_is_blocked_by_terrain_only() {
+        let terrain = terrain();
+
+        assert!(line_of_sight(&terrain, WorldCoords(10, 20), WorldCoords(190, 50)));
+        assert!(line_of_sight(&terrain, WorldCoords(10, 20), WorldCoords(10, 20)));
+        assert!(!line_of_sight(&terrain, WorldCoords(10, 20), WorldCoords(10, 80)));
+        assert!(!line_of_sight(&terrain, WorldCoords(10, 80), WorldCoords(10, 20)));
+    }
+
+    #[test]
+    fn shape_cast_stops_at_the_first_edge() {
+        let rect = WorldRect::new(WorldCoords(45, 20), WorldBounds(10, 10));
+        let hit = shape_cast(&terrain(), &[], &rect, Vec2(0.0, 200.0)).unwrap();
+
+        assert_eq!(hit.target, HitTarget::Terrain);
+        assert!((hit.distance - 30.0).abs() < 1e-4);
+        assert!(close(hit.normal, Vec2(0.0, -1.0)));
+        assert!((hit.point.1 - 60.0).abs() < 1e-4);
+
+        // Short of the ledge nothing is hit
+        assert!(shape_cast(&terrain(), &[], &rect, Vec2(0.0, 29.0)).is_none());
+    }
+
+    #[test]
+    fn shape_cast_ignores_overlapping_sprites() {
+        let rect = WorldRect::new(WorldCoords(45, 20), WorldBounds(10, 10));
+        let sprites = [sprite(45, 20, 10, 10), sprite(40, 40, 20, 5)];
+
+        let hit = shape_cast(&terrain(), &sprites, &rect, Vec2(0.0, 200.0)).unwrap();
+        assert_eq!(hit.target, HitTarget::Sprite(1));
+        assert!((hit.distance - 10.0).abs() < 1e-4);
+    }
+}
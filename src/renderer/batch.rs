@@ -0,0 +1,120 @@
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::iter::repeat;
+use std::ops::Range;
+use std::sync::Arc;
+
+use vulkano::buffer::BufferUsage;
+use vulkano::buffer::cpu_access::CpuAccessibleBuffer;
+use vulkano::device::{Device, Queue};
+
+use sprite::Sprite;
+
+use super::{RendererError, TextureId};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BatchMode {
+    // One instanced draw per run of sprites sharing a texture
+    Instanced,
+
+    // One draw per sprite, kept for comparison in benchmarks
+    PerSprite,
+}
+
+// Per-sprite vertex data, advanced once per instance
+#[derive(Copy, Clone, Default)]
+pub struct SpriteInstance {
+    pos: [f32; 2],
+    bounds: [f32; 2],
+    uv_pos: [f32; 2],
+    uv_size: [f32; 2],
+    tint: [f32; 4],
+}
+
+impl_vertex!(SpriteInstance, pos, bounds, uv_pos, uv_size, tint);
+
+impl SpriteInstance {
+    fn new(sprite: &Sprite, alpha: f32) -> SpriteInstance {
+        SpriteInstance {
+            pos: sprite.interpolated_position(alpha),
+            bounds: sprite.rect.bounds.to_render().into(),
+            uv_pos: sprite.uv.position,
+            uv_size: sprite.uv.size,
+            tint: sprite.tint,
+        }
+    }
+}
+
+// Sort sprites into draw order and split them into runs that can share a
+// draw call. Returns the instance data along with each run's texture and
+// range within it. Sprites on the same layer keep the order they were given
+// in, so only neighbours sharing a texture are drawn together.
+pub(super) fn batch_sprites(sprites: &[Sprite], alpha: f32, mode: BatchMode) -> (Vec<SpriteInstance>, Vec<(TextureId, Range<usize>)>) {
+    let mut order = sprites.iter().collect::<Vec<_>>();
+    order.sort_by_key(|sprite| sprite.layer);
+
+    let instances = order.iter().map(|&sprite| SpriteInstance::new(sprite, alpha)).collect();
+    let mut batches: Vec<(TextureId, Range<usize>)> = Vec::new();
+
+    for (index, sprite) in order.iter().enumerate() {
+        match batches.last_mut() {
+            Some(&mut (texture, ref mut range)) if mode == BatchMode::Instanced && texture == sprite.texture => {
+                range.end = index + 1;
+                continue;
+            },
+
+            _ => ()
+        }
+
+        batches.push((sprite.texture, index .. index + 1));
+    }
+
+    (instances, batches)
+}
+
+// Host visible instance buffers, one per frame in flight so that writing the
+// next frame's sprites does not wait on the gpu. Buffers grow as needed and
+// are otherwise reused.
+pub(super) struct InstanceRing {
+    buffers: RefCell<Vec<Option<Arc<CpuAccessibleBuffer<[SpriteInstance]>>>>>,
+    next: Cell<usize>,
+}
+
+impl InstanceRing {
+    pub fn new(frames: usize) -> InstanceRing {
+        InstanceRing {
+            buffers: RefCell::new(vec![None; frames]),
+            next: Cell::new(0),
+        }
+    }
+
+    pub fn upload(&self, device: &Arc<Device>, queue: &Arc<Queue>, instances: &[SpriteInstance]) -> Result<Arc<CpuAccessibleBuffer<[SpriteInstance]>>, RendererError> {
+        let mut buffers = self.buffers.borrow_mut();
+
+        let index = self.next.get();
+        self.next.set((index + 1) % buffers.len());
+
+        // Reuse the slot if it is big enough and no longer in use
+        if let Some(ref buffer) = buffers[index] {
+            if buffer.len() >= instances.len() {
+                if let Ok(mut contents) = buffer.write() {
+                    contents[.. instances.len()].copy_from_slice(instances);
+                    return Ok(buffer.clone());
+                }
+            }
+        }
+
+        let capacity = cmp::max(instances.len().next_power_of_two(), 64);
+
+        let buffer = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::vertex_buffer(),
+            Some(queue.family()),
+            instances.iter().cloned().chain(repeat(SpriteInstance::default())).take(capacity)
+        )
+            .map_err(RendererError::allocation("sprite instance buffer"))?;
+
+        buffers[index] = Some(buffer.clone());
+        Ok(buffer)
+    }
+}
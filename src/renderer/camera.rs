@@ -0,0 +1,168 @@
+use math::{Transform, Vec2};
+
+// Keeps a target inside a box around the camera centre, easing towards it
+// once it leaves
+#[derive(Copy, Clone, Debug)]
+pub struct Follow {
+    // Half extents of the box in world units
+    pub dead_zone: [f32; 2],
+
+    // Higher is snappier. Zero disables smoothing.
+    pub smoothing: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Camera {
+    // World coordinates shown at the centre of the viewport
+    pub position: [f32; 2],
+
+    // Position at the end of the previous simulation step
+    pub previous_position: [f32; 2],
+
+    // Screen pixels per world unit
+    pub zoom: f32,
+
+    // Radians, clockwise on screen
+    pub rotation: f32,
+
+    pub follow: Option<Follow>,
+}
+
+impl Camera {
+    pub fn new(position: [f32; 2]) -> Camera {
+        Camera {
+            position: position,
+            previous_position: position,
+            zoom: 1.0,
+            rotation: 0.0,
+            follow: None,
+        }
+    }
+
+    // Move towards `target` according to the follow settings, does nothing
+    // if there are none
+    pub fn update(&mut self, target: [f32; 2], dt: f32) {
+        let follow = match self.follow {
+            Some(follow) => follow,
+            None => return
+        };
+
+        let mut desired = self.position;
+
+        for axis in 0 .. 2 {
+            let offset = target[axis] - self.position[axis];
+            let limit = follow.dead_zone[axis];
+
+            if offset > limit {
+                desired[axis] = target[axis] - limit;
+            } else if offset < -limit {
+                desired[axis] = target[axis] + limit;
+            }
+        }
+
+        let t = if follow.smoothing > 0.0 {
+            1.0 - (-follow.smoothing * dt).exp()
+        } else {
+            1.0
+        };
+
+        for axis in 0 .. 2 {
+            self.position[axis] += (desired[axis] - self.position[axis]) * t;
+        }
+    }
+
+    // The camera `alpha` of the way from its previous to its current
+    // position
+    pub fn interpolated(&self, alpha: f32) -> Camera {
+        let mut camera = self.clone();
+
+        for axis in 0 .. 2 {
+            camera.position[axis] = self.previous_position[axis] + (self.position[axis] - self.previous_position[axis]) * alpha;
+        }
+
+        camera
+    }
+
+    // World to pixels, relative to the centre of the viewport
+    fn view(&self) -> Transform {
+        Transform::translate(-Vec2::from(self.position))
+            .then(&Transform::rotate(-self.rotation))
+            .then(&Transform::scale(Vec2(self.zoom, self.zoom)))
+    }
+
+    fn screen(&self, viewport: [u32; 2]) -> Transform {
+        self.view().then(&Transform::translate(Vec2(viewport[0] as f32, viewport[1] as f32) / 2.0))
+    }
+
+    // Column major world to clip space transform for a viewport of the
+    // given size in pixels
+    pub fn view_matrix(&self, viewport: [u32; 2]) -> [[f32; 4]; 4] {
+        self.view()
+            .then(&Transform::scale(Vec2(2.0 / viewport[0] as f32, 2.0 / viewport[1] as f32)))
+            .to_matrix()
+    }
+
+    pub fn world_to_screen(&self, world: [f32; 2], viewport: [u32; 2]) -> [f32; 2] {
+        self.screen(viewport).apply(world.into()).into()
+    }
+
+    // A camera zoomed out to nothing shows its position everywhere
+    pub fn screen_to_world(&self, screen: [f32; 2], viewport: [u32; 2]) -> [f32; 2] {
+        match self.screen(viewport).inverse() {
+            Some(inverse) => inverse.apply(screen.into()).into(),
+            None => self.position
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Camera;
+
+    fn close(a: [f32; 2], b: [f32; 2]) -> bool {
+        (a[0] - b[0]).abs() < 1e-3 && (a[1] - b[1]).abs() < 1e-3
+    }
+
+    fn camera() -> Camera {
+        let mut camera = Camera::new([300.0, -120.0]);
+        camera.zoom = 2.5;
+        camera.rotation = 0.7;
+        camera
+    }
+
+    #[test]
+    fn camera_position_is_the_centre_of_the_screen() {
+        let camera = camera();
+        let m = camera.view_matrix([800, 600]);
+
+        assert!(close(camera.world_to_screen(camera.position, [800, 600]), [400.0, 300.0]));
+        assert!(close([m[0][0] * 300.0 + m[1][0] * -120.0 + m[3][0], m[0][1] * 300.0 + m[1][1] * -120.0 + m[3][1]], [0.0, 0.0]));
+    }
+
+    #[test]
+    fn screen_and_world_round_trip() {
+        let camera = camera();
+
+        for &point in &[[0.0, 0.0], [800.0, 600.0], [123.0, 456.0], [-50.0, 900.0]] {
+            let world = camera.screen_to_world(point, [800, 600]);
+            assert!(close(camera.world_to_screen(world, [800, 600]), point));
+        }
+    }
+
+    #[test]
+    fn rotation_turns_the_world_the_other_way_on_screen() {
+        let mut camera = Camera::new([0.0, 0.0]);
+        camera.rotation = ::std::f32::consts::PI / 2.0;
+
+        // Turning the camera clockwise moves what was to its right up
+        assert!(close(camera.world_to_screen([10.0, 0.0], [100, 100]), [50.0, 40.0]));
+    }
+
+    #[test]
+    fn zero_zoom_maps_the_screen_to_the_camera() {
+        let mut camera = camera();
+        camera.zoom = 0.0;
+
+        assert_eq!(camera.screen_to_world([10.0, 20.0], [800, 600]), camera.position);
+    }
+}
@@ -0,0 +1,178 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use vulkano::format::Format;
+use vulkano::instance::{Instance, PhysicalDevice, PhysicalDeviceType};
+use vulkano::swapchain::{ColorSpace, PresentMode, SupportedPresentModes};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DeviceKind {
+    Discrete,
+    Integrated,
+    Cpu,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DevicePreference {
+    // Highest scoring device
+    Any,
+
+    // Position in the instance's device list
+    Index(usize),
+
+    // Case insensitive substring of the device name
+    Name(String),
+
+    Kind(DeviceKind),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PresentPreference {
+    Vsync,
+    Mailbox,
+    Immediate,
+}
+
+#[derive(Clone, Debug)]
+pub struct RendererConfig {
+    pub device: DevicePreference,
+    pub present_mode: PresentPreference,
+    pub srgb: bool,
+}
+
+impl Default for RendererConfig {
+    fn default() -> RendererConfig {
+        RendererConfig {
+            device: DevicePreference::Any,
+            present_mode: PresentPreference::Vsync,
+            srgb: true,
+        }
+    }
+}
+
+impl RendererConfig {
+    // Pick the highest scoring device that passes `usable`. Ties go to the
+    // lowest index so the choice is stable between runs.
+    pub fn select_device<'a, F>(&self, instance: &'a Arc<Instance>, usable: F) -> Option<PhysicalDevice<'a>>
+        where F: Fn(&PhysicalDevice) -> bool {
+
+        let mut best: Option<(i32, PhysicalDevice<'a>)> = None;
+
+        for device in PhysicalDevice::enumerate(instance) {
+            if !usable(&device) {
+                println!("Skipping device {}: {} (type: {:?})", device.index(), device.name(), device.ty());
+                continue;
+            }
+
+            let score = self.score_device(&device);
+            println!("Found device {}: {} (type: {:?}, score: {})", device.index(), device.name(), device.ty(), score);
+
+            if best.as_ref().map(|&(best_score, _)| score > best_score).unwrap_or(true) {
+                best = Some((score, device));
+            }
+        }
+
+        best.map(|(_, device)| device)
+    }
+
+    fn score_device(&self, device: &PhysicalDevice) -> i32 {
+        let base = match device.ty() {
+            PhysicalDeviceType::DiscreteGpu => 40,
+            PhysicalDeviceType::IntegratedGpu => 30,
+            PhysicalDeviceType::VirtualGpu => 20,
+            PhysicalDeviceType::Cpu => 10,
+            PhysicalDeviceType::Other => 0,
+        };
+
+        let preferred = match self.device {
+            DevicePreference::Any => false,
+            DevicePreference::Index(index) => device.index() == index,
+            DevicePreference::Name(ref name) => device.name().to_lowercase().contains(&name.to_lowercase()),
+            DevicePreference::Kind(kind) => match (kind, device.ty()) {
+                (DeviceKind::Discrete, PhysicalDeviceType::DiscreteGpu) |
+                (DeviceKind::Integrated, PhysicalDeviceType::IntegratedGpu) |
+                (DeviceKind::Cpu, PhysicalDeviceType::Cpu) => true,
+                _ => false
+            }
+        };
+
+        if preferred { base + 1000 } else { base }
+    }
+
+    // Falls back to fifo, which every surface supports
+    pub fn select_present_mode(&self, supported: &SupportedPresentModes) -> PresentMode {
+        let order: &[PresentMode] = match self.present_mode {
+            PresentPreference::Vsync => &[PresentMode::Fifo],
+            PresentPreference::Mailbox => &[PresentMode::Mailbox, PresentMode::Fifo],
+            PresentPreference::Immediate => &[PresentMode::Immediate, PresentMode::Mailbox, PresentMode::Fifo],
+        };
+
+        let mode = order.iter()
+            .cloned()
+            .find(|&mode| supported.supports(mode))
+            .unwrap_or(PresentMode::Fifo);
+
+        println!("Using present mode: {:?} (requested: {:?})", mode, self.present_mode);
+        mode
+    }
+
+    pub fn select_format(&self, supported: &[(Format, ColorSpace)]) -> Format {
+        let format = supported.iter()
+            .map(|&(format, _)| format)
+            .find(|&format| is_srgb(format) == self.srgb)
+            .unwrap_or(supported[0].0);
+
+        println!("Using format: {:?} (srgb requested: {})", format, self.srgb);
+        format
+    }
+
+    // Format for offscreen rendering
+    pub fn headless_format(&self) -> Format {
+        if self.srgb {
+            Format::R8G8B8A8Srgb
+        } else {
+            Format::R8G8B8A8Unorm
+        }
+    }
+}
+
+fn is_srgb(format: Format) -> bool {
+    match format {
+        Format::R8G8B8A8Srgb |
+        Format::B8G8R8A8Srgb |
+        Format::A8B8G8R8SrgbPack32 |
+        Format::R8G8B8Srgb |
+        Format::B8G8R8Srgb => true,
+        _ => false
+    }
+}
+
+impl FromStr for DevicePreference {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DevicePreference, String> {
+        Ok(match s {
+            "any" => DevicePreference::Any,
+            "discrete" => DevicePreference::Kind(DeviceKind::Discrete),
+            "integrated" => DevicePreference::Kind(DeviceKind::Integrated),
+            "cpu" => DevicePreference::Kind(DeviceKind::Cpu),
+            _ => match s.parse() {
+                Ok(index) => DevicePreference::Index(index),
+                Err(_) => DevicePreference::Name(s.to_owned())
+            }
+        })
+    }
+}
+
+impl FromStr for PresentPreference {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PresentPreference, String> {
+        match s {
+            "vsync" => Ok(PresentPreference::Vsync),
+            "mailbox" => Ok(PresentPreference::Mailbox),
+            "immediate" => Ok(PresentPreference::Immediate),
+            _ => Err(format!("Unknown present mode: {}", s))
+        }
+    }
+}
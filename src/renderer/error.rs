@@ -0,0 +1,191 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use image::ImageError;
+
+use vulkano::OomError;
+use vulkano::device::DeviceCreationError;
+use vulkano::image::ImageCreationError;
+use vulkano::instance::InstanceCreationError;
+use vulkano::pipeline::GraphicsPipelineCreationError;
+use vulkano::swapchain::{AcquireError, CapabilitiesError, SwapchainCreationError};
+use vulkano::sync::FlushError;
+
+#[derive(Debug)]
+pub enum RendererError {
+    InstanceCreationFailed(InstanceCreationError),
+
+    // No physical device is available to render with
+    NoSuitableDevice,
+
+    // No queue family on the device can both draw and present to the window
+    NoPresentQueue,
+
+    // A shader module failed to load, named by the shader
+    ShaderLoadFailed(&'static str, OomError),
+
+    // An image file could not be read or decoded
+    ImageLoadFailed(PathBuf, ImageError),
+
+    // A captured frame could not be written
+    ImageSaveFailed(PathBuf, io::Error),
+
+    // Frames can only be read back from a headless renderer
+    CaptureUnsupported,
+
+    // Out of memory creating a buffer or image, named by the resource
+    AllocationFailed(&'static str, Box<Error + Send + Sync>),
+
+    // A draw reached past the end of a buffer, named by the buffer
+    BufferOverrun(&'static str),
+
+    // The window surface has gone away and can no longer be presented to
+    SurfaceLost,
+
+    // The logical device was lost, usually due to a driver reset
+    DeviceLost,
+
+    // The surface offers no composite alpha mode to present with
+    UnsupportedSurface,
+
+    DeviceCreationFailed(DeviceCreationError),
+    SwapchainCreationFailed(SwapchainCreationError),
+
+    // The render pass has no subpass for a pipeline to draw in
+    MissingSubpass,
+    PipelineCreationFailed(GraphicsPipelineCreationError),
+
+    // Recording or submitting a command buffer failed
+    CommandFailed(Box<Error + Send + Sync>),
+}
+
+impl RendererError {
+    pub fn allocation<E>(resource: &'static str) -> impl FnOnce(E) -> RendererError where E: Error + Send + Sync + 'static {
+        move |err| RendererError::AllocationFailed(resource, Box::new(err))
+    }
+
+    // Lost surfaces and devices can not be rendered to again, anything else
+    // may succeed on the next frame
+    pub fn is_recoverable(&self) -> bool {
+        match *self {
+            RendererError::SurfaceLost | RendererError::DeviceLost => false,
+            _ => true
+        }
+    }
+
+    pub fn command<E>(err: E) -> RendererError where E: Error + Send + Sync + 'static {
+        RendererError::CommandFailed(Box::new(err))
+    }
+}
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RendererError::InstanceCreationFailed(ref err) => write!(f, "Failed to create Vulkan instance: {}", err),
+            RendererError::NoSuitableDevice => write!(f, "No suitable graphics device found"),
+            RendererError::NoPresentQueue => write!(f, "No queue family can present to the window"),
+            RendererError::ShaderLoadFailed(name, ref err) => write!(f, "Failed to load {} shader: {}", name, err),
+            RendererError::ImageLoadFailed(ref path, ref err) => write!(f, "Failed to load image {}: {}", path.display(), err),
+            RendererError::ImageSaveFailed(ref path, ref err) => write!(f, "Failed to save image {}: {}", path.display(), err),
+            RendererError::CaptureUnsupported => write!(f, "Only headless frames can be captured"),
+            RendererError::AllocationFailed(name, ref err) => write!(f, "Failed to allocate {}: {}", name, err),
+            RendererError::BufferOverrun(name) => write!(f, "Draw reached past the end of the {}", name),
+            RendererError::SurfaceLost => write!(f, "Window surface was lost"),
+            RendererError::DeviceLost => write!(f, "Graphics device was lost"),
+            RendererError::UnsupportedSurface => write!(f, "Window surface has no supported composite alpha mode"),
+            RendererError::DeviceCreationFailed(ref err) => write!(f, "Failed to create device: {}", err),
+            RendererError::SwapchainCreationFailed(ref err) => write!(f, "Failed to create swapchain: {}", err),
+            RendererError::MissingSubpass => write!(f, "Render pass has no subpass to draw in"),
+            RendererError::PipelineCreationFailed(ref err) => write!(f, "Failed to create pipeline: {}", err),
+            RendererError::CommandFailed(ref err) => write!(f, "Failed to execute commands: {}", err),
+        }
+    }
+}
+
+impl Error for RendererError {
+    fn description(&self) -> &str {
+        match *self {
+            RendererError::InstanceCreationFailed(_) => "instance creation failed",
+            RendererError::NoSuitableDevice => "no suitable device",
+            RendererError::NoPresentQueue => "no present queue",
+            RendererError::ShaderLoadFailed(..) => "shader load failed",
+            RendererError::ImageLoadFailed(..) => "image load failed",
+            RendererError::ImageSaveFailed(..) => "image save failed",
+            RendererError::CaptureUnsupported => "capture unsupported",
+            RendererError::AllocationFailed(..) => "allocation failed",
+            RendererError::BufferOverrun(_) => "buffer overrun",
+            RendererError::SurfaceLost => "surface lost",
+            RendererError::DeviceLost => "device lost",
+            RendererError::UnsupportedSurface => "unsupported surface",
+            RendererError::DeviceCreationFailed(_) => "device creation failed",
+            RendererError::SwapchainCreationFailed(_) => "swapchain creation failed",
+            RendererError::MissingSubpass => "missing subpass",
+            RendererError::PipelineCreationFailed(_) => "pipeline creation failed",
+            RendererError::CommandFailed(_) => "command failed",
+        }
+    }
+}
+
+impl From<InstanceCreationError> for RendererError {
+    fn from(err: InstanceCreationError) -> RendererError {
+        RendererError::InstanceCreationFailed(err)
+    }
+}
+
+impl From<DeviceCreationError> for RendererError {
+    fn from(err: DeviceCreationError) -> RendererError {
+        RendererError::DeviceCreationFailed(err)
+    }
+}
+
+impl From<SwapchainCreationError> for RendererError {
+    fn from(err: SwapchainCreationError) -> RendererError {
+        match err {
+            SwapchainCreationError::SurfaceLost => RendererError::SurfaceLost,
+            err => RendererError::SwapchainCreationFailed(err)
+        }
+    }
+}
+
+impl From<GraphicsPipelineCreationError> for RendererError {
+    fn from(err: GraphicsPipelineCreationError) -> RendererError {
+        RendererError::PipelineCreationFailed(err)
+    }
+}
+
+impl From<CapabilitiesError> for RendererError {
+    fn from(err: CapabilitiesError) -> RendererError {
+        match err {
+            CapabilitiesError::SurfaceLost => RendererError::SurfaceLost,
+            err => RendererError::command(err)
+        }
+    }
+}
+
+impl From<ImageCreationError> for RendererError {
+    fn from(err: ImageCreationError) -> RendererError {
+        RendererError::AllocationFailed("image", Box::new(err))
+    }
+}
+
+impl From<AcquireError> for RendererError {
+    fn from(err: AcquireError) -> RendererError {
+        match err {
+            AcquireError::SurfaceLost => RendererError::SurfaceLost,
+            AcquireError::DeviceLost => RendererError::DeviceLost,
+            err => RendererError::command(err)
+        }
+    }
+}
+
+impl From<FlushError> for RendererError {
+    fn from(err: FlushError) -> RendererError {
+        match err {
+            FlushError::SurfaceLost => RendererError::SurfaceLost,
+            FlushError::DeviceLost => RendererError::DeviceLost,
+            err => RendererError::command(err)
+        }
+    }
+}
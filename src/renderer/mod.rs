@@ -0,0 +1,942 @@
+use std::cell::{Cell, RefCell, UnsafeCell};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::path::Path;
+use std::ptr;
+
+use image::RgbaImage;
+
+use stateloop::app::Window;
+
+use vulkano::instance::{Instance, PhysicalDevice};
+use vulkano::device::{Device, Queue, DeviceExtensions};
+use vulkano::swapchain::{acquire_next_image, AcquireError, Swapchain, SwapchainCreationError, SurfaceTransform};
+use vulkano::buffer::{BufferUsage, TypedBufferAccess};
+use vulkano::buffer::cpu_access::CpuAccessibleBuffer;
+use vulkano::buffer::device_local::DeviceLocalBuffer;
+use vulkano::buffer::immutable::ImmutableBuffer;
+use vulkano::format::Format;
+use vulkano::image::ImageUsage;
+use vulkano::image::attachment::AttachmentImage;
+use vulkano::image::swapchain::SwapchainImage;
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineParams, GraphicsPipelineAbstract};
+use vulkano::pipeline::vertex::{SingleBufferDefinition, OneVertexOneInstanceDefinition};
+use vulkano::pipeline::input_assembly::{InputAssembly, PrimitiveTopology};
+use vulkano::pipeline::viewport::{Scissor, Viewport, ViewportsState};
+use vulkano::pipeline::multisample::Multisample;
+use vulkano::pipeline::depth_stencil::DepthStencil;
+use vulkano::pipeline::blend::Blend;
+use vulkano::pipeline::raster::{Rasterization, PolygonMode};
+use vulkano::descriptor::descriptor_set::DescriptorSet;
+use vulkano::sampler::Sampler;
+use vulkano::framebuffer::{Subpass, Framebuffer, FramebufferAbstract, RenderPassAbstract};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferBuilder, DynamicState};
+use vulkano::sync::{self, GpuFuture, FlushError};
+
+use sprite::Sprite;
+use terrain::TerrainMesh;
+use shaders;
+
+pub use self::batch::BatchMode;
+pub use self::camera::{Camera, Follow};
+pub use self::config::{RendererConfig, DevicePreference, DeviceKind, PresentPreference};
+pub use self::error::RendererError;
+pub use self::texture::{Texture, TextureId, Filtering};
+
+mod batch;
+mod camera;
+mod config;
+mod error;
+mod terrain_buffers;
+mod texture;
+
+use self::terrain_buffers::TerrainPoint;
+
+#[derive(Copy, Clone)]
+pub struct Point {
+    point: [f32; 2]
+}
+
+fn pt(x: f32, y: f32) -> Point {
+    Point {
+        point: [x, y]
+    }
+}
+
+impl_vertex!(Point, point);
+
+// Number of per-frame buffers kept so the cpu can run ahead of the gpu
+const FRAMES_IN_FLIGHT: usize = 3;
+
+// Swapchain and the framebuffers wrapping its images, rebuilt together
+struct SwapchainImages {
+    swapchain: Arc<Swapchain>,
+    framebuffers: Vec<Arc<FramebufferAbstract + Sync + Send>>,
+    dimensions: [u32; 2],
+}
+
+// Where finished frames end up
+enum Target {
+    Window {
+        render_pass: Arc<RenderPassAbstract + Send + Sync>,
+        images: RefCell<SwapchainImages>,
+
+        // Set on resize or when the surface reports out of date
+        recreate: Cell<bool>,
+        size: Cell<[u32; 2]>,
+    },
+
+    Headless {
+        image: Arc<AttachmentImage>,
+        framebuffer: Arc<FramebufferAbstract + Sync + Send>,
+        readback: Arc<CpuAccessibleBuffer<[u8]>>,
+        dimensions: [u32; 2],
+    }
+}
+
+pub struct Renderer {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    target: Target,
+
+    quad_vertex_buffer: Arc<ImmutableBuffer<[Point]>>,
+    sprite_pipeline: Arc<GraphicsPipelineAbstract + Sync + Send>,
+    sprite_set: Arc<DescriptorSet + Sync + Send>,
+    sprite_instances: batch::InstanceRing,
+    batch_mode: BatchMode,
+
+    textures: Vec<Texture>,
+    nearest_sampler: Arc<Sampler>,
+    linear_sampler: Arc<Sampler>,
+    tiling_sampler: Arc<Sampler>,
+
+    terrain_buffers: terrain_buffers::TerrainBuffers,
+    terrain_fill_pipeline: Arc<GraphicsPipelineAbstract + Sync + Send>,
+    terrain_fill_set: Arc<DescriptorSet + Sync + Send>,
+    terrain_textures: (TextureId, TextureId),
+
+    // Debug view of the triangles, drawn over everything else
+    terrain_wireframe: bool,
+    terrain_pipeline: Arc<GraphicsPipelineAbstract + Sync + Send>,
+    terrain_set: Arc<DescriptorSet + Sync + Send>,
+
+    display_uniform_buffer: Arc<DeviceLocalBuffer<shaders::sprite::DisplayUniforms>>,
+    display_size: [u32; 2],
+    camera: Camera,
+
+    // Sprites drawn in screen pixels on top of the scene
+    overlay_uniform_buffer: Arc<DeviceLocalBuffer<shaders::sprite::DisplayUniforms>>,
+    overlay_set: Arc<DescriptorSet + Sync + Send>,
+    overlay_instances: batch::InstanceRing,
+
+    timings: Cell<RenderTimings>,
+
+    frame_future: UnsafeCell<Box<GpuFuture>>,
+}
+
+// Cpu side timings of the last rendered frame. Vulkano's command buffer
+// builder can not write timestamp queries, so how long the gpu spends
+// drawing is not measured at all, whatever the device supports.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RenderTimings {
+    pub command_build: Duration,
+
+    // Acquiring the swapchain image plus submitting and flushing
+    pub blocked: Duration,
+}
+
+impl Renderer {
+    pub fn new(instance: Arc<Instance>, window: &Window, config: &RendererConfig) -> Result<Renderer, RendererError> {
+        // Select physical device that can present to the window
+        let physical = match config.select_device(&instance, |device| can_present(device, window)) {
+            Some(physical) => physical,
+            None if PhysicalDevice::enumerate(&instance).next().is_some() => return Err(RendererError::NoPresentQueue),
+            None => return Err(RendererError::NoSuitableDevice)
+        };
+
+        println!("Using device: {} (type: {:?})", physical.name(), physical.ty());
+
+        // Choose gpu queue
+        let queue = physical.queue_families().find(|&queue| {
+            println!("{:?}", queue);
+            queue.supports_graphics() && window.surface().is_supported(queue).unwrap_or(false)
+        })
+            .ok_or(RendererError::NoPresentQueue)?;
+
+        // Build vulkano device object
+        let (device, mut queues) = {
+            let device_ext = DeviceExtensions {
+                khr_swapchain: true,
+                ..DeviceExtensions::none()
+            };
+
+            Device::new(
+                &physical,
+                physical.supported_features(),
+                &device_ext,
+                [(queue, 0.5)].iter().cloned()
+            )?
+        };
+
+        let queue = queues.next().ok_or(RendererError::NoPresentQueue)?;
+
+        let (w, h) = window.window().get_inner_size_pixels()
+            .ok_or(RendererError::SurfaceLost)?;
+
+        // Create swapchain
+        let (swapchain, images) = {
+            let caps = window.surface().capabilities(physical)?;
+
+            let dimensions = caps.current_extent.unwrap_or([w, h]);
+            let present = config.select_present_mode(&caps.present_modes);
+            let alpha = caps.supported_composite_alpha.iter().next()
+                .ok_or(RendererError::UnsupportedSurface)?;
+            let format = config.select_format(&caps.supported_formats);
+
+            Swapchain::new(
+                device.clone(),
+                window.surface().clone(),
+                caps.min_image_count,
+                format,
+                dimensions,
+                1,
+                caps.supported_usage_flags,
+                &queue,
+                SurfaceTransform::Identity,
+                alpha,
+                present,
+                true,
+                None
+            )?
+        };
+
+        let render_pass = create_render_pass(&device, swapchain.format())?;
+
+        let target = Target::Window {
+            images: RefCell::new(SwapchainImages {
+                dimensions: images[0].dimensions(),
+                framebuffers: create_framebuffers(&render_pass, &images)?,
+                swapchain: swapchain,
+            }),
+
+            render_pass: render_pass.clone(),
+            recreate: Cell::new(false),
+            size: Cell::new([w, h]),
+        };
+
+        // Start with world coordinates matching screen pixels
+        let mut renderer = Renderer::build(device, queue, render_pass, target)?;
+        renderer.camera = Camera::new([w as f32 / 2.0, h as f32 / 2.0]);
+        renderer.update_display_uniforms(w, h)?;
+        Ok(renderer)
+    }
+
+    // Render into an offscreen image instead of a swapchain, for machines
+    // without a display. Frames can be read back with `capture`.
+    pub fn headless(instance: Arc<Instance>, w: u32, h: u32, config: &RendererConfig) -> Result<Renderer, RendererError> {
+        // Select physical device
+        let physical = config.select_device(&instance, |device| device.queue_families().any(|queue| queue.supports_graphics()))
+            .ok_or(RendererError::NoSuitableDevice)?;
+
+        println!("Using device: {} (type: {:?})", physical.name(), physical.ty());
+
+        // Choose gpu queue
+        let queue = physical.queue_families()
+            .find(|&queue| queue.supports_graphics())
+            .ok_or(RendererError::NoSuitableDevice)?;
+
+        // Build vulkano device object
+        let (device, mut queues) = Device::new(
+            &physical,
+            physical.supported_features(),
+            &DeviceExtensions::none(),
+            [(queue, 0.5)].iter().cloned()
+        )?;
+
+        let queue = queues.next().ok_or(RendererError::NoSuitableDevice)?;
+
+        // Create offscreen colour attachment
+        let format = config.headless_format();
+
+        let image = AttachmentImage::with_usage(
+            device.clone(),
+            [w, h],
+            format,
+            ImageUsage {
+                color_attachment: true,
+                transfer_source: true,
+                ..ImageUsage::none()
+            }
+        )?;
+
+        // Create buffer to copy finished frames into
+        let readback = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::transfer_dest(),
+            Some(queue.family()),
+            (0 .. w * h * 4).map(|_| 0u8)
+        )
+            .map_err(RendererError::allocation("readback buffer"))?;
+
+        let render_pass = create_render_pass(&device, format)?;
+
+        let framebuffer = Arc::new(Framebuffer::start(render_pass.clone())
+            .add(image.clone()).map_err(RendererError::command)?
+            .build().map_err(RendererError::command)?);
+
+        let target = Target::Headless {
+            image: image,
+            framebuffer: framebuffer,
+            readback: readback,
+            dimensions: [w, h],
+        };
+
+        // Start with world coordinates matching screen pixels
+        let mut renderer = Renderer::build(device, queue, render_pass, target)?;
+        renderer.camera = Camera::new([w as f32 / 2.0, h as f32 / 2.0]);
+        renderer.update_display_uniforms(w, h)?;
+        Ok(renderer)
+    }
+
+    fn build(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        render_pass: Arc<RenderPassAbstract + Send + Sync>,
+        target: Target
+    ) -> Result<Renderer, RendererError> {
+        // Create vertex buffer
+        let (quad_vertex_buffer, quad_vertex_buffer_future) = ImmutableBuffer::from_iter(
+            [
+                 Point { point: [0.0, 0.0] },
+                 Point { point: [1.0, 0.0] },
+                 Point { point: [0.0, 1.0] },
+                 Point { point: [1.0, 1.0] },
+            ].iter().cloned(),
+            BufferUsage::vertex_buffer(),
+            Some(queue.family()),
+            queue.clone()
+        )
+            .map_err(RendererError::allocation("vertex buffer"))?;
+
+        // Create uniform buffer
+        let uniform_buffer = DeviceLocalBuffer::new(
+            device.clone(),
+            BufferUsage::uniform_buffer_transfer_dest(),
+            Some(queue.family()),
+        )
+            .map_err(RendererError::allocation("uniform buffer"))?;
+
+        let overlay_uniform_buffer = DeviceLocalBuffer::new(
+            device.clone(),
+            BufferUsage::uniform_buffer_transfer_dest(),
+            Some(queue.family()),
+        )
+            .map_err(RendererError::allocation("overlay uniform buffer"))?;
+
+        let sprite_vs = shaders::sprite::vertex::load(&device)
+            .map_err(|err| RendererError::ShaderLoadFailed("sprite vertex", err))?;
+
+        let sprite_fs = shaders::sprite::fragment::load(&device)
+            .map_err(|err| RendererError::ShaderLoadFailed("sprite fragment", err))?;
+
+        let subpass = || Subpass::from(render_pass.clone(), 0).ok_or(RendererError::MissingSubpass);
+
+        // Create sprite pipeline
+        let sprite_pipeline = Arc::new(GraphicsPipeline::new(
+            device.clone(),
+            GraphicsPipelineParams {
+                vertex_input: OneVertexOneInstanceDefinition::<Point, batch::SpriteInstance>::new(),
+                vertex_shader: sprite_vs.main_entry_point(),
+                input_assembly: InputAssembly {
+                    topology: PrimitiveTopology::TriangleStrip,
+                    primitive_restart_enable: false
+                },
+                tessellation: None,
+                geometry_shader: None,
+                viewport: ViewportsState::DynamicViewports {
+                    scissors: vec![Scissor::irrelevant()],
+                },
+                raster: Default::default(),
+                multisample: Multisample::disabled(),
+                fragment_shader: sprite_fs.main_entry_point(),
+                depth_stencil: DepthStencil::disabled(),
+                blend: Blend::alpha_blending(),
+                render_pass: subpass()?,
+            }
+        )?);
+
+        let terrain_vs = shaders::terrain::vertex::load(&device)
+            .map_err(|err| RendererError::ShaderLoadFailed("terrain vertex", err))?;
+
+        let terrain_fs = shaders::terrain::fragment::load(&device)
+            .map_err(|err| RendererError::ShaderLoadFailed("terrain fragment", err))?;
+
+        let terrain_fill_vs = shaders::terrain_fill::vertex::load(&device)
+            .map_err(|err| RendererError::ShaderLoadFailed("terrain fill vertex", err))?;
+
+        let terrain_fill_fs = shaders::terrain_fill::fragment::load(&device)
+            .map_err(|err| RendererError::ShaderLoadFailed("terrain fill fragment", err))?;
+
+        // Create terrain pipelines, filled and wireframe
+        let terrain_fill_pipeline = Arc::new(GraphicsPipeline::new(
+            device.clone(),
+            GraphicsPipelineParams {
+                vertex_input: SingleBufferDefinition::<TerrainPoint>::new(),
+                vertex_shader: terrain_fill_vs.main_entry_point(),
+                input_assembly: InputAssembly {
+                    topology: PrimitiveTopology::TriangleStrip,
+                    primitive_restart_enable: true
+                },
+                tessellation: None,
+                geometry_shader: None,
+                viewport: ViewportsState::DynamicViewports {
+                    scissors: vec![Scissor::irrelevant()],
+                },
+                raster: Default::default(),
+                multisample: Multisample::disabled(),
+                fragment_shader: terrain_fill_fs.main_entry_point(),
+                depth_stencil: DepthStencil::disabled(),
+                blend: Blend::alpha_blending(),
+                render_pass: subpass()?,
+            }
+        )?);
+
+        let terrain_pipeline = Arc::new(GraphicsPipeline::new(
+            device.clone(),
+            GraphicsPipelineParams {
+                vertex_input: SingleBufferDefinition::<TerrainPoint>::new(),
+                vertex_shader: terrain_vs.main_entry_point(),
+                input_assembly: InputAssembly {
+                    topology: PrimitiveTopology::TriangleStrip,
+                    primitive_restart_enable: true
+                },
+                tessellation: None,
+                geometry_shader: None,
+                viewport: ViewportsState::DynamicViewports {
+                    scissors: vec![Scissor::irrelevant()],
+                },
+                raster: Rasterization {
+                    polygon_mode: PolygonMode::Line,
+                    ..Default::default()
+                },
+                multisample: Multisample::disabled(),
+                fragment_shader: terrain_fs.main_entry_point(),
+                depth_stencil: DepthStencil::disabled(),
+                blend: Blend::pass_through(),
+                render_pass: subpass()?,
+            }
+        )?);
+
+        let sprite_set = Arc::new(simple_descriptor_set!(sprite_pipeline.clone(), 0, {
+            display: uniform_buffer.clone()
+        }));
+
+        let terrain_fill_set = Arc::new(simple_descriptor_set!(terrain_fill_pipeline.clone(), 0, {
+            display: uniform_buffer.clone()
+        }));
+
+        let terrain_set = Arc::new(simple_descriptor_set!(terrain_pipeline.clone(), 0, {
+            display: uniform_buffer.clone()
+        }));
+
+        let overlay_set = Arc::new(simple_descriptor_set!(sprite_pipeline.clone(), 0, {
+            display: overlay_uniform_buffer.clone()
+        }));
+
+        let future = quad_vertex_buffer_future;
+
+        let nearest_sampler = texture::create_sampler(&device, Filtering::Nearest, false)?;
+        let linear_sampler = texture::create_sampler(&device, Filtering::Linear, false)?;
+        let tiling_sampler = texture::create_sampler(&device, Filtering::Nearest, true)?;
+
+        let mut renderer = Renderer {
+            device: device.clone(),
+            queue: queue,
+            target: target,
+
+            quad_vertex_buffer: quad_vertex_buffer,
+            sprite_pipeline: sprite_pipeline as Arc<GraphicsPipelineAbstract + Send + Sync>,
+            sprite_set: sprite_set as Arc<DescriptorSet + Sync + Send>,
+            sprite_instances: batch::InstanceRing::new(FRAMES_IN_FLIGHT),
+            batch_mode: BatchMode::Instanced,
+
+            textures: Vec::new(),
+            nearest_sampler: nearest_sampler,
+            linear_sampler: linear_sampler,
+            tiling_sampler: tiling_sampler,
+
+            terrain_buffers: terrain_buffers::TerrainBuffers::new(FRAMES_IN_FLIGHT),
+            terrain_fill_pipeline: terrain_fill_pipeline as Arc<GraphicsPipelineAbstract + Send + Sync>,
+            terrain_fill_set: terrain_fill_set as Arc<DescriptorSet + Sync + Send>,
+            terrain_textures: (TextureId::MISSING, TextureId::MISSING),
+
+            terrain_wireframe: false,
+            terrain_pipeline: terrain_pipeline as Arc<GraphicsPipelineAbstract + Send + Sync>,
+            terrain_set: terrain_set as Arc<DescriptorSet + Sync + Send>,
+
+            display_uniform_buffer: uniform_buffer,
+            display_size: [1, 1],
+            camera: Camera::new([0.0, 0.0]),
+
+            overlay_uniform_buffer: overlay_uniform_buffer,
+            overlay_set: overlay_set as Arc<DescriptorSet + Sync + Send>,
+            overlay_instances: batch::InstanceRing::new(FRAMES_IN_FLIGHT),
+
+            timings: Cell::new(RenderTimings::default()),
+
+            frame_future: UnsafeCell::new(Box::new(future) as Box<GpuFuture>),
+        };
+
+        // Become TextureId::MISSING and TextureId::WHITE
+        renderer.create_texture(&texture::missing_texture(), Filtering::Nearest)?;
+        renderer.create_texture(&texture::white_texture(), Filtering::Nearest)?;
+
+        let fill = renderer.create_tiling_texture(&texture::dirt_texture())?;
+        let grass = renderer.create_tiling_texture(&texture::grass_texture())?;
+        renderer.set_terrain_textures(fill, grass);
+
+        Ok(renderer)
+    }
+
+    // On failure the pending work is dropped and the next frame starts from
+    // an empty future
+    fn with_future<T, F>(&self, f: F) -> Result<(), RendererError>
+        where T: GpuFuture + 'static, F: FnOnce(Box<GpuFuture>) -> Result<T, RendererError> {
+
+        let frame_future = unsafe {
+            let ptr = self.frame_future.get();
+            ptr::read(ptr)
+        };
+
+        let (new_future, result) = match f(frame_future) {
+            Ok(future) => (Box::new(future) as Box<GpuFuture>, Ok(())),
+            Err(err) => (Box::new(sync::now(self.device.clone())) as Box<GpuFuture>, Err(err))
+        };
+
+        unsafe {
+            let ptr = self.frame_future.get();
+            ptr::write(ptr, new_future);
+        }
+
+        result
+    }
+
+    // Block until all submitted work has finished
+    pub fn wait_idle(&self) -> Result<(), RendererError> {
+        self.with_future(|future| {
+            future
+                .then_signal_fence_and_flush()?
+                .wait(None)?;
+
+            Ok(sync::now(self.device.clone()))
+        })
+    }
+
+    // Copied into the buffers of the next frame drawn, only where it differs
+    // from what they already hold. Does nothing if this revision of the mesh
+    // is already loaded.
+    pub fn load_terrain(&mut self, terrain: &TerrainMesh) -> Result<(), RendererError> {
+        self.terrain_buffers.set_mesh(terrain);
+        Ok(())
+    }
+
+    // Textures for the inside of the terrain and the strip along its
+    // surface. Both should come from create_tiling_texture.
+    pub fn set_terrain_textures(&mut self, fill: TextureId, grass: TextureId) {
+        self.terrain_textures = (fill, grass);
+    }
+
+    pub fn set_terrain_wireframe(&mut self, wireframe: bool) {
+        self.terrain_wireframe = wireframe;
+    }
+
+    pub fn terrain_wireframe(&self) -> bool {
+        self.terrain_wireframe
+    }
+
+    pub fn set_batch_mode(&mut self, mode: BatchMode) {
+        self.batch_mode = mode;
+    }
+
+    pub fn update_display_uniforms(&mut self, w: u32, h: u32) -> Result<(), RendererError> {
+        self.display_size = [w, h];
+        self.upload_display_uniforms(1.0)?;
+
+        // Overlay coordinates are plain pixels from the top left
+        let overlay = shaders::sprite::DisplayUniforms {
+            view: Camera::new([w as f32 / 2.0, h as f32 / 2.0]).view_matrix(self.display_size)
+        };
+
+        self.upload_uniforms(&self.overlay_uniform_buffer, overlay)
+    }
+
+    pub fn display_size(&self) -> [u32; 2] {
+        self.display_size
+    }
+
+    pub fn last_timings(&self) -> RenderTimings {
+        self.timings.get()
+    }
+
+    // Frames are drawn with the camera interpolated the same way as sprites
+    pub fn set_camera(&mut self, camera: &Camera) -> Result<(), RendererError> {
+        self.camera = camera.clone();
+        self.upload_display_uniforms(1.0)
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub fn world_to_screen(&self, world: [f32; 2]) -> [f32; 2] {
+        self.camera.world_to_screen(world, self.display_size)
+    }
+
+    pub fn screen_to_world(&self, screen: [f32; 2]) -> [f32; 2] {
+        self.camera.screen_to_world(screen, self.display_size)
+    }
+
+    fn upload_display_uniforms(&self, alpha: f32) -> Result<(), RendererError> {
+        let uniforms = shaders::sprite::DisplayUniforms {
+            view: self.camera.interpolated(alpha).view_matrix(self.display_size)
+        };
+
+        self.upload_uniforms(&self.display_uniform_buffer, uniforms)
+    }
+
+    fn upload_uniforms(&self, buffer: &Arc<DeviceLocalBuffer<shaders::sprite::DisplayUniforms>>, uniforms: shaders::sprite::DisplayUniforms) -> Result<(), RendererError> {
+        self.with_future(|future| {
+            let command_buffer = AutoCommandBufferBuilder::new(self.device.clone(), self.queue.family())
+                .map_err(RendererError::command)?
+                .update_buffer(
+                    buffer.clone(),
+                    uniforms
+                )
+                .map_err(RendererError::command)?
+                .build()
+                .map_err(RendererError::command)?;
+
+            future
+                .then_execute(self.queue.clone(), command_buffer)
+                .map_err(RendererError::command)
+        })
+    }
+
+    // Called when the window changes size. The swapchain is rebuilt lazily
+    // before the next frame is drawn.
+    pub fn resize(&mut self, w: u32, h: u32) -> Result<(), RendererError> {
+        self.update_display_uniforms(w, h)?;
+
+        if let Target::Window { ref recreate, ref size, .. } = self.target {
+            size.set([w, h]);
+            recreate.set(true);
+        }
+
+        Ok(())
+    }
+
+    // Returns false if the surface can not currently be presented to, for
+    // example while the window is minimised
+    fn recreate_swapchain(&self, render_pass: &Arc<RenderPassAbstract + Send + Sync>, images: &mut SwapchainImages, size: [u32; 2]) -> Result<bool, RendererError> {
+        let dimensions = images.swapchain.surface()
+            .capabilities(self.device.physical_device())?
+            .current_extent
+            .unwrap_or(size);
+
+        let (swapchain, new_images) = match images.swapchain.recreate_with_dimension(dimensions) {
+            Ok(result) => result,
+            Err(SwapchainCreationError::UnsupportedDimensions) => return Ok(false),
+            Err(err) => return Err(err.into())
+        };
+
+        images.framebuffers = create_framebuffers(render_pass, &new_images)?;
+        images.dimensions = new_images[0].dimensions();
+        images.swapchain = swapchain;
+        Ok(true)
+    }
+
+    // Record the render pass for one frame into the given framebuffer
+    fn draw_frame(&self, framebuffer: Arc<FramebufferAbstract + Send + Sync>, dimensions: [u32; 2], sprites: &[Sprite], alpha: f32, overlay: &[Sprite]) -> Result<AutoCommandBufferBuilder, RendererError> {
+        let dynamic_state = DynamicState {
+            line_width: None,
+            viewports: Some(vec![Viewport {
+                origin: [0.0, 0.0],
+                depth_range: 0.0 .. 1.0,
+                dimensions: [dimensions[0] as f32, dimensions[1] as f32],
+            }]),
+            scissors: None,
+        };
+
+        let mut buffer = AutoCommandBufferBuilder::new(self.device.clone(), self.queue.family())
+            .map_err(RendererError::command)?
+            .begin_render_pass(
+                framebuffer,
+                false,
+                vec![[0.0, 0.0, 0.0, 1.0].into()]
+            )
+            .map_err(RendererError::command)?;
+
+        let terrain = self.terrain_buffers.upload(&self.device, &self.queue)?;
+
+        if let Some(ref terrain) = terrain {
+            let (fill, grass) = self.terrain_textures;
+
+            buffer = buffer
+                .draw_indexed(
+                    self.terrain_fill_pipeline.clone(),
+                    dynamic_state.clone(),
+                    vec![terrain.vertices.clone()],
+                    terrain.fill.clone(),
+                    (self.terrain_fill_set.clone(), self.texture(fill).set.clone()),
+                    ()
+                )
+                .map_err(RendererError::command)?;
+
+            if let Some(ref strip) = terrain.grass {
+                buffer = buffer
+                    .draw_indexed(
+                        self.terrain_fill_pipeline.clone(),
+                        dynamic_state.clone(),
+                        vec![terrain.vertices.clone()],
+                        strip.clone(),
+                        (self.terrain_fill_set.clone(), self.texture(grass).set.clone()),
+                        ()
+                    )
+                    .map_err(RendererError::command)?;
+            }
+        }
+
+        buffer = self.draw_sprites(buffer, &dynamic_state, &self.sprite_instances, &self.sprite_set, sprites, alpha)?;
+
+        if let (true, Some(terrain)) = (self.terrain_wireframe, terrain) {
+            buffer = buffer
+                .draw_indexed(
+                    self.terrain_pipeline.clone(),
+                    dynamic_state.clone(),
+                    vec![terrain.vertices],
+                    terrain.fill,
+                    self.terrain_set.clone(),
+                    ()
+                )
+                .map_err(RendererError::command)?;
+        }
+
+        self.draw_sprites(buffer, &dynamic_state, &self.overlay_instances, &self.overlay_set, overlay, 1.0)?
+            .end_render_pass()
+            .map_err(RendererError::command)
+    }
+
+    fn draw_sprites(
+        &self,
+        mut buffer: AutoCommandBufferBuilder,
+        dynamic_state: &DynamicState,
+        ring: &batch::InstanceRing,
+        display_set: &Arc<DescriptorSet + Sync + Send>,
+        sprites: &[Sprite],
+        alpha: f32
+    ) -> Result<AutoCommandBufferBuilder, RendererError> {
+        if sprites.is_empty() {
+            return Ok(buffer);
+        }
+
+        let (instances, batches) = batch::batch_sprites(sprites, alpha, self.batch_mode);
+        let instance_buffer = ring.upload(&self.device, &self.queue, &instances)?;
+
+        for (texture, range) in batches {
+            let batch = instance_buffer.clone()
+                .into_buffer_slice()
+                .slice(range)
+                .ok_or(RendererError::BufferOverrun("sprite instance buffer"))?;
+
+            buffer = buffer
+                .draw(
+                    self.sprite_pipeline.clone(),
+                    dynamic_state.clone(),
+                    (self.quad_vertex_buffer.clone(), batch),
+                    (display_set.clone(), self.texture(texture).set.clone()),
+                    ()
+                )
+                .map_err(RendererError::command)?;
+        }
+
+        Ok(buffer)
+    }
+
+    pub fn render(&self, sprites: &[Sprite]) -> Result<(), RendererError> {
+        self.render_interpolated(sprites, 1.0)
+    }
+
+    // Draw sprites `alpha` of the way from their previous to their current
+    // positions
+    pub fn render_interpolated(&self, sprites: &[Sprite], alpha: f32) -> Result<(), RendererError> {
+        self.render_with_overlay(sprites, alpha, &[])
+    }
+
+    // As render_interpolated, with `overlay` drawn over everything in screen
+    // pixels
+    pub fn render_with_overlay(&self, sprites: &[Sprite], alpha: f32, overlay: &[Sprite]) -> Result<(), RendererError> {
+        // Frames that are skipped report no time
+        self.timings.set(RenderTimings::default());
+
+        self.upload_display_uniforms(alpha)?;
+
+        match self.target {
+            Target::Window { ref render_pass, ref images, ref recreate, ref size } => {
+                if recreate.get() {
+                    if !self.recreate_swapchain(render_pass, &mut images.borrow_mut(), size.get())? {
+                        return Ok(());
+                    }
+
+                    recreate.set(false);
+                }
+
+                let images = images.borrow();
+
+                let acquire_start = Instant::now();
+                let (image_num, acquire_future) = match acquire_next_image(images.swapchain.clone(), Duration::new(1, 0)) {
+                    Ok(result) => result,
+
+                    Err(AcquireError::OutOfDate) => {
+                        recreate.set(true);
+                        return Ok(());
+                    },
+
+                    Err(err) => return Err(err.into())
+                };
+
+                let acquired = Instant::now();
+
+                let command_buffer = self.draw_frame(images.framebuffers[image_num].clone(), images.dimensions, sprites, alpha, overlay)?
+                    .build()
+                    .map_err(RendererError::command)?;
+
+                let built = Instant::now();
+
+                let result = self.with_future(|mut future| {
+                    future.cleanup_finished();
+
+                    let future = future
+                        .join(acquire_future)
+                        .then_execute(
+                            self.queue.clone(),
+                            command_buffer
+                        )
+                        .map_err(RendererError::command)?
+                        .then_swapchain_present(
+                            self.queue.clone(),
+                            images.swapchain.clone(),
+                            image_num
+                        )
+                        .then_signal_fence_and_flush();
+
+                    match future {
+                        Ok(future) => Ok(Box::new(future) as Box<GpuFuture>),
+
+                        // Presentation failed, drop the frame and rebuild
+                        Err(FlushError::OutOfDate) => {
+                            recreate.set(true);
+                            Ok(Box::new(sync::now(self.device.clone())) as Box<GpuFuture>)
+                        },
+
+                        Err(err) => Err(err.into())
+                    }
+                });
+
+                self.timings.set(RenderTimings {
+                    command_build: built - acquired,
+                    blocked: (acquired - acquire_start) + built.elapsed(),
+                });
+
+                result
+            },
+
+            Target::Headless { ref image, ref framebuffer, ref readback, dimensions } => self.with_future(|mut future| {
+                future.cleanup_finished();
+
+                let start = Instant::now();
+
+                let command_buffer = self.draw_frame(framebuffer.clone(), dimensions, sprites, alpha, overlay)?
+                    .copy_image_to_buffer(image.clone(), readback.clone())
+                    .map_err(RendererError::command)?
+                    .build()
+                    .map_err(RendererError::command)?;
+
+                let built = Instant::now();
+
+                let future = future
+                    .then_execute(
+                        self.queue.clone(),
+                        command_buffer
+                    )
+                    .map_err(RendererError::command)?
+                    .then_signal_fence_and_flush()?;
+
+                self.timings.set(RenderTimings {
+                    command_build: built - start,
+                    blocked: built.elapsed(),
+                });
+
+                Ok(future)
+            })
+        }
+    }
+
+    // Wait for the last headless frame and copy it out of the readback
+    // buffer. Returns None when rendering to a window.
+    pub fn capture(&self) -> Result<Option<RgbaImage>, RendererError> {
+        match self.target {
+            Target::Headless { ref readback, dimensions, .. } => {
+                self.wait_idle()?;
+
+                let pixels = readback.read().map_err(RendererError::command)?;
+                Ok(RgbaImage::from_raw(dimensions[0], dimensions[1], pixels.to_vec()))
+            },
+
+            Target::Window { .. } => Ok(None)
+        }
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), RendererError> {
+        let frame = self.capture()?
+            .ok_or(RendererError::CaptureUnsupported)?;
+
+        frame.save(path.as_ref())
+            .map_err(|err| RendererError::ImageSaveFailed(path.as_ref().to_path_buf(), err))
+    }
+}
+
+fn can_present(device: &PhysicalDevice, window: &Window) -> bool {
+    device.queue_families().any(|queue| {
+        queue.supports_graphics() && window.surface().is_supported(queue).unwrap_or(false)
+    })
+}
+
+fn create_render_pass(device: &Arc<Device>, format: Format) -> Result<Arc<RenderPassAbstract + Send + Sync>, RendererError> {
+    let render_pass = single_pass_renderpass!(
+        device.clone(),
+        attachments: {
+            color: {
+                load: Clear,
+                store: Store,
+                format: format,
+                samples: 1,
+            }
+        },
+        pass: {
+            color: [color],
+            depth_stencil: {}
+        }
+    ).map_err(RendererError::command)?;
+
+    Ok(Arc::new(render_pass))
+}
+
+fn create_framebuffers(render_pass: &Arc<RenderPassAbstract + Send + Sync>, images: &[Arc<SwapchainImage>]) -> Result<Vec<Arc<FramebufferAbstract + Send + Sync>>, RendererError> {
+    images.iter().map(|image| {
+        let framebuffer = Framebuffer::start(render_pass.clone())
+            .add(image.clone()).map_err(RendererError::command)?
+            .build().map_err(RendererError::command)?;
+
+        Ok(Arc::new(framebuffer) as Arc<FramebufferAbstract + Send + Sync>)
+    }).collect()
+}
@@ -0,0 +1,259 @@
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::iter::repeat;
+use std::ops::Range;
+use std::sync::Arc;
+
+use vulkano::buffer::{BufferSlice, BufferUsage};
+use vulkano::buffer::cpu_access::CpuAccessibleBuffer;
+use vulkano::device::{Device, Queue};
+
+use math::Vec2;
+use terrain::{self, TerrainMesh, TerrainVertex};
+
+use super::RendererError;
+
+// World units covered by one repeat of the fill texture
+const FILL_TILE: f32 = 128.0;
+
+// World units covered by one repeat of the grass texture along the surface
+const GRASS_TILE: f32 = 64.0;
+
+// How far the grass strip reaches out of and into the terrain
+const GRASS_ABOVE: f32 = 8.0;
+const GRASS_BELOW: f32 = 16.0;
+
+// Shade of fan vertices away from the surface, darkening the fill with depth
+const INNER_SHADE: f32 = 0.6;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct TerrainPoint {
+    point: [f32; 2],
+    uv: [f32; 2],
+    shade: f32,
+}
+
+impl_vertex!(TerrainPoint, point, uv, shade);
+
+// Index ranges of one frame's terrain, drawn from the same vertex buffer
+pub(super) struct TerrainDraw {
+    pub vertices: Arc<CpuAccessibleBuffer<[TerrainPoint]>>,
+    pub fill: BufferSlice<[u32], Arc<CpuAccessibleBuffer<[u32]>>>,
+    pub grass: Option<BufferSlice<[u32], Arc<CpuAccessibleBuffer<[u32]>>>>,
+}
+
+// Buffers for one frame in flight and the mesh revision written to them
+struct Slot {
+    vertex_buffer: Arc<CpuAccessibleBuffer<[TerrainPoint]>>,
+    index_buffer: Arc<CpuAccessibleBuffer<[u32]>>,
+    revision: Option<usize>,
+}
+
+// Host visible terrain buffers that live as long as the renderer, one pair
+// per frame in flight. A new mesh is only kept on the cpu until the next
+// frame is drawn, which copies whatever differs from that frame's slot. The
+// buffers grow as needed and are otherwise reused.
+//
+// The fill triangles come first, followed by a strip of quads along the
+// surface for the grass.
+pub(super) struct TerrainBuffers {
+    slots: RefCell<Vec<Option<Slot>>>,
+    next: Cell<usize>,
+
+    vertices: Vec<TerrainPoint>,
+    indices: Vec<u32>,
+    fill_indices: usize,
+    revision: Option<usize>,
+}
+
+impl TerrainBuffers {
+    pub fn new(frames: usize) -> TerrainBuffers {
+        TerrainBuffers {
+            slots: RefCell::new((0 .. frames).map(|_| None).collect()),
+            next: Cell::new(0),
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            fill_indices: 0,
+            revision: None,
+        }
+    }
+
+    // Does nothing if this revision of the mesh is already the latest
+    pub fn set_mesh(&mut self, terrain: &TerrainMesh) {
+        if self.revision == Some(terrain.revision()) {
+            return;
+        }
+
+        // Texture coordinates come from the world position, so the fill
+        // lines up across polygons and stays put as the terrain changes
+        self.vertices.clear();
+        self.vertices.extend(terrain.vertices().iter().map(|vertex| {
+            let (coords, shade) = match *vertex {
+                TerrainVertex::Surface(coords) => (coords, 1.0),
+                TerrainVertex::Inner(coords) => (coords, INNER_SHADE),
+            };
+
+            let point: [f32; 2] = coords.to_render().into();
+            TerrainPoint { point: point, uv: [point[0] / FILL_TILE, point[1] / FILL_TILE], shade: shade }
+        }));
+
+        self.indices.clear();
+        self.indices.extend(terrain.mesh_indices(0));
+        self.fill_indices = self.indices.len();
+
+        self.add_grass(terrain);
+
+        self.revision = Some(terrain.revision());
+    }
+
+    // Quads straddling each surface edge, pushed out along the vertex
+    // normals so that neighbours meet. The texture runs along the surface by
+    // distance travelled, with v going from the outside in.
+    fn add_grass(&mut self, terrain: &TerrainMesh) {
+        for chain in terrain.surface_chains() {
+            for edge in 0 .. chain.edge_count() {
+                let (start, end) = chain.edge(edge);
+                let next = (edge + 1) % chain.points().len();
+                let edge_normal = chain.edge_normal(edge);
+
+                let end_distance = if next == 0 { chain.length() } else { chain.distance(next) };
+                let base = self.vertices.len() as u32;
+
+                // Brightest where the surface faces up, which is -y
+                let shade = 0.7 + 0.3 * (-edge_normal.1).max(0.0);
+
+                for &(point, vertex, u) in &[(start, edge, chain.distance(edge)), (end, next, end_distance)] {
+                    let normal = miter(chain.vertex_normal(vertex), edge_normal);
+
+                    for &(offset, v) in &[(GRASS_ABOVE, 0.0), (-GRASS_BELOW, 1.0)] {
+                        self.vertices.push(TerrainPoint {
+                            point: (point + normal * offset).into(),
+                            uv: [u / GRASS_TILE, v],
+                            shade: shade,
+                        });
+                    }
+                }
+
+                self.indices.extend_from_slice(&[base, base + 1, base + 2, base + 3, terrain::RESTART]);
+            }
+        }
+    }
+
+    // Buffers holding the latest mesh for the frame about to be drawn, None
+    // if there is nothing to draw
+    pub fn upload(&self, device: &Arc<Device>, queue: &Arc<Queue>) -> Result<Option<TerrainDraw>, RendererError> {
+        if self.indices.is_empty() {
+            return Ok(None);
+        }
+
+        let mut slots = self.slots.borrow_mut();
+
+        let index = self.next.get();
+        self.next.set((index + 1) % slots.len());
+
+        let slot = match slots[index].take() {
+            Some(mut slot) => {
+                if self.update(&mut slot) { slot } else { self.create(device, queue)? }
+            },
+
+            None => self.create(device, queue)?
+        };
+
+        let draw = {
+            let slice = |range: Range<usize>| {
+                slot.index_buffer.clone().into_buffer_slice().slice(range)
+                    .ok_or(RendererError::BufferOverrun("terrain index buffer"))
+            };
+
+            let grass = if self.indices.len() > self.fill_indices {
+                Some(slice(self.fill_indices .. self.indices.len())?)
+            } else {
+                None
+            };
+
+            TerrainDraw {
+                vertices: slot.vertex_buffer.clone(),
+                fill: slice(0 .. self.fill_indices)?,
+                grass: grass,
+            }
+        };
+
+        slots[index] = Some(slot);
+
+        Ok(Some(draw))
+    }
+
+    // Bring a slot up to date in place, comparing against what its buffers
+    // already hold so only the part that changed is written. Fails if it is
+    // too small or still in use by the gpu.
+    fn update(&self, slot: &mut Slot) -> bool {
+        if slot.revision == self.revision {
+            return true;
+        }
+
+        if slot.vertex_buffer.len() < self.vertices.len() || slot.index_buffer.len() < self.indices.len() {
+            return false;
+        }
+
+        // Lock both before writing either, so a busy slot is left as it was
+        let (mut vertices, mut indices) = match (slot.vertex_buffer.write(), slot.index_buffer.write()) {
+            (Ok(vertices), Ok(indices)) => (vertices, indices),
+            _ => return false
+        };
+
+        write_changed(&mut vertices, &self.vertices);
+        write_changed(&mut indices, &self.indices);
+
+        slot.revision = self.revision;
+
+        true
+    }
+
+    fn create(&self, device: &Arc<Device>, queue: &Arc<Queue>) -> Result<Slot, RendererError> {
+        let vertex_capacity = cmp::max(self.vertices.len().next_power_of_two(), 64);
+        let index_capacity = cmp::max(self.indices.len().next_power_of_two(), 64);
+
+        let vertex_buffer = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::vertex_buffer(),
+            Some(queue.family()),
+            self.vertices.iter().cloned().chain(repeat(TerrainPoint::default())).take(vertex_capacity)
+        )
+            .map_err(RendererError::allocation("terrain vertex buffer"))?;
+
+        let index_buffer = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::index_buffer(),
+            Some(queue.family()),
+            self.indices.iter().cloned().chain(repeat(0)).take(index_capacity)
+        )
+            .map_err(RendererError::allocation("terrain index buffer"))?;
+
+        Ok(Slot {
+            vertex_buffer: vertex_buffer,
+            index_buffer: index_buffer,
+            revision: self.revision,
+        })
+    }
+}
+
+// Overwrite the start of `buffer` with `new`, from the first element that
+// differs to the last
+fn write_changed<T: Copy + PartialEq>(buffer: &mut [T], new: &[T]) {
+    let old = &mut buffer[.. new.len()];
+
+    let start = match old.iter().zip(new.iter()).position(|(a, b)| a != b) {
+        Some(start) => start,
+        None => return
+    };
+
+    let end = new.len() - old.iter().rev().zip(new.iter().rev()).position(|(a, b)| a != b).unwrap_or(0);
+
+    old[start .. end].copy_from_slice(&new[start .. end]);
+}
+
+// Scale a vertex normal so the strip keeps its width along the edge, within
+// reason at sharp corners
+fn miter(normal: Vec2, edge_normal: Vec2) -> Vec2 {
+    normal / normal.dot(edge_normal).max(0.5)
+}
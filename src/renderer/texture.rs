@@ -0,0 +1,186 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use image::{self, Rgba, RgbaImage};
+
+use vulkano::buffer::BufferUsage;
+use vulkano::buffer::cpu_access::CpuAccessibleBuffer;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferBuilder};
+use vulkano::descriptor::descriptor_set::DescriptorSet;
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::image::Dimensions;
+use vulkano::image::immutable::ImmutableImage;
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+use vulkano::sync::GpuFuture;
+
+use super::{Renderer, RendererError};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TextureId(usize);
+
+impl TextureId {
+    // Flat magenta, used by sprites without a texture
+    pub const MISSING: TextureId = TextureId(0);
+
+    // Flat white, for untextured sprites coloured by their tint
+    pub const WHITE: TextureId = TextureId(1);
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Filtering {
+    Nearest,
+    Linear,
+}
+
+pub struct Texture {
+    pub dimensions: [u32; 2],
+    pub(super) set: Arc<DescriptorSet + Send + Sync>,
+}
+
+// Tiling samplers repeat the texture along u and v, for surfaces whose
+// texture coordinates run well past 1
+pub(super) fn create_sampler(device: &Arc<Device>, filtering: Filtering, tiling: bool) -> Result<Arc<Sampler>, RendererError> {
+    let filter = match filtering {
+        Filtering::Nearest => Filter::Nearest,
+        Filtering::Linear => Filter::Linear,
+    };
+
+    let address = if tiling { SamplerAddressMode::Repeat } else { SamplerAddressMode::ClampToEdge };
+
+    Sampler::new(
+        device.clone(),
+        filter,
+        filter,
+        MipmapMode::Nearest,
+        address,
+        address,
+        SamplerAddressMode::ClampToEdge,
+        0.0,
+        1.0,
+        0.0,
+        0.0
+    )
+        .map_err(RendererError::allocation("sampler"))
+}
+
+pub(super) fn missing_texture() -> RgbaImage {
+    RgbaImage::from_pixel(1, 1, Rgba { data: [255, 0, 255, 255] })
+}
+
+pub(super) fn white_texture() -> RgbaImage {
+    RgbaImage::from_pixel(1, 1, Rgba { data: [255, 255, 255, 255] })
+}
+
+// Speckled earth, the default terrain fill. Each pixel is independent so it
+// tiles without seams.
+pub(super) fn dirt_texture() -> RgbaImage {
+    RgbaImage::from_fn(64, 64, |x, y| {
+        let noise = (speckle(x, y) % 32) as u8;
+        Rgba { data: [96 + noise, 64 + noise / 2, 40 + noise / 2, 255] }
+    })
+}
+
+// Default grass strip. Columns run along the surface, the top rows are
+// blades of varying height against nothing and the rest is turf that fades
+// into the fill below.
+pub(super) fn grass_texture() -> RgbaImage {
+    const BLADES: u32 = 8;
+    const HEIGHT: u32 = 24;
+
+    RgbaImage::from_fn(64, HEIGHT, |x, y| {
+        let noise = (speckle(x, y) % 24) as u8;
+        let blade = 2 + speckle(x, 0) % (BLADES - 1);
+
+        let alpha = if y < BLADES - blade {
+            0
+        } else if y >= HEIGHT - 6 {
+            (255 * (HEIGHT - y) / 7) as u8
+        } else {
+            255
+        };
+
+        Rgba { data: [60 + noise, 130 + noise, 40 + noise / 2, alpha] }
+    })
+}
+
+// Cheap hash of a pixel position
+fn speckle(x: u32, y: u32) -> u32 {
+    let mut h = x.wrapping_mul(374761393) ^ y.wrapping_mul(668265263);
+    h = (h ^ (h >> 13)).wrapping_mul(1274126177);
+    h ^ (h >> 16)
+}
+
+impl Renderer {
+    pub fn load_texture<P: AsRef<Path>>(&mut self, path: P, filtering: Filtering) -> Result<TextureId, RendererError> {
+        let pixels = image::open(path.as_ref())
+            .map_err(|err| RendererError::ImageLoadFailed(path.as_ref().to_owned(), err))?
+            .to_rgba();
+
+        self.create_texture(&pixels, filtering)
+    }
+
+    pub fn create_texture(&mut self, pixels: &RgbaImage, filtering: Filtering) -> Result<TextureId, RendererError> {
+        let sampler = match filtering {
+            Filtering::Nearest => self.nearest_sampler.clone(),
+            Filtering::Linear => self.linear_sampler.clone(),
+        };
+
+        self.create_texture_with(pixels, sampler)
+    }
+
+    // A texture that repeats rather than clamping at its edges, as the
+    // terrain textures do
+    pub fn create_tiling_texture(&mut self, pixels: &RgbaImage) -> Result<TextureId, RendererError> {
+        let sampler = self.tiling_sampler.clone();
+        self.create_texture_with(pixels, sampler)
+    }
+
+    fn create_texture_with(&mut self, pixels: &RgbaImage, sampler: Arc<Sampler>) -> Result<TextureId, RendererError> {
+        let (w, h) = pixels.dimensions();
+
+        let image = ImmutableImage::new(
+            self.device.clone(),
+            Dimensions::Dim2d { width: w, height: h },
+            Format::R8G8B8A8Srgb,
+            Some(self.queue.family())
+        )?;
+
+        // Stage pixels in host visible memory and copy them across
+        let upload = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::transfer_source(),
+            Some(self.queue.family()),
+            pixels.iter().cloned()
+        )
+            .map_err(RendererError::allocation("texture upload buffer"))?;
+
+        self.with_future(|future| {
+            let command_buffer = AutoCommandBufferBuilder::new(self.device.clone(), self.queue.family())
+                .map_err(RendererError::command)?
+                .copy_buffer_to_image(upload, image.clone())
+                .map_err(RendererError::command)?
+                .build()
+                .map_err(RendererError::command)?;
+
+            future
+                .then_execute(self.queue.clone(), command_buffer)
+                .map_err(RendererError::command)
+        })?;
+
+        let set = Arc::new(simple_descriptor_set!(self.sprite_pipeline.clone(), 1, {
+            tex: (image.clone(), sampler)
+        }));
+
+        self.textures.push(Texture {
+            dimensions: [w, h],
+            set: set as Arc<DescriptorSet + Send + Sync>,
+        });
+
+        Ok(TextureId(self.textures.len() - 1))
+    }
+
+    pub fn texture(&self, id: TextureId) -> &Texture {
+        &self.textures[id.0]
+    }
+}
@@ -0,0 +1,437 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use ron;
+
+use binary::{read_u8, read_u32, read_u64, read_i32, read_f32, read_string, write_u8, write_u32, write_u64, write_i32, write_f32, write_str};
+use controller::ControllerTuning;
+use input::{Bindings, InputFrame};
+use level::Level;
+use sprite::Sprite;
+use ty::WorldCoords;
+
+// File layout, all integers little endian:
+//
+//   magic "PREC", version u8, seed u64, sim rate u32,
+//   tuning and bindings as RON strings (u32 length then bytes), the level
+//   in its binary form,
+//   one entry per simulated tick, then an end byte of 0 and the final sprite
+//   positions (u32 count then i32 pairs).
+//
+// An entry starts with a byte holding the tick's step count in the low five
+// bits, with bit 5 set if action states follow and bit 6 if analog values
+// follow. Either is only written when it changed since the previous entry.
+const MAGIC: &[u8; 4] = b"PREC";
+const VERSION: u8 = 3;
+
+const STEPS_MASK: u8 = 0x1f;
+const ACTIONS_FLAG: u8 = 0x20;
+const ANALOG_FLAG: u8 = 0x40;
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u8),
+    Corrupt(&'static str),
+    Config(String),
+
+    // A sprite ended up somewhere other than it did when recorded
+    Diverged {
+        sprite: usize,
+        expected: WorldCoords,
+        actual: WorldCoords,
+    },
+
+    // The recording stopped before its final positions were written
+    Incomplete,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReplayError::Io(ref err) => write!(f, "{}", err),
+            ReplayError::BadMagic => write!(f, "Not a replay file"),
+            ReplayError::UnsupportedVersion(version) => write!(f, "Unsupported replay version {}", version),
+            ReplayError::Corrupt(what) => write!(f, "Corrupt replay: {}", what),
+            ReplayError::Config(ref err) => write!(f, "Bad recorded config: {}", err),
+            ReplayError::Diverged { sprite, expected, actual } => write!(f, "Sprite {} ended at {:?}, recorded at {:?}", sprite, actual, expected),
+            ReplayError::Incomplete => write!(f, "Recording has no final positions to compare"),
+        }
+    }
+}
+
+impl Error for ReplayError {
+    fn description(&self) -> &str {
+        match *self {
+            ReplayError::Io(..) => "io error",
+            ReplayError::BadMagic => "bad magic",
+            ReplayError::UnsupportedVersion(..) => "unsupported version",
+            ReplayError::Corrupt(..) => "corrupt replay",
+            ReplayError::Config(..) => "bad recorded config",
+            ReplayError::Diverged { .. } => "replay diverged",
+            ReplayError::Incomplete => "incomplete recording",
+        }
+    }
+}
+
+impl From<io::Error> for ReplayError {
+    fn from(err: io::Error) -> ReplayError {
+        ReplayError::Io(err)
+    }
+}
+
+// Everything besides input that the simulation depends on
+#[derive(Clone, Debug)]
+pub struct Header {
+    pub seed: u64,
+    pub sim_rate: u32,
+    pub tuning: ControllerTuning,
+    pub bindings: Bindings,
+    pub level: Level,
+}
+
+fn action_bytes(actions: usize) -> usize {
+    (actions + 7) / 8
+}
+
+// Writes the input of each simulated tick as it happens
+pub struct Recorder {
+    writer: BufWriter<File>,
+    last: Option<InputFrame>,
+}
+
+impl Recorder {
+    pub fn create<P: AsRef<Path>>(path: P, header: &Header) -> Result<Recorder, ReplayError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        let tuning = ron::ser::to_string(&header.tuning).map_err(|err| ReplayError::Config(err.to_string()))?;
+        let bindings = ron::ser::to_string(&header.bindings).map_err(|err| ReplayError::Config(err.to_string()))?;
+
+        writer.write_all(MAGIC)?;
+        write_u8(&mut writer, VERSION)?;
+        write_u64(&mut writer, header.seed)?;
+        write_u32(&mut writer, header.sim_rate)?;
+        write_str(&mut writer, &tuning)?;
+        write_str(&mut writer, &bindings)?;
+        header.level.write_to(&mut writer)?;
+
+        Ok(Recorder {
+            writer: writer,
+            last: None,
+        })
+    }
+
+    pub fn record(&mut self, steps: u32, frame: &InputFrame) -> io::Result<()> {
+        if steps == 0 || steps > STEPS_MASK as u32 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Step count out of range"));
+        }
+
+        let (actions, analog) = match self.last {
+            Some(ref last) => (last.actions != frame.actions, last.analog != frame.analog),
+            None => (true, true)
+        };
+
+        let mut tag = steps as u8;
+        if actions { tag |= ACTIONS_FLAG; }
+        if analog { tag |= ANALOG_FLAG; }
+
+        write_u8(&mut self.writer, tag)?;
+
+        if actions {
+            let mut bits = vec![0u8; action_bytes(frame.actions.len())];
+            for (i, _) in frame.actions.iter().enumerate().filter(|&(_, &down)| down) {
+                bits[i / 8] |= 1 << (i % 8);
+            }
+
+            self.writer.write_all(&bits)?;
+        }
+
+        if analog {
+            for value in frame.analog.iter() {
+                write_f32(&mut self.writer, *value)?;
+            }
+        }
+
+        self.last = Some(frame.clone());
+        Ok(())
+    }
+
+    // End the recording with the positions a replay should reach
+    pub fn finish(mut self, sprites: &[Sprite]) -> io::Result<()> {
+        write_u8(&mut self.writer, 0)?;
+        write_u32(&mut self.writer, sprites.len() as u32)?;
+
+        for sprite in sprites {
+            write_i32(&mut self.writer, sprite.rect.position.0)?;
+            write_i32(&mut self.writer, sprite.rect.position.1)?;
+        }
+
+        self.writer.flush()
+    }
+}
+
+pub struct Replay {
+    pub header: Header,
+    reader: BufReader<File>,
+    actions: usize,
+    frame: InputFrame,
+
+    // Read once the end of the input is reached
+    expected: Option<Vec<WorldCoords>>,
+    finished: bool,
+}
+
+impl Replay {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Replay, ReplayError> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(ReplayError::BadMagic);
+        }
+
+        let version = read_u8(&mut reader)?;
+        if version != VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+
+        let seed = read_u64(&mut reader)?;
+        let sim_rate = read_u32(&mut reader)?;
+        if sim_rate == 0 {
+            return Err(ReplayError::Corrupt("zero simulation rate"));
+        }
+
+        let tuning = ron::de::from_str(&read_string(&mut reader)?).map_err(|err| ReplayError::Config(err.to_string()))?;
+        let bindings: Bindings = ron::de::from_str(&read_string(&mut reader)?).map_err(|err| ReplayError::Config(err.to_string()))?;
+        let actions = bindings.actions.len();
+
+        let level = Level::read_from(&mut reader)?;
+        if !level.validate().is_empty() {
+            return Err(ReplayError::Corrupt("invalid level"));
+        }
+
+        Ok(Replay {
+            header: Header {
+                seed: seed,
+                sim_rate: sim_rate,
+                tuning: tuning,
+                bindings: bindings,
+                level: level,
+            },
+
+            reader: reader,
+            actions: actions,
+            frame: InputFrame {
+                actions: vec![false; actions],
+                analog: [0.0; 6],
+            },
+
+            expected: None,
+            finished: false,
+        })
+    }
+
+    // Step count and input of the next recorded tick, None once there are
+    // no more. A recording cut off between entries ends there.
+    pub fn next(&mut self) -> Result<Option<(u32, InputFrame)>, ReplayError> {
+        if self.finished {
+            return Ok(None);
+        }
+
+        let tag = match read_u8(&mut self.reader) {
+            Ok(tag) => tag,
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                self.finished = true;
+                return Ok(None);
+            },
+
+            Err(err) => return Err(err.into())
+        };
+
+        if tag == 0 {
+            self.finished = true;
+
+            let count = read_u32(&mut self.reader)?;
+            let mut expected = Vec::new();
+
+            for _ in 0 .. count {
+                let x = read_i32(&mut self.reader)?;
+                let y = read_i32(&mut self.reader)?;
+                expected.push(WorldCoords(x, y));
+            }
+
+            self.expected = Some(expected);
+            return Ok(None);
+        }
+
+        if tag & !(STEPS_MASK | ACTIONS_FLAG | ANALOG_FLAG) != 0 || tag & STEPS_MASK == 0 {
+            return Err(ReplayError::Corrupt("bad entry"));
+        }
+
+        if tag & ACTIONS_FLAG != 0 {
+            let mut bits = vec![0; action_bytes(self.actions)];
+            self.reader.read_exact(&mut bits)?;
+
+            for (i, down) in self.frame.actions.iter_mut().enumerate() {
+                *down = bits[i / 8] & (1 << (i % 8)) != 0;
+            }
+        }
+
+        if tag & ANALOG_FLAG != 0 {
+            for value in self.frame.analog.iter_mut() {
+                *value = read_f32(&mut self.reader)?;
+            }
+        }
+
+        Ok(Some(((tag & STEPS_MASK) as u32, self.frame.clone())))
+    }
+
+    // Compare sprites against where they were when the recording finished.
+    // Only meaningful once every entry has been read.
+    pub fn check(&self, sprites: &[Sprite]) -> Result<(), ReplayError> {
+        let expected = match self.expected {
+            Some(ref expected) => expected,
+            None => return Err(ReplayError::Incomplete)
+        };
+
+        if expected.len() != sprites.len() {
+            return Err(ReplayError::Corrupt("sprite count differs"));
+        }
+
+        for (i, (&expected, sprite)) in expected.iter().zip(sprites.iter()).enumerate() {
+            let actual = sprite.rect.position;
+
+            if expected.0 != actual.0 || expected.1 != actual.1 {
+                return Err(ReplayError::Diverged {
+                    sprite: i,
+                    expected: expected,
+                    actual: actual,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use controller::ControllerTuning;
+    use input::{Bindings, InputFrame, InputMap};
+    use level::Level;
+    use world::World;
+
+    use super::*;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    fn header() -> Header {
+        Header {
+            seed: 7,
+            sim_rate: 60,
+            tuning: ControllerTuning::default(),
+            bindings: Bindings::default(),
+            level: Level::builtin(),
+        }
+    }
+
+    // Actions in binding order: dig, jump, move_left, move_right,
+    // toggle_stats, toggle_wireframe
+    fn frame(tick: u32) -> InputFrame {
+        let mut analog = [0.0; 6];
+        analog[0] = if tick > 20 { -0.5 } else { 0.0 };
+
+        InputFrame {
+            actions: vec![tick == 40, tick % 15 == 0, false, tick < 30, false, false],
+            analog: analog,
+        }
+    }
+
+    // Play `ticks` of input into a fresh world, recording them to `path`
+    fn record(path: &Path, header: &Header, ticks: u32) -> World {
+        let mut world = World::from_level(&header.level, header.tuning.clone(), header.seed);
+        let mut input = InputMap::new(header.bindings.clone());
+        let mut recorder = Recorder::create(path, header).unwrap();
+
+        for tick in 0 .. ticks {
+            let steps = 1 + tick % 3;
+            input.apply_frame(&frame(tick));
+
+            recorder.record(steps, &input.frame()).unwrap();
+            world.tick(&input, steps, DT);
+        }
+
+        recorder.finish(&world.sprites).unwrap();
+        world
+    }
+
+    fn replay(path: &Path) -> (Replay, World) {
+        let mut replay = Replay::open(path).unwrap();
+        let mut world = World::from_level(&replay.header.level, replay.header.tuning.clone(), replay.header.seed);
+        let mut input = InputMap::new(replay.header.bindings.clone());
+
+        while let Some((steps, frame)) = replay.next().unwrap() {
+            input.apply_frame(&frame);
+            world.tick(&input, steps, DT);
+        }
+
+        (replay, world)
+    }
+
+    #[test]
+    fn recording_replays_to_the_same_positions() {
+        let path = env::temp_dir().join("replay_round_trip.prec");
+        let header = header();
+
+        let recorded = record(&path, &header, 60);
+        let start = World::from_level(&header.level, header.tuning.clone(), header.seed);
+        assert!(recorded.sprites.iter().zip(start.sprites.iter()).any(|(a, b)| a.rect.position != b.rect.position));
+
+        let (replay, world) = replay(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(world.steps, recorded.steps);
+        assert!(replay.check(&world.sprites).is_ok());
+    }
+
+    #[test]
+    fn moved_sprites_fail_the_check() {
+        let path = env::temp_dir().join("replay_diverged.prec");
+        record(&path, &header(), 20);
+
+        let (replay, mut world) = replay(&path);
+        fs::remove_file(&path).unwrap();
+
+        world.sprites[0].rect.position.0 += 1;
+        match replay.check(&world.sprites) {
+            Err(ReplayError::Diverged { sprite: 0, .. }) => (),
+            other => panic!("expected a divergence, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn unfinished_recording_is_incomplete() {
+        let path = env::temp_dir().join("replay_unfinished.prec");
+
+        {
+            let mut recorder = Recorder::create(&path, &header()).unwrap();
+            recorder.record(1, &frame(0)).unwrap();
+        }
+
+        let (replay, world) = replay(&path);
+        fs::remove_file(&path).unwrap();
+
+        match replay.check(&world.sprites) {
+            Err(ReplayError::Incomplete) => (),
+            other => panic!("expected an incomplete recording, got {:?}", other)
+        }
+    }
+}
@@ -1,7 +1,5 @@
 use std::fmt;
 
-use ty::WorldRect;
-
 pub mod sprite {
     mod v {
         #[derive(VulkanoShader)]
@@ -20,7 +18,7 @@ pub mod sprite {
     pub use self::v::Shader as vertex;
     pub use self::f::Shader as fragment;
 
-    pub use self::v::ty::{DisplayUniforms, SpriteUniforms};
+    pub use self::v::ty::DisplayUniforms;
 }
 
 pub mod terrain {
@@ -42,17 +40,21 @@ pub mod terrain {
     pub use self::f::Shader as fragment;
 }
 
-impl<'a> From<&'a WorldRect> for sprite::SpriteUniforms {
-    fn from(rect: &'a WorldRect) -> sprite::SpriteUniforms {
-        sprite::SpriteUniforms {
-            pos: [rect.position.0, rect.position.1],
-            bounds: [rect.bounds.0, rect.bounds.1],
-        }
+pub mod terrain_fill {
+    mod v {
+        #[derive(VulkanoShader)]
+        #[ty = "vertex"]
+        #[path = "src/shaders/terrain_fill_vertex.glsl"]
+        struct Dummy;
     }
-}
 
-impl From<WorldRect> for sprite::SpriteUniforms {
-    fn from(rect: WorldRect) -> sprite::SpriteUniforms {
-        sprite::SpriteUniforms::from(&rect)
+    mod f {
+        #[derive(VulkanoShader)]
+        #[ty = "fragment"]
+        #[path = "src/shaders/terrain_fill_fragment.glsl"]
+        struct Dummy;
     }
+
+    pub use self::v::Shader as vertex;
+    pub use self::f::Shader as fragment;
 }
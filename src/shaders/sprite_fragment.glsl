@@ -1,7 +1,12 @@
 #version 450 core
 
+layout(set = 1, binding = 0) uniform sampler2D tex;
+
+layout(location = 0) in vec2 uv;
+layout(location = 1) in vec4 frag_tint;
+
 layout(location = 0) out vec4 colour;
 
 void main() {
-    colour = vec4(1, 0, 1, 1);
+    colour = texture(tex, uv) * frag_tint;
 }
@@ -1,23 +1,26 @@
 #version 450 core
 
-layout(binding = 0) uniform DisplayUniforms {
-    uvec2 bounds;
+layout(set = 0, binding = 0) uniform DisplayUniforms {
+    mat4 view;
 } display;
 
-layout(push_constant) uniform SpriteUniforms {
-    ivec2 pos;
-    uvec2 bounds;
-} sprite;
-
 layout(location = 0) in vec2 point;
 
+// Per instance
+layout(location = 1) in vec2 pos;
+layout(location = 2) in vec2 bounds;
+layout(location = 3) in vec2 uv_pos;
+layout(location = 4) in vec2 uv_size;
+layout(location = 5) in vec4 tint;
+
+layout(location = 0) out vec2 uv;
+layout(location = 1) out vec4 frag_tint;
+
 void main() {
-    vec2 world_coords = sprite.pos + vec2(sprite.bounds) * point;
+    vec2 world_coords = pos + bounds * point;
+
+    gl_Position = display.view * vec4(world_coords, 0, 1);
 
-    gl_Position = vec4(
-        2 * world_coords.x / display.bounds.x - 1, 
-        2 * world_coords.y / display.bounds.y - 1, 
-        0, 
-        1
-    );
+    uv = uv_pos + uv_size * point;
+    frag_tint = tint;
 }
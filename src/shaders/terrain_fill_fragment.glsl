@@ -0,0 +1,13 @@
+#version 450 core
+
+layout(set = 1, binding = 0) uniform sampler2D tex;
+
+layout(location = 0) in vec2 uv;
+layout(location = 1) in float shade;
+
+layout(location = 0) out vec4 colour;
+
+void main() {
+    vec4 texel = texture(tex, uv);
+    colour = vec4(texel.rgb * shade, texel.a);
+}
@@ -0,0 +1,19 @@
+#version 450 core
+
+layout(set = 0, binding = 0) uniform DisplayUniforms {
+    mat4 view;
+} display;
+
+layout(location = 0) in vec2 point;
+layout(location = 1) in vec2 uv;
+layout(location = 2) in float shade;
+
+layout(location = 0) out vec2 frag_uv;
+layout(location = 1) out float frag_shade;
+
+void main() {
+    gl_Position = display.view * vec4(point, 0, 1);
+
+    frag_uv = uv;
+    frag_shade = shade;
+}
@@ -1,13 +1,68 @@
-use ty::WorldRect;
+use ty::{WorldCoords, WorldRect};
+use renderer::TextureId;
+
+// Region of a texture in normalised coordinates
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct UvRect {
+    pub position: [f32; 2],
+    pub size: [f32; 2],
+}
+
+impl UvRect {
+    pub fn full() -> UvRect {
+        UvRect {
+            position: [0.0, 0.0],
+            size: [1.0, 1.0],
+        }
+    }
+
+    // Sub-rectangle given in pixels of a texture with the given dimensions
+    pub fn from_pixels(x: u32, y: u32, w: u32, h: u32, dimensions: [u32; 2]) -> UvRect {
+        let (tw, th) = (dimensions[0] as f32, dimensions[1] as f32);
+
+        UvRect {
+            position: [x as f32 / tw, y as f32 / th],
+            size: [w as f32 / tw, h as f32 / th],
+        }
+    }
+}
 
 pub struct Sprite {
-    pub rect: WorldRect
+    pub rect: WorldRect,
+
+    // Position at the end of the previous simulation step
+    pub previous_position: WorldCoords,
+
+    pub texture: TextureId,
+    pub uv: UvRect,
+
+    // Multiplied with the texture colour
+    pub tint: [f32; 4],
+
+    // Sprites on higher layers are drawn on top. Within a layer, later
+    // sprites are drawn over earlier ones.
+    pub layer: i32,
 }
 
 impl Sprite {
     pub fn new(rect: WorldRect) -> Sprite {
+        Sprite::textured(rect, TextureId::MISSING, UvRect::full())
+    }
+
+    pub fn textured(rect: WorldRect, texture: TextureId, uv: UvRect) -> Sprite {
         Sprite {
-            rect: rect
+            previous_position: rect.position,
+            rect: rect,
+            texture: texture,
+            uv: uv,
+
+            tint: [1.0, 1.0, 1.0, 1.0],
+            layer: 0
         }
     }
+
+    // Blend between the previous and current positions
+    pub fn interpolated_position(&self, alpha: f32) -> [f32; 2] {
+        self.previous_position.to_render().lerp(self.rect.position.to_render(), alpha).into()
+    }
 }
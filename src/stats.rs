@@ -0,0 +1,163 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::mem;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+fn millis(duration: Duration) -> f32 {
+    duration.as_secs() as f32 * 1000.0 + duration.subsec_nanos() as f32 / 1000000.0
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Timing {
+    // Time between the ends of consecutive frames
+    Frame,
+
+    // Simulation and input handling
+    Tick,
+
+    // Recording the frame's command buffer
+    CommandBuild,
+
+    // Cpu time spent blocked acquiring a swapchain image, submitting and
+    // flushing. This is not time the gpu spent drawing, which is not
+    // measured.
+    Blocked,
+}
+
+impl Timing {
+    pub const ALL: [Timing; 4] = [Timing::Frame, Timing::Tick, Timing::CommandBuild, Timing::Blocked];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Timing::Frame => "frame",
+            Timing::Tick => "tick",
+            Timing::CommandBuild => "command_build",
+            Timing::Blocked => "blocked",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+// Statistics over the current window, in milliseconds
+#[derive(Copy, Clone, Debug)]
+pub struct Summary {
+    pub min: f32,
+    pub avg: f32,
+    pub max: f32,
+    pub p99: f32,
+}
+
+// Collects timings over a rolling window of frames. Timings recorded during
+// a frame are summed and become one sample when the frame ends.
+pub struct FrameStats {
+    window: usize,
+    samples: Vec<VecDeque<f32>>,
+    current: [f32; 4],
+
+    last_frame: Option<Instant>,
+    frames: u64,
+
+    // One row per frame for offline analysis
+    csv: Option<BufWriter<File>>,
+}
+
+impl FrameStats {
+    pub fn new(window: usize) -> FrameStats {
+        FrameStats {
+            window: window,
+            samples: Timing::ALL.iter().map(|_| VecDeque::with_capacity(window)).collect(),
+            current: [0.0; 4],
+
+            last_frame: None,
+            frames: 0,
+
+            csv: None,
+        }
+    }
+
+    // Write every following frame's timings to a csv file
+    pub fn write_csv<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let mut csv = BufWriter::new(File::create(path)?);
+
+        let header = Timing::ALL.iter().map(|timing| format!("{}_ms", timing.name())).collect::<Vec<_>>();
+        writeln!(csv, "frame,{}", header.join(","))?;
+
+        self.csv = Some(csv);
+        Ok(())
+    }
+
+    pub fn record(&mut self, timing: Timing, duration: Duration) {
+        self.current[timing.index()] += millis(duration);
+    }
+
+    // Close the current frame. A failed csv write stops further writes so
+    // the error is only reported once.
+    pub fn end_frame(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        let last = mem::replace(&mut self.last_frame, Some(now));
+
+        // The first frame has no start to measure from, so is not counted
+        match last {
+            Some(last) => self.current[Timing::Frame.index()] = millis(now - last),
+            None => {
+                self.current = [0.0; 4];
+                return Ok(());
+            }
+        }
+
+        self.frames += 1;
+
+        for (samples, &value) in self.samples.iter_mut().zip(self.current.iter()) {
+            if samples.len() == self.window {
+                samples.pop_front();
+            }
+
+            samples.push_back(value);
+        }
+
+        let result = match self.csv {
+            Some(ref mut csv) => {
+                let row = self.current.iter().map(|value| format!("{:.3}", value)).collect::<Vec<_>>();
+                writeln!(csv, "{},{}", self.frames, row.join(","))
+            },
+
+            None => Ok(())
+        };
+
+        if result.is_err() {
+            self.csv = None;
+        }
+
+        self.current = [0.0; 4];
+        result
+    }
+
+    // Samples in the window, oldest first
+    pub fn history<'a>(&'a self, timing: Timing) -> impl Iterator<Item = f32> + 'a {
+        self.samples[timing.index()].iter().cloned()
+    }
+
+    pub fn summary(&self, timing: Timing) -> Option<Summary> {
+        let samples = &self.samples[timing.index()];
+        if samples.is_empty() {
+            return None;
+        }
+
+        let mut sorted = samples.iter().cloned().collect::<Vec<_>>();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let p99 = ((sorted.len() as f32 * 0.99).ceil() as usize).max(1) - 1;
+
+        Some(Summary {
+            min: sorted[0],
+            avg: sorted.iter().sum::<f32>() / sorted.len() as f32,
+            max: sorted[sorted.len() - 1],
+            p99: sorted[p99],
+        })
+    }
+}
@@ -0,0 +1,348 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+use ty::WorldCoords;
+
+use super::{area, cross, within};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operation {
+    Union,
+    Subtract,
+}
+
+// Combine solid regions by splitting every edge where the two outlines meet,
+// keeping the pieces that border the result and chaining them back up into
+// loops. Region loops must keep their solid side on the left, as polygons do.
+// The shape must be a simple outline but may wind either way.
+pub fn combine(region: &[Vec<WorldCoords>], shape: &[WorldCoords], operation: Operation) -> Vec<Vec<WorldCoords>> {
+    let mut shape = shape.to_vec();
+    if area(&shape) < 0 {
+        shape.reverse();
+    }
+
+    let region_edges: Vec<(WorldCoords, WorldCoords)> = region.iter().flat_map(|outline| edges(outline)).collect();
+    let shape_edges = edges(&shape);
+
+    let mut region_splits = vec![Vec::new(); region_edges.len()];
+    let mut shape_splits = vec![Vec::new(); shape_edges.len()];
+
+    for (i, &(a, b)) in region_edges.iter().enumerate() {
+        for (j, &(c, d)) in shape_edges.iter().enumerate() {
+            let (d1, d2) = (cross(c, d, a), cross(c, d, b));
+            let (d3, d4) = (cross(a, b, c), cross(a, b, d));
+
+            // Both edges get the same rounded point, so the pieces still
+            // join up afterwards
+            if d1.signum() * d2.signum() < 0 && d3.signum() * d4.signum() < 0 {
+                let t = d1 as f64 / (d1 - d2) as f64;
+                let point = WorldCoords(
+                    (a.0 as f64 + t * (b.0 - a.0) as f64).round() as i32,
+                    (a.1 as f64 + t * (b.1 - a.1) as f64).round() as i32,
+                );
+
+                region_splits[i].push((t, point));
+                shape_splits[j].push((d3 as f64 / (d3 - d4) as f64, point));
+                continue;
+            }
+
+            // Touching or overlapping, split at whichever ends lie on the
+            // other edge
+            if d3 == 0 && within(a, b, c) { region_splits[i].push((along(a, b, c), c)); }
+            if d4 == 0 && within(a, b, d) { region_splits[i].push((along(a, b, d), d)); }
+            if d1 == 0 && within(c, d, a) { shape_splits[j].push((along(c, d, a), a)); }
+            if d2 == 0 && within(c, d, b) { shape_splits[j].push((along(c, d, b), b)); }
+        }
+    }
+
+    let region_pieces = split(&region_edges, region_splits);
+    let shape_pieces = split(&shape_edges, shape_splits);
+
+    let shape_set: HashSet<((i32, i32), (i32, i32))> = shape_pieces.iter().map(|piece| key(piece.start, piece.end)).collect();
+    let region_set: HashSet<((i32, i32), (i32, i32))> = region_pieces.iter().map(|piece| key(piece.start, piece.end)).collect();
+
+    let shape_loops = [shape];
+    let mut kept = Vec::new();
+
+    // Pieces along both outlines are kept from the region alone: once if
+    // both have solid on the same side for a union, and if they face each
+    // other when subtracting.
+    for piece in &region_pieces {
+        let keep = if shape_set.contains(&key(piece.start, piece.end)) {
+            operation == Operation::Union
+        } else if shape_set.contains(&key(piece.end, piece.start)) {
+            operation == Operation::Subtract
+        } else {
+            !inside(&shape_loops, piece.middle)
+        };
+
+        if keep {
+            kept.push((piece.start, piece.end));
+        }
+    }
+
+    for piece in &shape_pieces {
+        if region_set.contains(&key(piece.start, piece.end)) || region_set.contains(&key(piece.end, piece.start)) {
+            continue;
+        }
+
+        match operation {
+            Operation::Union => if !inside(region, piece.middle) { kept.push((piece.start, piece.end)); },
+
+            // Carved edges face the other way, with the solid left behind on
+            // their left
+            Operation::Subtract => if inside(region, piece.middle) { kept.push((piece.end, piece.start)); },
+        }
+    }
+
+    chain(kept)
+}
+
+fn edges(outline: &[WorldCoords]) -> Vec<(WorldCoords, WorldCoords)> {
+    (0 .. outline.len()).map(|i| (outline[i], outline[(i + 1) % outline.len()])).collect()
+}
+
+fn key(a: WorldCoords, b: WorldCoords) -> ((i32, i32), (i32, i32)) {
+    ((a.0, a.1), (b.0, b.1))
+}
+
+// How far along ab a point on it is, 0 to 1
+fn along(a: WorldCoords, b: WorldCoords, p: WorldCoords) -> f64 {
+    let (dx, dy) = (b.0 as f64 - a.0 as f64, b.1 as f64 - a.1 as f64);
+    ((p.0 as f64 - a.0 as f64) * dx + (p.1 as f64 - a.1 as f64) * dy) / (dx * dx + dy * dy)
+}
+
+// Part of an edge between two splits
+struct Piece {
+    start: WorldCoords,
+    end: WorldCoords,
+
+    // Taken from the edge before rounding, so that a piece between two
+    // rounded points is still judged by where it really lies
+    middle: (f64, f64),
+}
+
+// Cut each edge at its split points, dropping any zero length pieces
+fn split(edges: &[(WorldCoords, WorldCoords)], splits: Vec<Vec<(f64, WorldCoords)>>) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+
+    for (&(a, b), mut points) in edges.iter().zip(splits.into_iter()) {
+        points.sort_by(|x, y| x.0.partial_cmp(&y.0).unwrap_or(Ordering::Equal));
+
+        let (mut start, mut from) = (a, 0.0);
+        for (to, point) in points.into_iter().chain(Some((1.0, b))) {
+            if point == start {
+                continue;
+            }
+
+            let t = (from + to) / 2.0;
+            pieces.push(Piece {
+                start: start,
+                end: point,
+                middle: (a.0 as f64 + t * (b.0 as f64 - a.0 as f64), a.1 as f64 + t * (b.1 as f64 - a.1 as f64)),
+            });
+
+            start = point;
+            from = to;
+        }
+    }
+
+    pieces
+}
+
+// Whether a point is inside the loops, even-odd
+fn inside(loops: &[Vec<WorldCoords>], (x, y): (f64, f64)) -> bool {
+    let mut inside = false;
+
+    for outline in loops {
+        for (c, d) in edges(outline) {
+            let (cx, cy, dx, dy) = (c.0 as f64, c.1 as f64, d.0 as f64, d.1 as f64);
+
+            if (cy > y) != (dy > y) && x < cx + (y - cy) * (dx - cx) / (dy - cy) {
+                inside = !inside;
+            }
+        }
+    }
+
+    inside
+}
+
+// Join edges end to start into closed loops. Edges that do not close up
+// are dropped, as are loops left without area once collinear points are
+// removed.
+pub fn chain(edges: Vec<(WorldCoords, WorldCoords)>) -> Vec<Vec<WorldCoords>> {
+    let mut starting: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+    for (i, &(a, _)) in edges.iter().enumerate() {
+        starting.entry((a.0, a.1)).or_insert_with(Vec::new).push(i);
+    }
+
+    let mut used = vec![false; edges.len()];
+    let mut loops = Vec::new();
+
+    for first in 0 .. edges.len() {
+        if used[first] {
+            continue;
+        }
+
+        let mut outline = Vec::new();
+        let mut edge = first;
+        let mut closed = false;
+
+        loop {
+            used[edge] = true;
+            outline.push(edges[edge].0);
+
+            let end = edges[edge].1;
+            if end == edges[first].0 {
+                closed = true;
+                break;
+            }
+
+            let next = starting.get(&(end.0, end.1))
+                .and_then(|candidates| candidates.iter().cloned().find(|&candidate| !used[candidate]));
+
+            match next {
+                Some(next) => edge = next,
+                None => break
+            }
+        }
+
+        if closed {
+            let outline = simplify(outline);
+
+            if outline.len() >= 3 && area(&outline) != 0 {
+                loops.push(outline);
+            }
+        }
+    }
+
+    loops
+}
+
+// Drop vertices in the middle of a straight line, left over from splits
+fn simplify(mut outline: Vec<WorldCoords>) -> Vec<WorldCoords> {
+    let mut i = 0;
+
+    while i < outline.len() && outline.len() >= 3 {
+        let len = outline.len();
+        let (prev, next) = (outline[(i + len - 1) % len], outline[(i + 1) % len]);
+
+        if cross(prev, outline[i], next) == 0 {
+            outline.remove(i);
+            i = i.saturating_sub(1);
+        } else {
+            i += 1;
+        }
+    }
+
+    outline
+}
+
+#[cfg(test)]
+mod tests {
+    use ty::WorldCoords;
+
+    use super::*;
+
+    fn square(x: i32, y: i32, size: i32) -> Vec<WorldCoords> {
+        vec![WorldCoords(x, y), WorldCoords(x + size, y), WorldCoords(x + size, y + size), WorldCoords(x, y + size)]
+    }
+
+    // Holes count against the area, as they wind the other way
+    fn total_area(loops: &[Vec<WorldCoords>]) -> i64 {
+        loops.iter().map(|outline| area(outline)).sum::<i64>() / 2
+    }
+
+    #[test]
+    fn union_of_overlapping_squares_is_one_outline() {
+        let loops = combine(&[square(0, 0, 20)], &square(10, 10, 20), Operation::Union);
+
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].len(), 8);
+        assert_eq!(total_area(&loops), 400 + 400 - 100);
+    }
+
+    #[test]
+    fn subtracting_a_corner_leaves_the_rest() {
+        let loops = combine(&[square(0, 0, 20)], &square(10, 10, 20), Operation::Subtract);
+
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].len(), 6);
+        assert_eq!(total_area(&loops), 400 - 100);
+    }
+
+    #[test]
+    fn shapes_wind_either_way() {
+        let mut reversed = square(10, 10, 20);
+        reversed.reverse();
+
+        for &operation in &[Operation::Union, Operation::Subtract] {
+            assert_eq!(total_area(&combine(&[square(0, 0, 20)], &reversed, operation)), total_area(&combine(&[square(0, 0, 20)], &square(10, 10, 20), operation)));
+        }
+    }
+
+    #[test]
+    fn subtracting_inside_cuts_a_hole() {
+        let loops = combine(&[square(0, 0, 30)], &square(10, 10, 10), Operation::Subtract);
+
+        assert_eq!(loops.len(), 2);
+        assert_eq!(total_area(&loops), 900 - 100);
+
+        // Solid stays on the left, so the hole winds clockwise
+        let hole = loops.iter().find(|outline| outline.contains(&WorldCoords(10, 10))).unwrap();
+        assert!(area(hole) < 0);
+    }
+
+    #[test]
+    fn union_fills_a_hole() {
+        let mut hole = square(10, 10, 10);
+        hole.reverse();
+
+        let loops = combine(&[square(0, 0, 30), hole], &square(5, 5, 20), Operation::Union);
+
+        assert_eq!(loops.len(), 1);
+        assert_eq!(total_area(&loops), 900);
+    }
+
+    #[test]
+    fn subtracting_across_a_hole_opens_it_up() {
+        let mut hole = square(10, 10, 10);
+        hole.reverse();
+
+        // A cut from the hole out through the bottom edge
+        let loops = combine(&[square(0, 0, 30), hole], &[WorldCoords(14, 15), WorldCoords(16, 15), WorldCoords(16, 40), WorldCoords(14, 40)], Operation::Subtract);
+
+        assert_eq!(loops.len(), 1);
+        assert!(area(&loops[0]) > 0);
+        assert_eq!(total_area(&loops), 900 - 100 - 2 * 10);
+    }
+
+    #[test]
+    fn disjoint_shapes_are_added_alongside() {
+        let loops = combine(&[square(0, 0, 20)], &square(50, 0, 10), Operation::Union);
+
+        assert_eq!(loops.len(), 2);
+        assert_eq!(total_area(&loops), 400 + 100);
+    }
+
+    #[test]
+    fn disjoint_shapes_subtract_nothing() {
+        let loops = combine(&[square(0, 0, 20)], &square(50, 0, 10), Operation::Subtract);
+
+        assert_eq!(loops, vec![square(0, 0, 20)]);
+    }
+
+    #[test]
+    fn squares_sharing_an_edge_merge() {
+        let loops = combine(&[square(0, 0, 20)], &square(20, 0, 20), Operation::Union);
+
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].len(), 4);
+        assert_eq!(total_area(&loops), 800);
+    }
+
+    #[test]
+    fn nothing_is_left_of_a_covered_region() {
+        assert!(combine(&[square(10, 10, 10)], &square(0, 0, 30), Operation::Subtract).is_empty());
+    }
+}
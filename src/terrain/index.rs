@@ -0,0 +1,609 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::f32;
+
+use math::Vec2;
+
+use super::TerrainMesh;
+
+// Side of a grid cell in world units, a few surface edges across
+pub const DEFAULT_CELL_SIZE: f32 = 64.0;
+
+// One edge of the terrain surface
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct IndexedEdge {
+    pub start: Vec2,
+    pub end: Vec2,
+
+    // Unit length, pointing out of the terrain
+    pub normal: Vec2,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct EdgeHit {
+    pub edge: IndexedEdge,
+    pub point: Vec2,
+
+    // From the ray's origin
+    pub distance: f32,
+}
+
+// Uniform grid over the surface edges and the filled triangles of a terrain
+// mesh. Updating reads every edge and triangle of the new mesh and matches
+// them against what is indexed, so it always costs a pass over the whole
+// terrain. Only the grid cells of what was added or removed are touched,
+// which saves clearing and filling the rest of the grid again.
+pub struct TerrainIndex {
+    cell_size: f32,
+    edges: Layer<IndexedEdge>,
+    triangles: Layer<[Vec2; 3]>,
+    revision: Option<usize>,
+}
+
+impl TerrainIndex {
+    pub fn new(terrain: &TerrainMesh) -> TerrainIndex {
+        TerrainIndex::with_cell_size(terrain, DEFAULT_CELL_SIZE)
+    }
+
+    pub fn with_cell_size(terrain: &TerrainMesh, cell_size: f32) -> TerrainIndex {
+        let mut index = TerrainIndex {
+            cell_size: cell_size,
+            edges: Layer::new(),
+            triangles: Layer::new(),
+            revision: None,
+        };
+
+        index.update(terrain);
+        index
+    }
+
+    // Bring the index in line with the terrain, returning whether anything
+    // changed. Does nothing if it has already seen this revision.
+    pub fn update(&mut self, terrain: &TerrainMesh) -> bool {
+        if self.revision == Some(terrain.revision()) {
+            return false;
+        }
+
+        let edges = terrain.surface_chains().iter()
+            .flat_map(|chain| (0 .. chain.edge_count()).map(move |edge| {
+                let (start, end) = chain.edge(edge);
+
+                IndexedEdge {
+                    start: start,
+                    end: end,
+                    normal: chain.edge_normal(edge),
+                }
+            }))
+            .collect();
+
+        let triangles: Vec<[Vec2; 3]> = terrain.triangles().into_iter()
+            .map(|corners| [corners[0].to_render(), corners[1].to_render(), corners[2].to_render()])
+            .collect();
+
+        let changed_edges = self.edges.sync(edges, self.cell_size);
+        let changed_triangles = self.triangles.sync(triangles, self.cell_size);
+
+        self.revision = Some(terrain.revision());
+
+        changed_edges || changed_triangles
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.edges.len()
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    // Edges that cross or touch the box
+    pub fn edges_in(&self, min: Vec2, max: Vec2) -> Vec<IndexedEdge> {
+        self.edges.query(min, max, self.cell_size)
+    }
+
+    // Whether a point is inside the filled terrain, edges included
+    pub fn contains(&self, point: Vec2) -> bool {
+        self.triangles.query(point, point, self.cell_size).iter()
+            .any(|triangle| in_triangle(triangle, point))
+    }
+
+    // The first surface edge along a ray, from either side, within a distance
+    // of its origin. Walks the grid cell by cell from the origin.
+    pub fn raycast(&self, origin: Vec2, direction: Vec2, max_distance: f32) -> Option<EdgeHit> {
+        let length = direction.length();
+        let (min_cell, max_cell) = match self.edges.extent {
+            Some(extent) if length > 0.0 => extent,
+            _ => return None
+        };
+
+        let direction = direction / length;
+
+        // Only the part of the ray over the grid needs walking
+        let size = self.cell_size;
+        let low = Vec2(min_cell.0 as f32, min_cell.1 as f32) * size;
+        let high = Vec2((max_cell.0 + 1) as f32, (max_cell.1 + 1) as f32) * size;
+
+        let (enter, exit) = match clip(origin, direction, low, high) {
+            Some((enter, exit)) => (enter.max(0.0), exit.min(max_distance)),
+            None => return None
+        };
+
+        if enter > exit {
+            return None;
+        }
+
+        let start = origin + direction * enter;
+        let mut cell = [
+            clamp((start.0 / size).floor() as i32, min_cell.0, max_cell.0),
+            clamp((start.1 / size).floor() as i32, min_cell.1, max_cell.1),
+        ];
+
+        // Distance along the ray to the next cell boundary on each axis, and
+        // between boundaries
+        let mut next = [0.0; 2];
+        let mut delta = [0.0; 2];
+        let mut step = [0; 2];
+
+        for axis in 0 .. 2 {
+            if direction[axis] > 0.0 {
+                step[axis] = 1;
+                delta[axis] = size / direction[axis];
+                next[axis] = ((cell[axis] + 1) as f32 * size - origin[axis]) / direction[axis];
+            } else if direction[axis] < 0.0 {
+                step[axis] = -1;
+                delta[axis] = -size / direction[axis];
+                next[axis] = (cell[axis] as f32 * size - origin[axis]) / direction[axis];
+            } else {
+                delta[axis] = f32::INFINITY;
+                next[axis] = f32::INFINITY;
+            }
+        }
+
+        let mut best: Option<EdgeHit> = None;
+
+        loop {
+            if let Some(slots) = self.edges.cells.get(&(cell[0], cell[1])) {
+                for &slot in slots {
+                    let edge = self.edges.items[slot].unwrap();
+
+                    if let Some(distance) = ray_edge(origin, direction, &edge) {
+                        if distance <= max_distance && best.map_or(true, |best| distance < best.distance) {
+                            best = Some(EdgeHit {
+                                edge: edge,
+                                point: origin + direction * distance,
+                                distance: distance,
+                            });
+                        }
+                    }
+                }
+            }
+
+            // A hit in this cell can not be beaten by anything further on
+            let leave = next[0].min(next[1]);
+            if best.map_or(false, |best| best.distance <= leave) || leave > exit {
+                return best;
+            }
+
+            let axis = if next[0] < next[1] { 0 } else { 1 };
+            cell[axis] += step[axis];
+            next[axis] += delta[axis];
+        }
+    }
+}
+
+// Distance along a ray with a unit direction to where it crosses an edge
+pub fn ray_edge(origin: Vec2, direction: Vec2, edge: &IndexedEdge) -> Option<f32> {
+    let along = edge.end - edge.start;
+    let denominator = direction.cross(along);
+
+    if denominator.abs() < 1e-9 {
+        return None;
+    }
+
+    let to_start = edge.start - origin;
+    let distance = to_start.cross(along) / denominator;
+    let t = to_start.cross(direction) / denominator;
+
+    if distance >= 0.0 && t >= 0.0 && t <= 1.0 {
+        Some(distance)
+    } else {
+        None
+    }
+}
+
+// Something that can be bucketed by the cells it covers
+trait Item: Copy {
+    // Same for items that are the same, so updates can keep them in place
+    fn key(&self) -> [i32; 6];
+
+    fn bounds(&self) -> (Vec2, Vec2);
+
+    // Whether the item reaches into a box its bounds overlap
+    fn touches(&self, min: Vec2, max: Vec2) -> bool;
+}
+
+impl Item for IndexedEdge {
+    fn key(&self) -> [i32; 6] {
+        // The normal only matters for which way it faces
+        [
+            self.start.0 as i32, self.start.1 as i32,
+            self.end.0 as i32, self.end.1 as i32,
+            (self.normal.0 * 1024.0) as i32, (self.normal.1 * 1024.0) as i32,
+        ]
+    }
+
+    fn bounds(&self) -> (Vec2, Vec2) {
+        (self.start.min(self.end), self.start.max(self.end))
+    }
+
+    // Misses if every corner is on the same side of the line
+    fn touches(&self, min: Vec2, max: Vec2) -> bool {
+        let along = self.end - self.start;
+        let side = |x: f32, y: f32| along.cross(Vec2(x, y) - self.start);
+
+        let corners = [side(min.0, min.1), side(max.0, min.1), side(min.0, max.1), side(max.0, max.1)];
+        !(corners.iter().all(|&c| c > 0.0) || corners.iter().all(|&c| c < 0.0))
+    }
+}
+
+impl Item for [Vec2; 3] {
+    fn key(&self) -> [i32; 6] {
+        [
+            self[0].0 as i32, self[0].1 as i32,
+            self[1].0 as i32, self[1].1 as i32,
+            self[2].0 as i32, self[2].1 as i32,
+        ]
+    }
+
+    fn bounds(&self) -> (Vec2, Vec2) {
+        (self[0].min(self[1]).min(self[2]), self[0].max(self[1]).max(self[2]))
+    }
+
+    // Cells only partly covered by the bounds are kept, which costs a few
+    // extra tests but nothing is missed
+    fn touches(&self, _min: Vec2, _max: Vec2) -> bool {
+        true
+    }
+}
+
+// Items of one kind and the cells each one is listed in. Removed items
+// leave a free slot behind for the next one added.
+struct Layer<T> {
+    items: Vec<Option<T>>,
+    free: Vec<usize>,
+    slots: HashMap<[i32; 6], Vec<usize>>,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+
+    // Range of cells anything has been put in. Only ever grows.
+    extent: Option<((i32, i32), (i32, i32))>,
+}
+
+impl<T: Item> Layer<T> {
+    fn new() -> Layer<T> {
+        Layer {
+            items: Vec::new(),
+            free: Vec::new(),
+            slots: HashMap::new(),
+            cells: HashMap::new(),
+            extent: None,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.items.len() - self.free.len()
+    }
+
+    // Keep whatever is already here, remove what is not wanted any more and
+    // add the rest. Returns whether anything was added or removed.
+    fn sync(&mut self, wanted: Vec<T>, cell_size: f32) -> bool {
+        let mut kept = vec![false; self.items.len()];
+        let mut added = Vec::new();
+
+        for item in wanted {
+            let existing = self.slots.get(&item.key())
+                .and_then(|slots| slots.iter().cloned().find(|&slot| !kept[slot]));
+
+            match existing {
+                Some(slot) => kept[slot] = true,
+                None => added.push(item)
+            }
+        }
+
+        let removed: Vec<usize> = (0 .. kept.len())
+            .filter(|&slot| !kept[slot] && self.items[slot].is_some())
+            .collect();
+
+        let changed = !removed.is_empty() || !added.is_empty();
+
+        for slot in removed {
+            self.remove(slot, cell_size);
+        }
+
+        for item in added {
+            self.insert(item, cell_size);
+        }
+
+        changed
+    }
+
+    fn insert(&mut self, item: T, cell_size: f32) {
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.items[slot] = Some(item);
+                slot
+            },
+
+            None => {
+                self.items.push(Some(item));
+                self.items.len() - 1
+            }
+        };
+
+        self.slots.entry(item.key()).or_insert_with(Vec::new).push(slot);
+
+        let (low, high) = cell_range(item.bounds(), cell_size);
+        for x in low.0 .. high.0 + 1 {
+            for y in low.1 .. high.1 + 1 {
+                let min = Vec2(x as f32, y as f32) * cell_size;
+                let max = min + Vec2(cell_size, cell_size);
+
+                if item.touches(min, max) {
+                    self.cells.entry((x, y)).or_insert_with(Vec::new).push(slot);
+                }
+            }
+        }
+
+        self.extent = Some(match self.extent {
+            Some((min, max)) => ((cmp::min(min.0, low.0), cmp::min(min.1, low.1)), (cmp::max(max.0, high.0), cmp::max(max.1, high.1))),
+            None => (low, high)
+        });
+    }
+
+    fn remove(&mut self, slot: usize, cell_size: f32) {
+        let item = match self.items[slot].take() {
+            Some(item) => item,
+            None => return
+        };
+
+        self.free.push(slot);
+
+        let key = item.key();
+        let empty = match self.slots.get_mut(&key) {
+            Some(slots) => {
+                slots.retain(|&other| other != slot);
+                slots.is_empty()
+            },
+
+            None => false
+        };
+
+        if empty {
+            self.slots.remove(&key);
+        }
+
+        let (low, high) = cell_range(item.bounds(), cell_size);
+        for x in low.0 .. high.0 + 1 {
+            for y in low.1 .. high.1 + 1 {
+                let empty = match self.cells.get_mut(&(x, y)) {
+                    Some(slots) => {
+                        slots.retain(|&other| other != slot);
+                        slots.is_empty()
+                    },
+
+                    None => false
+                };
+
+                if empty {
+                    self.cells.remove(&(x, y));
+                }
+            }
+        }
+    }
+
+    // Each item listed in the cells under the box that reaches into it,
+    // once
+    fn query(&self, min: Vec2, max: Vec2, cell_size: f32) -> Vec<T> {
+        let mut slots = Vec::new();
+
+        let (low, high) = cell_range((min, max), cell_size);
+        for x in low.0 .. high.0 + 1 {
+            for y in low.1 .. high.1 + 1 {
+                if let Some(cell) = self.cells.get(&(x, y)) {
+                    slots.extend_from_slice(cell);
+                }
+            }
+        }
+
+        slots.sort();
+        slots.dedup();
+
+        slots.into_iter()
+            .filter_map(|slot| self.items[slot])
+            .filter(|item| overlaps(item, min, max))
+            .collect()
+    }
+}
+
+fn overlaps<T: Item>(item: &T, min: Vec2, max: Vec2) -> bool {
+    let (item_min, item_max) = item.bounds();
+    item_min.0 <= max.0 && min.0 <= item_max.0 && item_min.1 <= max.1 && min.1 <= item_max.1 && item.touches(min, max)
+}
+
+fn clamp(value: i32, min: i32, max: i32) -> i32 {
+    cmp::max(min, cmp::min(max, value))
+}
+
+fn cell_range((min, max): (Vec2, Vec2), cell_size: f32) -> ((i32, i32), (i32, i32)) {
+    (
+        ((min.0 / cell_size).floor() as i32, (min.1 / cell_size).floor() as i32),
+        ((max.0 / cell_size).floor() as i32, (max.1 / cell_size).floor() as i32),
+    )
+}
+
+// Either winding, edges included
+fn in_triangle(triangle: &[Vec2; 3], point: Vec2) -> bool {
+    let side = |a: Vec2, b: Vec2| (b - a).cross(point - a);
+    let sides = [side(triangle[0], triangle[1]), side(triangle[1], triangle[2]), side(triangle[2], triangle[0])];
+
+    sides.iter().all(|&s| s >= 0.0) || sides.iter().all(|&s| s <= 0.0)
+}
+
+// Distances along a ray where it enters and leaves a box, if it crosses the
+// line of the box at all
+fn clip(origin: Vec2, direction: Vec2, min: Vec2, max: Vec2) -> Option<(f32, f32)> {
+    let mut enter = f32::NEG_INFINITY;
+    let mut exit = f32::INFINITY;
+
+    for axis in 0 .. 2 {
+        if direction[axis] == 0.0 {
+            if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                return None;
+            }
+
+            continue;
+        }
+
+        let a = (min[axis] - origin[axis]) / direction[axis];
+        let b = (max[axis] - origin[axis]) / direction[axis];
+
+        enter = enter.max(a.min(b));
+        exit = exit.min(a.max(b));
+    }
+
+    if enter <= exit { Some((enter, exit)) } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use collision;
+    use math::Vec2;
+    use rng::Rng;
+    use terrain::{circle, TerrainMesh};
+    use ty::WorldCoords;
+
+    use super::*;
+
+    const CASES: usize = 500;
+
+    // Bumpy ground with a cave, spread over a few dozen cells
+    fn terrain() -> TerrainMesh {
+        let mut outline: Vec<WorldCoords> = (0 .. 60).map(|i| WorldCoords(i * 20, 300 - (i * 7) % 13 * 9 - i % 2 * 5)).collect();
+        outline.push(WorldCoords(1180, 800));
+        outline.push(WorldCoords(0, 800));
+
+        let cave = vec![WorldCoords(300, 500), WorldCoords(600, 450), WorldCoords(700, 600), WorldCoords(400, 650)];
+
+        TerrainMesh::from_outlines(vec![outline, cave]).unwrap()
+    }
+
+    fn float(rng: &mut Rng, min: f32, max: f32) -> f32 {
+        min + rng.next_f32() * (max - min)
+    }
+
+    fn point(rng: &mut Rng) -> Vec2 {
+        Vec2(float(rng, -100.0, 1300.0), float(rng, 0.0, 900.0))
+    }
+
+    fn edges(terrain: &TerrainMesh) -> Vec<IndexedEdge> {
+        collision::surface_segments(terrain).iter()
+            .map(|segment| IndexedEdge { start: segment.start, end: segment.end, normal: segment.normal })
+            .collect()
+    }
+
+    fn keys(edges: &[IndexedEdge]) -> Vec<[i32; 6]> {
+        let mut keys: Vec<[i32; 6]> = edges.iter().map(|edge| edge.key()).collect();
+        keys.sort();
+        keys
+    }
+
+    // Nearest edge crossing the ray, trying every edge
+    fn scan_raycast(edges: &[IndexedEdge], origin: Vec2, direction: Vec2, max_distance: f32) -> Option<f32> {
+        let direction = direction.normalize();
+
+        edges.iter()
+            .filter_map(|edge| ray_edge(origin, direction, edge))
+            .filter(|&distance| distance <= max_distance)
+            .fold(None, |best: Option<f32>, distance| Some(best.map_or(distance, |best| best.min(distance))))
+    }
+
+    // Compare every kind of query against a scan over the terrain
+    fn assert_matches_scan(index: &TerrainIndex, terrain: &TerrainMesh, rng: &mut Rng) {
+        let edges = edges(terrain);
+        let triangles: Vec<[Vec2; 3]> = terrain.triangles().into_iter()
+            .map(|corners| [corners[0].to_render(), corners[1].to_render(), corners[2].to_render()])
+            .collect();
+
+        assert_eq!(index.edge_count(), edges.len());
+        assert_eq!(index.triangle_count(), triangles.len());
+
+        for _ in 0 .. CASES {
+            let (a, b) = (point(rng), point(rng) * 0.2);
+            let (min, max) = (a, a + Vec2(b.0.abs(), b.1.abs()));
+
+            let scanned: Vec<IndexedEdge> = edges.iter().cloned().filter(|edge| overlaps(edge, min, max)).collect();
+            assert_eq!(keys(&index.edges_in(min, max)), keys(&scanned));
+
+            let inside = triangles.iter().any(|triangle| in_triangle(triangle, a));
+            assert_eq!(index.contains(a), inside);
+
+            let direction = point(rng) - a;
+            let max_distance = float(rng, 0.0, 1500.0);
+
+            let hit = index.raycast(a, direction, max_distance).map(|hit| hit.distance);
+            match (hit, scan_raycast(&edges, a, direction, max_distance)) {
+                (Some(found), Some(expected)) => assert!((found - expected).abs() < 1e-3),
+                (found, expected) => assert_eq!(found, expected)
+            }
+        }
+    }
+
+    #[test]
+    fn queries_match_a_scan() {
+        let terrain = terrain();
+        let index = TerrainIndex::new(&terrain);
+
+        assert_matches_scan(&index, &terrain, &mut Rng::new(23));
+    }
+
+    #[test]
+    fn queries_match_a_scan_with_small_and_large_cells() {
+        let terrain = terrain();
+        let mut rng = Rng::new(5);
+
+        for &cell_size in &[7.0, 1000.0] {
+            assert_matches_scan(&TerrainIndex::with_cell_size(&terrain, cell_size), &terrain, &mut rng);
+        }
+    }
+
+    #[test]
+    fn updates_match_a_scan() {
+        let mut terrain = terrain();
+        let mut index = TerrainIndex::new(&terrain);
+        let mut rng = Rng::new(11);
+
+        // Seen revisions are skipped
+        assert!(!index.update(&terrain));
+
+        let mut edits = 0;
+
+        for i in 0 .. 12 {
+            let centre = WorldCoords(rng.range(0, 1180), rng.range(150, 700));
+            let radius = rng.range(20, 120) as u32;
+
+            let changed = if i % 3 == 2 {
+                terrain.union(&circle(centre, radius))
+            } else {
+                terrain.subtract(&circle(centre, radius))
+            };
+
+            if changed {
+                edits += 1;
+            }
+
+            assert_eq!(index.update(&terrain), changed);
+            assert_matches_scan(&index, &terrain, &mut rng);
+        }
+
+        assert!(edits > 6);
+    }
+}
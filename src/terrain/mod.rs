@@ -0,0 +1,1120 @@
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::error::Error;
+use std::fmt;
+use std::iter::once;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use math::Vec2;
+use ty::WorldCoords;
+
+use self::boolean::{chain, combine, Operation};
+use self::triangulate::triangulate;
+
+pub use self::index::{ray_edge, EdgeHit, IndexedEdge, TerrainIndex};
+pub use self::surface::{SurfaceChain, SurfacePoint};
+
+mod boolean;
+mod index;
+mod surface;
+mod triangulate;
+
+static NEXT_REVISION: AtomicUsize = ATOMIC_USIZE_INIT;
+
+fn next_revision() -> usize {
+    NEXT_REVISION.fetch_add(1, Ordering::Relaxed)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TerrainVertex {
+    Surface(WorldCoords),
+    Inner(WorldCoords)
+}
+
+impl TerrainVertex {
+    pub fn coords(&self) -> WorldCoords {
+        match *self {
+            TerrainVertex::Surface(coord) | TerrainVertex::Inner(coord) => coord
+        }
+    }
+}
+
+// Something structurally wrong with a mesh. Indices are into the vertex list.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MeshProblem {
+    Empty,
+
+    // Vertex 0 is always the first fan centre
+    SurfaceCentre { index: usize },
+
+    // An inner vertex needs a surface vertex before it to continue the
+    // surface from
+    InnerBeforeSurface { index: usize },
+
+    DuplicateVertex { first: usize, duplicate: usize },
+
+    // Fan centre followed by two surface vertices
+    DegenerateTriangle { vertices: [usize; 3] },
+
+    // Two surface edges, each given by its end vertices
+    SelfIntersection { first: (usize, usize), second: (usize, usize) },
+
+    // An outline of fewer than three vertices, or with no area
+    DegenerateOutline { first: usize, count: usize },
+}
+
+impl MeshProblem {
+    // Every vertex involved, the offending one first
+    pub fn vertices(&self) -> Vec<usize> {
+        match *self {
+            MeshProblem::Empty => Vec::new(),
+            MeshProblem::SurfaceCentre { index } | MeshProblem::InnerBeforeSurface { index } => vec![index],
+            MeshProblem::DuplicateVertex { first, duplicate } => vec![duplicate, first],
+            MeshProblem::DegenerateTriangle { vertices } => vertices.to_vec(),
+            MeshProblem::SelfIntersection { first, second } => vec![first.0, first.1, second.0, second.1],
+            MeshProblem::DegenerateOutline { first, count } => (first .. first + count).collect(),
+        }
+    }
+}
+
+impl fmt::Display for MeshProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MeshProblem::Empty => write!(f, "mesh has no vertices"),
+            MeshProblem::SurfaceCentre { index } => write!(f, "vertex {} is the first fan centre and must be Inner", index),
+            MeshProblem::InnerBeforeSurface { index } => write!(f, "vertex {} is Inner but no Surface vertex comes before it", index),
+            MeshProblem::DuplicateVertex { first, duplicate } => write!(f, "vertex {} is at the same position as vertex {}", duplicate, first),
+            MeshProblem::DegenerateTriangle { vertices } => write!(f, "triangle of vertices {}, {} and {} has no area", vertices[0], vertices[1], vertices[2]),
+            MeshProblem::SelfIntersection { first, second } => write!(f, "surface edge {}-{} crosses edge {}-{}", first.0, first.1, second.0, second.1),
+            MeshProblem::DegenerateOutline { first, count } => write!(f, "outline of {} vertices starting at vertex {} has no area", count, first),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MeshError(pub Vec<MeshProblem>);
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid terrain mesh")?;
+
+        for problem in &self.0 {
+            write!(f, "\n  {}", problem)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for MeshError {
+    fn description(&self) -> &str {
+        "invalid terrain mesh"
+    }
+}
+
+// Twice the signed area of the triangle abc
+fn cross(a: WorldCoords, b: WorldCoords, c: WorldCoords) -> i64 {
+    (b.0 as i64 - a.0 as i64) * (c.1 as i64 - a.1 as i64) - (b.1 as i64 - a.1 as i64) * (c.0 as i64 - a.0 as i64)
+}
+
+// Whether c lies within the bounding box of ab, for collinear points
+fn within(a: WorldCoords, b: WorldCoords, c: WorldCoords) -> bool {
+    c.0 >= a.0.min(b.0) && c.0 <= a.0.max(b.0) && c.1 >= a.1.min(b.1) && c.1 <= a.1.max(b.1)
+}
+
+// Whether segments ab and cd touch at all, including overlapping
+fn segments_intersect(a: WorldCoords, b: WorldCoords, c: WorldCoords, d: WorldCoords) -> bool {
+    let (d1, d2) = (cross(c, d, a), cross(c, d, b));
+    let (d3, d4) = (cross(a, b, c), cross(a, b, d));
+
+    if d1.signum() * d2.signum() < 0 && d3.signum() * d4.signum() < 0 {
+        return true;
+    }
+
+    (d1 == 0 && within(c, d, a)) || (d2 == 0 && within(c, d, b))
+        || (d3 == 0 && within(a, b, c)) || (d4 == 0 && within(a, b, d))
+}
+
+// Twice the signed area, positive for counter-clockwise outlines
+fn area(outline: &[WorldCoords]) -> i64 {
+    (0 .. outline.len()).map(|i| {
+        let (a, b) = (outline[i], outline[(i + 1) % outline.len()]);
+        a.0 as i64 * b.1 as i64 - b.0 as i64 * a.1 as i64
+    }).sum()
+}
+
+// Even-odd test, points on the outline may go either way
+fn contains(outline: &[WorldCoords], point: WorldCoords) -> bool {
+    let mut inside = false;
+
+    for i in 0 .. outline.len() {
+        let (a, b) = (outline[i], outline[(i + 1) % outline.len()]);
+
+        if (a.1 > point.1) != (b.1 > point.1) {
+            let side = cross(a, b, point);
+
+            if (b.1 > a.1 && side > 0) || (b.1 < a.1 && side < 0) {
+                inside = !inside;
+            }
+        }
+    }
+
+    inside
+}
+
+// Everything wrong with a set of closed outlines. Indices count through the
+// outlines in order, as if they were one list.
+pub fn outline_problems(outlines: &[Vec<WorldCoords>]) -> Vec<MeshProblem> {
+    let mut problems = Vec::new();
+
+    if outlines.is_empty() {
+        return vec![MeshProblem::Empty];
+    }
+
+    let mut seen = HashMap::new();
+    let mut edges = Vec::new();
+    let mut points = Vec::new();
+
+    for outline in outlines {
+        let first = points.len();
+
+        if outline.len() < 3 || area(outline) == 0 {
+            problems.push(MeshProblem::DegenerateOutline { first: first, count: outline.len() });
+        }
+
+        for (i, &coords) in outline.iter().enumerate() {
+            let index = first + i;
+
+            match seen.entry((coords.0, coords.1)) {
+                Entry::Occupied(entry) => problems.push(MeshProblem::DuplicateVertex { first: *entry.get(), duplicate: index }),
+                Entry::Vacant(entry) => { entry.insert(index); }
+            }
+
+            edges.push((index, first + (i + 1) % outline.len()));
+            points.push(coords);
+        }
+    }
+
+    for (i, &(a, b)) in edges.iter().enumerate() {
+        for &(c, d) in &edges[i + 1 ..] {
+            if a == c || a == d || b == c || b == d {
+                continue;
+            }
+
+            if segments_intersect(points[a], points[b], points[c], points[d]) {
+                problems.push(MeshProblem::SelfIntersection { first: (a, b), second: (c, d) });
+            }
+        }
+    }
+
+    problems
+}
+
+// A solid piece of terrain built from outlines, along with its triangles.
+// The outline runs counter-clockwise and holes clockwise, so the solid side
+// is always left of a surface edge.
+#[derive(Clone, Debug)]
+pub struct Polygon {
+    outline: Vec<WorldCoords>,
+    holes: Vec<Vec<WorldCoords>>,
+
+    // Indices into the outline followed by each hole
+    triangles: Vec<[u32; 3]>,
+}
+
+impl Polygon {
+    // Windings are not checked, see sort_outlines
+    pub fn new(outline: Vec<WorldCoords>, holes: Vec<Vec<WorldCoords>>) -> Polygon {
+        let triangles = triangulate(&outline, &holes);
+
+        Polygon {
+            outline: outline,
+            holes: holes,
+            triangles: triangles,
+        }
+    }
+
+    pub fn outline(&self) -> &[WorldCoords] {
+        &self.outline
+    }
+
+    pub fn holes(&self) -> &[Vec<WorldCoords>] {
+        &self.holes
+    }
+
+    pub fn triangles(&self) -> &[[u32; 3]] {
+        &self.triangles
+    }
+
+    // The outline then each hole
+    pub fn loops<'a>(&'a self) -> impl Iterator<Item=&'a [WorldCoords]> + 'a {
+        once(&self.outline[..]).chain(self.holes.iter().map(|hole| &hole[..]))
+    }
+
+    // Triangles found some other way, as indices like `triangles`. They are
+    // only used if they cover the polygon exactly, otherwise it is
+    // triangulated.
+    fn with_triangles(outline: Vec<WorldCoords>, holes: Vec<Vec<WorldCoords>>, triangles: Option<Vec<[u32; 3]>>) -> Polygon {
+        let covered = match triangles {
+            Some(triangles) => {
+                let points: Vec<WorldCoords> = once(&outline).chain(holes.iter()).flat_map(|outline| outline.iter().cloned()).collect();
+                let expected: i64 = area(&outline) + holes.iter().map(|hole| area(hole)).sum::<i64>();
+
+                let areas: Vec<i64> = triangles.iter()
+                    .map(|triangle| cross(points[triangle[0] as usize], points[triangle[1] as usize], points[triangle[2] as usize]))
+                    .collect();
+
+                if areas.iter().all(|&area| area > 0) && areas.iter().sum::<i64>() == expected {
+                    Some(triangles)
+                } else {
+                    None
+                }
+            },
+
+            None => None
+        };
+
+        match covered {
+            Some(triangles) => Polygon {
+                outline: outline,
+                holes: holes,
+                triangles: triangles,
+            },
+
+            None => Polygon::new(outline, holes)
+        }
+    }
+
+    // Each triangle by its corners
+    fn corners(&self) -> Vec<[WorldCoords; 3]> {
+        let points: Vec<WorldCoords> = self.loops().flat_map(|outline| outline.iter().cloned()).collect();
+
+        self.triangles.iter()
+            .map(|triangle| [points[triangle[0] as usize], points[triangle[1] as usize], points[triangle[2] as usize]])
+            .collect()
+    }
+
+    fn vertex_count(&self) -> usize {
+        self.loops().map(|outline| outline.len()).sum()
+    }
+}
+
+// Whether a triangle and a shape overlap or touch at all
+fn triangle_touches(triangle: &[WorldCoords; 3], shape: &[WorldCoords], shape_bounds: (WorldCoords, WorldCoords)) -> bool {
+    if !bounds_overlap(bounds(triangle), shape_bounds) {
+        return false;
+    }
+
+    let crossed = (0 .. 3).any(|i| (0 .. shape.len()).any(|j| {
+        segments_intersect(triangle[i], triangle[(i + 1) % 3], shape[j], shape[(j + 1) % shape.len()])
+    }));
+
+    crossed || contains(shape, triangle[0]) || contains(triangle, shape[0])
+}
+
+// Loops around a set of counter-clockwise triangles, from the edges that
+// are not shared by two of them
+fn boundary(triangles: &[[WorldCoords; 3]]) -> Vec<Vec<WorldCoords>> {
+    let edges: Vec<(WorldCoords, WorldCoords)> = triangles.iter()
+        .flat_map(|triangle| (0 .. 3).map(move |i| (triangle[i], triangle[(i + 1) % 3])))
+        .collect();
+
+    let set: HashSet<((i32, i32), (i32, i32))> = edges.iter().map(|&(a, b)| ((a.0, a.1), (b.0, b.1))).collect();
+
+    chain(edges.into_iter().filter(|&(a, b)| !set.contains(&((b.0, b.1), (a.0, a.1)))).collect())
+}
+
+// Hand triangles to the polygons that have all three of their corners, as
+// vertex indices. None if any of them has nowhere to go.
+fn share_triangles(polygons: &[(Vec<WorldCoords>, Vec<Vec<WorldCoords>>)], triangles: &[[WorldCoords; 3]]) -> Option<Vec<Vec<[u32; 3]>>> {
+    let mut vertices: HashMap<(i32, i32), Vec<(usize, u32)>> = HashMap::new();
+
+    for (i, &(ref outline, ref holes)) in polygons.iter().enumerate() {
+        let points = once(outline).chain(holes.iter()).flat_map(|outline| outline.iter());
+
+        for (index, point) in points.enumerate() {
+            vertices.entry((point.0, point.1)).or_insert_with(Vec::new).push((i, index as u32));
+        }
+    }
+
+    let find = |polygon: usize, corner: WorldCoords| {
+        vertices.get(&(corner.0, corner.1))
+            .and_then(|entries| entries.iter().find(|&&(other, _)| other == polygon))
+            .map(|&(_, index)| index)
+    };
+
+    let mut shared = vec![Vec::new(); polygons.len()];
+
+    for triangle in triangles {
+        let candidates = match vertices.get(&(triangle[0].0, triangle[0].1)) {
+            Some(candidates) => candidates,
+            None => return None
+        };
+
+        let placed = candidates.iter().filter_map(|&(polygon, _)| {
+            match (find(polygon, triangle[0]), find(polygon, triangle[1]), find(polygon, triangle[2])) {
+                (Some(a), Some(b), Some(c)) => Some((polygon, [a, b, c])),
+                _ => None
+            }
+        }).next();
+
+        match placed {
+            Some((polygon, indices)) => shared[polygon].push(indices),
+            None => return None
+        }
+    }
+
+    Some(shared)
+}
+
+// Smallest and largest corner
+fn bounds(points: &[WorldCoords]) -> (WorldCoords, WorldCoords) {
+    points.iter().fold((WorldCoords(i32::max_value(), i32::max_value()), WorldCoords(i32::min_value(), i32::min_value())), |(min, max), p| {
+        (WorldCoords(min.0.min(p.0), min.1.min(p.1)), WorldCoords(max.0.max(p.0), max.1.max(p.1)))
+    })
+}
+
+fn bounds_overlap((min_a, max_a): (WorldCoords, WorldCoords), (min_b, max_b): (WorldCoords, WorldCoords)) -> bool {
+    min_a.0 <= max_b.0 && min_b.0 <= max_a.0 && min_a.1 <= max_b.1 && min_b.1 <= max_a.1
+}
+
+// Outline approximating a circle, with more vertices for bigger circles
+pub fn circle(centre: WorldCoords, radius: u32) -> Vec<WorldCoords> {
+    let radius = radius as f64;
+    let count = ((radius * 2.0 * ::std::f64::consts::PI / 16.0).ceil() as usize).max(8).min(64);
+
+    (0 .. count).map(|i| {
+        let angle = i as f64 / count as f64 * 2.0 * ::std::f64::consts::PI;
+        WorldCoords(centre.0 + (radius * angle.cos()).round() as i32, centre.1 + (radius * angle.sin()).round() as i32)
+    }).collect()
+}
+
+// Sort outlines into polygons and holes by how deeply each is nested, and
+// wind them to match. Unchecked, see outline_problems.
+pub fn sort_outlines(outlines: Vec<Vec<WorldCoords>>) -> Vec<Polygon> {
+    group_outlines(outlines).into_iter().map(|(outline, holes)| Polygon::new(outline, holes)).collect()
+}
+
+// Each outline with its holes, not yet triangulated
+fn group_outlines(outlines: Vec<Vec<WorldCoords>>) -> Vec<(Vec<WorldCoords>, Vec<Vec<WorldCoords>>)> {
+    let depths: Vec<usize> = outlines.iter().enumerate().map(|(i, outline)| {
+        outlines.iter().enumerate()
+            .filter(|&(j, other)| i != j && contains(other, outline[0]))
+            .count()
+    }).collect();
+
+    let mut outers = Vec::new();
+    let mut holes = Vec::new();
+
+    for (i, mut outline) in outlines.iter().cloned().enumerate() {
+        let hole = depths[i] % 2 == 1;
+
+        if (area(&outline) > 0) == hole {
+            outline.reverse();
+        }
+
+        if hole {
+            holes.push((i, outline));
+        } else {
+            outers.push((i, outline, Vec::new()));
+        }
+    }
+
+    // A hole belongs to the outline one level out that contains it
+    for (i, hole) in holes {
+        let parent = outers.iter().position(|&(j, ref outer, _)| depths[j] + 1 == depths[i] && contains(outer, outlines[i][0]));
+
+        if let Some(parent) = parent {
+            outers[parent].2.push(hole);
+        }
+    }
+
+    outers.into_iter().map(|(_, outline, holes)| (outline, holes)).collect()
+}
+
+pub struct TerrainMesh {
+    mesh: Vec<TerrainVertex>,
+
+    // Set for meshes built from outlines, which draw as triangles rather
+    // than fans. mesh then holds each polygon's vertices in turn, all Surface.
+    polygons: Option<Vec<Polygon>>,
+
+    // Built along with the mesh, so they always match its revision
+    chains: Vec<SurfaceChain>,
+
+    // Different for every mesh and every change to one, so the renderer
+    // can tell when it needs uploading again
+    revision: usize,
+}
+
+impl TerrainMesh {
+    // Unchecked, see from_vertices
+    pub fn new(mesh: Vec<TerrainVertex>) -> TerrainMesh {
+        TerrainMesh::build(mesh, None)
+    }
+
+    fn build(mesh: Vec<TerrainVertex>, polygons: Option<Vec<Polygon>>) -> TerrainMesh {
+        let mut terrain = TerrainMesh {
+            mesh: mesh,
+            polygons: polygons,
+            chains: Vec::new(),
+            revision: next_revision(),
+        };
+
+        terrain.chains = surface::chains(terrain.surface_edges());
+        terrain
+    }
+
+    // Triangulate closed outlines. Outlines inside another cut holes into
+    // it, and outlines inside a hole are solid again. Winding does not
+    // matter.
+    pub fn from_outlines(outlines: Vec<Vec<WorldCoords>>) -> Result<TerrainMesh, MeshError> {
+        let problems = outline_problems(&outlines);
+
+        if problems.is_empty() {
+            Ok(TerrainMesh::from_polygons(sort_outlines(outlines)))
+        } else {
+            Err(MeshError(problems))
+        }
+    }
+
+    pub fn from_polygons(polygons: Vec<Polygon>) -> TerrainMesh {
+        let mesh = polygons.iter()
+            .flat_map(|polygon| polygon.loops())
+            .flat_map(|outline| outline.iter().map(|&coords| TerrainVertex::Surface(coords)))
+            .collect();
+
+        TerrainMesh::build(mesh, Some(polygons))
+    }
+
+    pub fn revision(&self) -> usize {
+        self.revision
+    }
+
+    // Carve a shape out of the terrain, returning whether anything changed.
+    // Only the triangles the shape reaches are triangulated again, unless
+    // the new ones fail to line up with the rest, in which case the polygon
+    // is triangulated whole. A fan mesh is turned into outlines first,
+    // which counts as a change even if the shape misses it.
+    pub fn subtract(&mut self, shape: &[WorldCoords]) -> bool {
+        self.combine(shape, Operation::Subtract)
+    }
+
+    // Add a shape to the terrain, joining it to any polygons it overlaps
+    pub fn union(&mut self, shape: &[WorldCoords]) -> bool {
+        self.combine(shape, Operation::Union)
+    }
+
+    pub fn subtract_circle(&mut self, centre: WorldCoords, radius: u32) -> bool {
+        self.subtract(&circle(centre, radius))
+    }
+
+    pub fn union_circle(&mut self, centre: WorldCoords, radius: u32) -> bool {
+        self.union(&circle(centre, radius))
+    }
+
+    fn combine(&mut self, shape: &[WorldCoords], operation: Operation) -> bool {
+        if shape.len() < 3 || area(shape) == 0 {
+            return false;
+        }
+
+        let converted = self.polygons.is_none();
+        if converted {
+            *self = TerrainMesh::from_polygons(sort_outlines(self.fan_outlines()));
+        }
+
+        let polygons = {
+            let polygons = match self.polygons {
+                Some(ref polygons) => polygons,
+                None => return converted
+            };
+
+            let shape_bounds = bounds(shape);
+            let affected = |polygon: &Polygon| bounds_overlap(bounds(&polygon.outline), shape_bounds);
+
+            let region: Vec<Vec<WorldCoords>> = polygons.iter()
+                .filter(|polygon| affected(*polygon))
+                .flat_map(|polygon| polygon.loops())
+                .map(|outline| outline.to_vec())
+                .collect();
+
+            let mut result: Vec<Polygon> = polygons.iter().filter(|polygon| !affected(*polygon)).cloned().collect();
+            let untouched = result.len();
+
+            // Triangles the shape reaches are cut out, and the hole they
+            // leave is edited and triangulated on its own. The rest keep
+            // their triangles.
+            let mut patched = Vec::new();
+            let mut touched = Vec::new();
+
+            for polygon in polygons.iter().filter(|polygon| affected(*polygon)) {
+                for triangle in polygon.corners() {
+                    if triangle_touches(&triangle, shape, shape_bounds) {
+                        touched.push(triangle);
+                    } else {
+                        patched.push(triangle);
+                    }
+                }
+            }
+
+            for (outline, holes) in group_outlines(combine(&boundary(&touched), shape, operation)) {
+                patched.extend(Polygon::new(outline, holes).corners());
+            }
+
+            let outlines = group_outlines(combine(&region, shape, operation));
+            let shared = share_triangles(&outlines, &patched);
+
+            // Keep the triangles of anything that came through unchanged
+            let mut reused = 0;
+            let mut triangulated = 0;
+
+            for (i, (outline, holes)) in outlines.into_iter().enumerate() {
+                let same = polygons.iter().find(|polygon| polygon.outline == outline && polygon.holes == holes);
+
+                match same {
+                    Some(polygon) => {
+                        result.push(polygon.clone());
+                        reused += 1;
+                    },
+
+                    None => {
+                        let triangles = shared.as_ref().map(|shared| shared[i].clone());
+                        result.push(Polygon::with_triangles(outline, holes, triangles));
+                        triangulated += 1;
+                    }
+                }
+            }
+
+            if triangulated == 0 && untouched + reused == polygons.len() {
+                return converted;
+            }
+
+            result
+        };
+
+        *self = TerrainMesh::from_polygons(polygons);
+        true
+    }
+
+    // Outlines covering the same ground as the fans, built up by joining
+    // their triangles one at a time. Any gap between the first and last
+    // vertex of a fan becomes part of the surface.
+    fn fan_outlines(&self) -> Vec<Vec<WorldCoords>> {
+        self.triangles().into_iter().fold(Vec::new(), |region, triangle| combine(&region, &triangle, Operation::Union))
+    }
+
+    pub fn from_vertices(mesh: Vec<TerrainVertex>) -> Result<TerrainMesh, MeshError> {
+        let mesh = TerrainMesh::new(mesh);
+        let problems = mesh.validate();
+
+        if problems.is_empty() {
+            Ok(mesh)
+        } else {
+            Err(MeshError(problems))
+        }
+    }
+
+    // Everything structurally wrong with the mesh, empty if it is fine
+    pub fn validate(&self) -> Vec<MeshProblem> {
+        if let Some(ref polygons) = self.polygons {
+            let outlines: Vec<Vec<WorldCoords>> = polygons.iter()
+                .flat_map(|polygon| polygon.loops())
+                .map(|outline| outline.to_vec())
+                .collect();
+
+            return outline_problems(&outlines);
+        }
+
+        let mut problems = Vec::new();
+
+        match self.mesh.first() {
+            None => return vec![MeshProblem::Empty],
+            Some(&TerrainVertex::Surface(_)) => problems.push(MeshProblem::SurfaceCentre { index: 0 }),
+            Some(&TerrainVertex::Inner(_)) => ()
+        }
+
+        let mut seen = HashMap::new();
+        for (index, vertex) in self.mesh.iter().enumerate() {
+            let coords = vertex.coords();
+
+            match seen.entry((coords.0, coords.1)) {
+                Entry::Occupied(entry) => problems.push(MeshProblem::DuplicateVertex { first: *entry.get(), duplicate: index }),
+                Entry::Vacant(entry) => { entry.insert(index); }
+            }
+        }
+
+        let mut centre = 0;
+        let mut last: Option<usize> = None;
+        let mut edges = Vec::new();
+
+        for (index, vertex) in self.mesh.iter().enumerate().skip(1) {
+            match *vertex {
+                TerrainVertex::Surface(coords) => {
+                    if let Some(prev) = last {
+                        if cross(self.mesh[centre].coords(), self.mesh[prev].coords(), coords) == 0 {
+                            problems.push(MeshProblem::DegenerateTriangle { vertices: [centre, prev, index] });
+                        }
+
+                        edges.push((prev, index));
+                    }
+
+                    last = Some(index);
+                },
+
+                TerrainVertex::Inner(_) => {
+                    if last.is_none() {
+                        problems.push(MeshProblem::InnerBeforeSurface { index: index });
+                    }
+
+                    centre = index;
+                }
+            }
+        }
+
+        for (i, &(a, b)) in edges.iter().enumerate() {
+            for &(c, d) in &edges[i + 1 ..] {
+                // Neighbouring edges share an end
+                if a == c || a == d || b == c || b == d {
+                    continue;
+                }
+
+                let coords = |index: usize| self.mesh[index].coords();
+                if segments_intersect(coords(a), coords(b), coords(c), coords(d)) {
+                    problems.push(MeshProblem::SelfIntersection { first: (a, b), second: (c, d) });
+                }
+            }
+        }
+
+        problems
+    }
+
+    // For meshes built from outlines, every outline vertex as Surface
+    pub fn vertices(&self) -> &[TerrainVertex] {
+        &self.mesh
+    }
+
+    pub fn polygons(&self) -> Option<&[Polygon]> {
+        self.polygons.as_ref().map(|polygons| &polygons[..])
+    }
+
+    pub fn mesh_vertices<'a>(&'a self) -> impl Iterator<Item=WorldCoords> + 'a {
+        self.mesh.iter().map(|vertex| vertex.coords())
+    }
+
+    // Pairs of consecutive surface vertices, along with the centre of the fan
+    // they belong to. The surface carries on across inner vertices.
+    //
+    // Outline edges have no fan centre, so they are given a point on their
+    // solid side instead.
+    pub fn surface_edges(&self) -> Vec<(WorldCoords, WorldCoords, WorldCoords)> {
+        let mut edges = Vec::new();
+
+        if let Some(ref polygons) = self.polygons {
+            for outline in polygons.iter().flat_map(|polygon| polygon.loops()) {
+                for i in 0 .. outline.len() {
+                    let (start, end) = (outline[i], outline[(i + 1) % outline.len()]);
+                    let solid = WorldCoords(start.0 - (end.1 - start.1), start.1 + (end.0 - start.0));
+
+                    edges.push((start, end, solid));
+                }
+            }
+
+            return edges;
+        }
+
+        let mut centre = match self.mesh.first() {
+            Some(vertex) => vertex.coords(),
+            None => return edges
+        };
+
+        let mut last = None;
+
+        for vertex in self.mesh.iter().skip(1) {
+            match *vertex {
+                TerrainVertex::Surface(coords) => {
+                    if let Some(prev) = last {
+                        edges.push((prev, coords, centre));
+                    }
+
+                    last = Some(coords);
+                },
+
+                TerrainVertex::Inner(coords) => {
+                    centre = coords;
+                }
+            }
+        }
+
+        edges
+    }
+
+    // The surface as runs of connected edges, with outward normals and
+    // distances along each run
+    pub fn surface_chains(&self) -> &[SurfaceChain] {
+        &self.chains
+    }
+
+    // The closest point on any part of the surface, along with which chain
+    // it is on. Chains whose bounds are further away than the best point so
+    // far are skipped without looking at their edges.
+    pub fn closest_surface_point(&self, point: Vec2) -> Option<(usize, SurfacePoint)> {
+        let mut best: Option<(usize, SurfacePoint)> = None;
+
+        for (index, chain) in self.chains.iter().enumerate() {
+            if best.map_or(false, |(_, closest)| chain.bounds_distance(point) >= closest.offset.abs()) {
+                continue;
+            }
+
+            if let Some(found) = chain.closest_point(point) {
+                if best.map_or(true, |(_, closest)| found.offset.abs() < closest.offset.abs()) {
+                    best = Some((index, found));
+                }
+            }
+        }
+
+        best
+    }
+
+    // Calculate triangle_strip indices
+    pub fn mesh_indices(&self, offset: u32) -> impl Iterator<Item=u32> {
+        
+        let mut indices: Vec<u32> = Vec::new();
+
+        // One strip per triangle
+        if let Some(ref polygons) = self.polygons {
+            let mut base = offset;
+
+            for polygon in polygons {
+                for triangle in polygon.triangles() {
+                    indices.extend(triangle.iter().map(|&index| index + base));
+                    indices.push(RESTART);
+                }
+
+                base += polygon.vertex_count() as u32;
+            }
+
+            return indices.into_iter().chain(once(RESTART));
+        }
+
+        let mut base = 0;
+        let mut start = true;
+        let mut last = None;
+
+        for (index, vertex) in self.mesh.iter().enumerate().skip(1) {
+            let index = index as u32;
+
+            if start {
+                indices.extend_from_slice(&[base + offset]);
+                start = false;
+            } else {
+                start = true;
+            }
+
+            match *vertex {
+                TerrainVertex::Surface(_) => {
+                    indices.extend_from_slice(&[index + offset]);
+                    last = Some(index + offset);
+                },
+
+                // Nothing to continue the surface from yet, so the fan
+                // starts empty. validate reports this.
+                TerrainVertex::Inner(_) => {
+                    indices.extend_from_slice(&[index + offset, RESTART]);
+
+                    if let Some(last) = last {
+                        indices.push(last);
+                    }
+
+                    base = index;
+                    start = true;
+                }
+            }
+        }
+
+        indices.into_iter().chain(once(RESTART))
+    }
+
+    // Every filled triangle, read back out of the strips. Winding is not
+    // consistent and ones without area are left out.
+    pub fn triangles(&self) -> Vec<[WorldCoords; 3]> {
+        let vertices: Vec<WorldCoords> = self.mesh_vertices().collect();
+
+        let mut triangles = Vec::new();
+        let mut strip = Vec::new();
+
+        for index in self.mesh_indices(0) {
+            if index == RESTART {
+                strip.clear();
+                continue;
+            }
+
+            strip.push(vertices[index as usize]);
+
+            let len = strip.len();
+            if len >= 3 && cross(strip[len - 3], strip[len - 2], strip[len - 1]) != 0 {
+                triangles.push([strip[len - 3], strip[len - 2], strip[len - 1]]);
+            }
+        }
+
+        triangles
+    }
+}
+
+pub static RESTART: u32 = 0xffffffff;
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+
+    use math::Vec2;
+    use ty::WorldCoords;
+
+    use super::*;
+
+    // A fan around the centre of a 40 unit square. It stops one corner
+    // short, leaving a wedge from the centre to the left edge unfilled.
+    fn fan() -> TerrainMesh {
+        TerrainMesh::from_vertices(vec![
+            TerrainVertex::Inner(WorldCoords(0, 0)),
+            TerrainVertex::Surface(WorldCoords(-20, -20)),
+            TerrainVertex::Surface(WorldCoords(20, -20)),
+            TerrainVertex::Surface(WorldCoords(20, 20)),
+            TerrainVertex::Surface(WorldCoords(-20, 20)),
+        ]).unwrap()
+    }
+
+    fn total_area(mesh: &TerrainMesh) -> i64 {
+        mesh.polygons().unwrap().iter().flat_map(|polygon| polygon.loops()).map(|outline| area(outline)).sum::<i64>() / 2
+    }
+
+    #[test]
+    fn editing_a_fan_mesh_turns_it_into_outlines() {
+        let mut mesh = fan();
+        let revision = mesh.revision();
+
+        assert!(mesh.subtract(&[WorldCoords(-4, -14), WorldCoords(4, -14), WorldCoords(4, -6), WorldCoords(-4, -6)]));
+        assert!(mesh.revision() != revision);
+        assert!(mesh.validate().is_empty());
+
+        let polygons = mesh.polygons().unwrap();
+        assert_eq!(polygons.len(), 1);
+        assert_eq!(polygons[0].holes().len(), 1);
+        assert_eq!(total_area(&mesh), 40 * 40 - 20 * 40 / 2 - 8 * 8);
+    }
+
+    #[test]
+    fn converting_a_fan_keeps_its_ground() {
+        let mut mesh = fan();
+
+        // Misses the fan, but the mesh is still converted
+        assert!(mesh.union(&[WorldCoords(300, 300), WorldCoords(310, 300), WorldCoords(310, 310)]));
+        assert!(mesh.polygons().is_some());
+        assert_eq!(total_area(&mesh), 1200 + 50);
+
+        // Once converted, a miss changes nothing
+        assert!(!mesh.subtract(&[WorldCoords(500, 500), WorldCoords(510, 500), WorldCoords(510, 510)]));
+
+        // The wedge meets the centre, so a strip through it splits the rest
+        assert!(mesh.subtract(&[WorldCoords(-30, -5), WorldCoords(30, -5), WorldCoords(30, 5), WorldCoords(-30, 5)]));
+        assert_eq!(mesh.polygons().unwrap().len(), 3);
+        assert_eq!(total_area(&mesh), 1200 - 225 + 50);
+    }
+
+    // A long strip of ground with a bumpy top, no three vertices in a line
+    fn ground() -> TerrainMesh {
+        let mut outline: Vec<WorldCoords> = (0 .. 101).map(|i| WorldCoords(i * 20, 100 - (i * 7) % 13 * 4 - i % 2 * 5)).collect();
+        outline.push(WorldCoords(2000, 400));
+        outline.push(WorldCoords(0, 400));
+
+        TerrainMesh::from_outlines(vec![outline]).unwrap()
+    }
+
+    fn corners(mesh: &TerrainMesh) -> Vec<[WorldCoords; 3]> {
+        mesh.polygons().unwrap().iter().flat_map(|polygon| polygon.corners()).collect()
+    }
+
+    // Each polygon's triangles add up to its area, none of them flipped
+    fn assert_covered(mesh: &TerrainMesh) {
+        for polygon in mesh.polygons().unwrap() {
+            let areas: Vec<i64> = polygon.corners().iter().map(|t| cross(t[0], t[1], t[2])).collect();
+
+            assert!(areas.iter().all(|&area| area > 0));
+            assert_eq!(areas.iter().sum::<i64>(), polygon.loops().map(|outline| area(outline)).sum::<i64>());
+        }
+    }
+
+    #[test]
+    fn edits_keep_the_triangles_they_do_not_reach() {
+        let mut mesh = ground();
+        let before = corners(&mesh);
+
+        let crater = circle(WorldCoords(1000, 100), 30);
+        assert!(mesh.subtract(&crater));
+        assert!(mesh.validate().is_empty());
+        assert_covered(&mesh);
+
+        let after = corners(&mesh);
+        let missed: Vec<&[WorldCoords; 3]> = before.iter()
+            .filter(|triangle| !triangle_touches(triangle, &crater, bounds(&crater)))
+            .collect();
+
+        assert!(missed.len() > before.len() / 2);
+        assert!(missed.iter().all(|triangle| after.contains(triangle)));
+    }
+
+    #[test]
+    fn union_joins_polygons_and_keeps_them_covered() {
+        let square = |x: i32| vec![WorldCoords(x, 0), WorldCoords(x + 100, 0), WorldCoords(x + 100, 100), WorldCoords(x, 100)];
+        let mut mesh = TerrainMesh::from_outlines(vec![square(0), square(200)]).unwrap();
+
+        assert!(mesh.union(&[WorldCoords(50, 40), WorldCoords(250, 40), WorldCoords(250, 60), WorldCoords(50, 60)]));
+        assert_eq!(mesh.polygons().unwrap().len(), 1);
+        assert_eq!(total_area(&mesh), 2 * 100 * 100 + 100 * 20);
+        assert_covered(&mesh);
+
+        // Then cut it back apart through the bar and part of each square
+        assert!(mesh.subtract(&[WorldCoords(80, -10), WorldCoords(220, -10), WorldCoords(220, 110), WorldCoords(80, 110)]));
+        assert_eq!(mesh.polygons().unwrap().len(), 2);
+        assert_eq!(total_area(&mesh), 2 * 80 * 100);
+        assert_covered(&mesh);
+    }
+
+    #[test]
+    fn vertices_dropped_by_an_edit_fall_back_to_triangulating_whole() {
+        // The middle of the bottom edge goes once the outline is rebuilt,
+        // taking the triangles that use it with it
+        let mut mesh = TerrainMesh::from_outlines(vec![vec![
+            WorldCoords(0, 0), WorldCoords(400, 0), WorldCoords(400, 100), WorldCoords(50, 100), WorldCoords(0, 100),
+        ]]).unwrap();
+
+        assert!(mesh.subtract(&[WorldCoords(390, 90), WorldCoords(410, 90), WorldCoords(410, 110)]));
+        assert!(!mesh.polygons().unwrap()[0].outline().contains(&WorldCoords(50, 100)));
+        assert_covered(&mesh);
+    }
+
+    #[test]
+    fn repeated_digging_stays_covered() {
+        let mut mesh = ground();
+
+        for i in 0 .. 20 {
+            mesh.subtract_circle(WorldCoords(100 + i * 90, 100 + i % 3 * 40), 40 + (i % 4) as u32 * 10);
+            assert_covered(&mesh);
+        }
+
+        assert!(mesh.validate().is_empty());
+    }
+
+    #[test]
+    fn closest_surface_point_matches_every_chain() {
+        let square = |x: i32, y: i32| vec![WorldCoords(x, y), WorldCoords(x + 50, y), WorldCoords(x + 50, y + 50), WorldCoords(x, y + 50)];
+        let hole = vec![WorldCoords(10, 310), WorldCoords(40, 310), WorldCoords(40, 340), WorldCoords(10, 340)];
+
+        let mesh = TerrainMesh::from_outlines(vec![square(0, 0), square(200, 0), square(0, 300), hole]).unwrap();
+        assert_eq!(mesh.surface_chains().len(), 4);
+
+        for &point in &[Vec2(25.0, -10.0), Vec2(260.0, 25.0), Vec2(25.0, 325.0), Vec2(5.0, 305.0), Vec2(120.0, 160.0)] {
+            let (chain, found) = mesh.closest_surface_point(point).unwrap();
+
+            let gaps: Vec<f32> = mesh.surface_chains().iter().map(|chain| chain.closest_point(point).unwrap().offset.abs()).collect();
+            let nearest = gaps.iter().cloned().fold(f32::MAX, f32::min);
+
+            assert_eq!(gaps[chain], nearest);
+            assert_eq!(found.offset.abs(), nearest);
+        }
+    }
+
+    fn outline(points: &[(i32, i32)]) -> Vec<WorldCoords> {
+        points.iter().map(|&(x, y)| WorldCoords(x, y)).collect()
+    }
+
+    #[test]
+    fn empty_meshes_are_reported() {
+        assert_eq!(TerrainMesh::new(Vec::new()).validate(), vec![MeshProblem::Empty]);
+        assert_eq!(outline_problems(&[]), vec![MeshProblem::Empty]);
+    }
+
+    #[test]
+    fn fans_start_at_a_centre() {
+        let mesh = TerrainMesh::new(vec![
+            TerrainVertex::Surface(WorldCoords(0, 0)),
+            TerrainVertex::Surface(WorldCoords(-20, -20)),
+            TerrainVertex::Surface(WorldCoords(20, -20)),
+            TerrainVertex::Surface(WorldCoords(20, 20)),
+        ]);
+
+        assert_eq!(mesh.validate(), vec![MeshProblem::SurfaceCentre { index: 0 }]);
+    }
+
+    #[test]
+    fn fans_need_surface_before_another_centre() {
+        let mesh = TerrainMesh::new(vec![
+            TerrainVertex::Inner(WorldCoords(0, 0)),
+            TerrainVertex::Inner(WorldCoords(5, 5)),
+            TerrainVertex::Surface(WorldCoords(-20, -20)),
+            TerrainVertex::Surface(WorldCoords(20, -20)),
+            TerrainVertex::Surface(WorldCoords(20, 20)),
+        ]);
+
+        assert_eq!(mesh.validate(), vec![MeshProblem::InnerBeforeSurface { index: 1 }]);
+    }
+
+    #[test]
+    fn fan_duplicates_point_back_at_the_first() {
+        let mut vertices = fan().vertices().to_vec();
+        vertices.push(TerrainVertex::Inner(WorldCoords(0, 0)));
+
+        assert_eq!(TerrainMesh::new(vertices).validate(), vec![MeshProblem::DuplicateVertex { first: 0, duplicate: 5 }]);
+    }
+
+    #[test]
+    fn fan_triangles_need_area() {
+        let mesh = TerrainMesh::new(vec![
+            TerrainVertex::Inner(WorldCoords(0, 0)),
+            TerrainVertex::Surface(WorldCoords(-20, -20)),
+            TerrainVertex::Surface(WorldCoords(20, -20)),
+            TerrainVertex::Surface(WorldCoords(40, -40)),
+        ]);
+
+        assert_eq!(mesh.validate(), vec![MeshProblem::DegenerateTriangle { vertices: [0, 2, 3] }]);
+    }
+
+    #[test]
+    fn fan_surfaces_must_not_cross() {
+        let mut vertices = fan().vertices().to_vec();
+        vertices.push(TerrainVertex::Surface(WorldCoords(0, -30)));
+
+        assert_eq!(TerrainMesh::new(vertices).validate(), vec![MeshProblem::SelfIntersection { first: (1, 2), second: (4, 5) }]);
+    }
+
+    #[test]
+    fn outlines_need_area() {
+        let square = outline(&[(0, 0), (10, 0), (10, 10), (0, 10)]);
+        let line = outline(&[(20, 0), (30, 0), (40, 0)]);
+        let pair = outline(&[(20, 20), (30, 30)]);
+
+        assert_eq!(outline_problems(&[square.clone(), line]), vec![MeshProblem::DegenerateOutline { first: 4, count: 3 }]);
+        assert_eq!(outline_problems(&[pair, square.clone()]), vec![MeshProblem::DegenerateOutline { first: 0, count: 2 }]);
+        assert_eq!(outline_problems(&[square, Vec::new()]), vec![MeshProblem::DegenerateOutline { first: 4, count: 0 }]);
+    }
+
+    #[test]
+    fn outlines_sharing_a_vertex_are_duplicates_with_touching_edges() {
+        let square = outline(&[(0, 0), (10, 0), (10, 10), (0, 10)]);
+        let triangle = outline(&[(10, 10), (20, 10), (20, 20)]);
+
+        assert_eq!(outline_problems(&[square, triangle]), vec![
+            MeshProblem::DuplicateVertex { first: 2, duplicate: 4 },
+            MeshProblem::SelfIntersection { first: (1, 2), second: (4, 5) },
+            MeshProblem::SelfIntersection { first: (1, 2), second: (6, 4) },
+            MeshProblem::SelfIntersection { first: (2, 3), second: (4, 5) },
+            MeshProblem::SelfIntersection { first: (2, 3), second: (6, 4) },
+        ]);
+    }
+
+    #[test]
+    fn outlines_must_not_cross_themselves() {
+        let bowtie = outline(&[(0, 0), (20, 20), (20, 0), (0, 10)]);
+
+        assert_eq!(outline_problems(&[bowtie]), vec![MeshProblem::SelfIntersection { first: (0, 1), second: (2, 3) }]);
+    }
+}
@@ -0,0 +1,280 @@
+use std::cmp::Ordering;
+use std::f32;
+use std::mem;
+
+use math::Vec2;
+use ty::WorldCoords;
+
+// A place on the surface
+#[derive(Copy, Clone, Debug)]
+pub struct SurfacePoint {
+    pub edge: usize,
+    pub point: Vec2,
+
+    // Blended between the vertex normals at either end of the edge, so it
+    // turns smoothly around corners. Points out of the terrain.
+    pub normal: Vec2,
+    pub tangent: Vec2,
+
+    // How far along the chain from its first point
+    pub distance: f32,
+
+    // Signed distance from here to the point that was asked about, positive
+    // outside the terrain. Zero unless this came from a closest point query.
+    pub offset: f32,
+}
+
+// A run of connected surface edges. Closed chains come from outlines and
+// have an edge from their last point back to their first.
+#[derive(Clone, Debug)]
+pub struct SurfaceChain {
+    points: Vec<Vec2>,
+    closed: bool,
+
+    edge_normals: Vec<Vec2>,
+    edge_tangents: Vec<Vec2>,
+    vertex_normals: Vec<Vec2>,
+
+    // Distance along the chain to each point
+    distances: Vec<f32>,
+    length: f32,
+
+    // Smallest and largest corner around every point
+    min: Vec2,
+    max: Vec2,
+}
+
+impl SurfaceChain {
+    // Edge normals must be unit length and point out of the terrain
+    fn new(points: Vec<Vec2>, closed: bool, edge_normals: Vec<Vec2>) -> SurfaceChain {
+        let count = edge_normals.len();
+        let edge_tangents: Vec<Vec2> = (0 .. count)
+            .map(|i| (points[(i + 1) % points.len()] - points[i]).normalize())
+            .collect();
+
+        // A vertex takes the average of the edges either side. Open ends only
+        // have the one, and a hairpin where the two cancel keeps the first.
+        let vertex_normals = (0 .. points.len()).map(|i| {
+            let before = if i > 0 { Some(edge_normals[i - 1]) } else if closed { Some(edge_normals[count - 1]) } else { None };
+            let after = if i < count { Some(edge_normals[i]) } else { None };
+
+            match (before, after) {
+                (Some(before), Some(after)) => {
+                    let sum = before + after;
+                    if sum.length_squared() > 1e-6 { sum.normalize() } else { before }
+                },
+
+                (Some(normal), None) | (None, Some(normal)) => normal,
+                (None, None) => Vec2(0.0, 0.0)
+            }
+        }).collect();
+
+        let mut distances = Vec::with_capacity(points.len());
+        let mut length = 0.0;
+
+        for i in 0 .. points.len() {
+            distances.push(length);
+
+            if i < count {
+                length += points[i].distance(points[(i + 1) % points.len()]);
+            }
+        }
+
+        let min = points.iter().fold(Vec2(f32::MAX, f32::MAX), |min, &p| min.min(p));
+        let max = points.iter().fold(Vec2(f32::MIN, f32::MIN), |max, &p| max.max(p));
+
+        SurfaceChain {
+            points: points,
+            closed: closed,
+            edge_normals: edge_normals,
+            edge_tangents: edge_tangents,
+            vertex_normals: vertex_normals,
+            distances: distances,
+            length: length,
+            min: min,
+            max: max,
+        }
+    }
+
+    pub fn points(&self) -> &[Vec2] {
+        &self.points
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn length(&self) -> f32 {
+        self.length
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.edge_normals.len()
+    }
+
+    pub fn edge(&self, edge: usize) -> (Vec2, Vec2) {
+        (self.points[edge], self.points[(edge + 1) % self.points.len()])
+    }
+
+    pub fn edge_normal(&self, edge: usize) -> Vec2 {
+        self.edge_normals[edge]
+    }
+
+    // Unit direction from the start of the edge to its end
+    pub fn edge_tangent(&self, edge: usize) -> Vec2 {
+        self.edge_tangents[edge]
+    }
+
+    pub fn vertex_normal(&self, vertex: usize) -> Vec2 {
+        self.vertex_normals[vertex]
+    }
+
+    // Along the chain, at right angles to the vertex normal
+    pub fn vertex_tangent(&self, vertex: usize) -> Vec2 {
+        let normal = self.vertex_normals[vertex];
+        let edge = if vertex < self.edge_count() { vertex } else { vertex - 1 };
+
+        let tangent = normal.perpendicular();
+        if tangent.dot(self.edge_tangents[edge]) < 0.0 { -tangent } else { tangent }
+    }
+
+    // How far a point is from the box around the chain, zero inside it. No
+    // point on the chain is any closer.
+    pub fn bounds_distance(&self, point: Vec2) -> f32 {
+        let outside = (self.min - point).max(point - self.max).max(Vec2(0.0, 0.0));
+        outside.length()
+    }
+
+    // Distance along the chain to a vertex
+    pub fn distance(&self, vertex: usize) -> f32 {
+        self.distances[vertex]
+    }
+
+    // The point some way along the chain. Closed chains wrap around, open
+    // ones stop at their ends.
+    pub fn point_at(&self, distance: f32) -> Option<SurfacePoint> {
+        if self.edge_count() == 0 {
+            return None;
+        }
+
+        let distance = if self.closed && self.length > 0.0 {
+            let wrapped = distance % self.length;
+            if wrapped < 0.0 { wrapped + self.length } else { wrapped }
+        } else {
+            distance.max(0.0).min(self.length)
+        };
+
+        let edge = match self.distances.binary_search_by(|d| d.partial_cmp(&distance).unwrap_or(Ordering::Less)) {
+            Ok(vertex) => vertex,
+            Err(vertex) => vertex - 1
+        };
+        let edge = edge.min(self.edge_count() - 1);
+
+        let (start, end) = self.edge(edge);
+        let edge_length = start.distance(end);
+        let t = if edge_length > 0.0 { (distance - self.distances[edge]) / edge_length } else { 0.0 };
+
+        Some(self.at(edge, t))
+    }
+
+    // The nearest point on the chain to a point anywhere
+    pub fn closest_point(&self, point: Vec2) -> Option<SurfacePoint> {
+        let mut best: Option<(usize, f32, f32)> = None;
+
+        for edge in 0 .. self.edge_count() {
+            let (start, end) = self.edge(edge);
+            let t = project(start, end, point);
+            let gap = start.lerp(end, t).distance(point);
+
+            if best.map_or(true, |(_, _, best_gap)| gap < best_gap) {
+                best = Some((edge, t, gap));
+            }
+        }
+
+        best.map(|(edge, t, gap)| {
+            let mut found = self.at(edge, t);
+
+            let inside = (point - found.point).dot(found.normal) < 0.0;
+            found.offset = if inside { -gap } else { gap };
+
+            found
+        })
+    }
+
+    fn at(&self, edge: usize, t: f32) -> SurfacePoint {
+        let (start, end) = self.edge(edge);
+        let next = (edge + 1) % self.points.len();
+
+        let normal = self.vertex_normals[edge].lerp(self.vertex_normals[next], t);
+        let normal = if normal.length_squared() > 1e-6 { normal.normalize() } else { self.edge_normals[edge] };
+
+        SurfacePoint {
+            edge: edge,
+            point: start.lerp(end, t),
+            normal: normal,
+            tangent: self.edge_tangents[edge],
+            distance: self.distances[edge] + t * start.distance(end),
+            offset: 0.0,
+        }
+    }
+}
+
+// Split surface edges into chains wherever one does not start where the
+// last ended, closing a chain when it gets back to its first point. Each
+// edge comes with a point on its solid side, which the normal faces away
+// from. Zero length edges are skipped.
+pub fn chains(edges: Vec<(WorldCoords, WorldCoords, WorldCoords)>) -> Vec<SurfaceChain> {
+    let mut chains = Vec::new();
+
+    let mut points: Vec<Vec2> = Vec::new();
+    let mut normals = Vec::new();
+    let mut first: Option<WorldCoords> = None;
+    let mut last: Option<WorldCoords> = None;
+
+    for (start, end, solid) in edges {
+        if start == end {
+            continue;
+        }
+
+        if last != Some(start) {
+            if !normals.is_empty() {
+                chains.push(SurfaceChain::new(mem::replace(&mut points, Vec::new()), false, mem::replace(&mut normals, Vec::new())));
+            }
+
+            points.clear();
+            points.push(start.to_render());
+            first = Some(start);
+        }
+
+        let edge = end.to_render() - start.to_render();
+        let mut normal = edge.perpendicular().normalize();
+        if normal.dot(start.to_render() - solid.to_render()) < 0.0 {
+            normal = -normal;
+        }
+
+        normals.push(normal);
+
+        if Some(end) == first {
+            chains.push(SurfaceChain::new(mem::replace(&mut points, Vec::new()), true, mem::replace(&mut normals, Vec::new())));
+            first = None;
+            last = None;
+        } else {
+            points.push(end.to_render());
+            last = Some(end);
+        }
+    }
+
+    if !normals.is_empty() {
+        chains.push(SurfaceChain::new(points, false, normals));
+    }
+
+    chains
+}
+
+// How far along ab the closest point to p is, 0 to 1
+fn project(a: Vec2, b: Vec2, p: Vec2) -> f32 {
+    let edge = b - a;
+    let length = edge.length_squared();
+
+    if length == 0.0 { 0.0 } else { ((p - a).dot(edge) / length).max(0.0).min(1.0) }
+}
@@ -0,0 +1,248 @@
+use ty::WorldCoords;
+
+use super::{cross, segments_intersect};
+
+// Ear clipping, following Eberly's "Triangulation by Ear Clipping". Each hole
+// is joined to the outline by a pair of bridge edges, turning the polygon
+// into a single ring that ears are then cut from.
+//
+// The outline must run counter-clockwise and holes clockwise. Triangles are
+// indices into the outline followed by each hole in turn.
+pub fn triangulate(outline: &[WorldCoords], holes: &[Vec<WorldCoords>]) -> Vec<[u32; 3]> {
+    let mut points = outline.to_vec();
+    let mut ring: Vec<usize> = (0 .. outline.len()).collect();
+
+    let mut starts = Vec::new();
+    for hole in holes {
+        starts.push(points.len());
+        points.extend_from_slice(hole);
+    }
+
+    // Rightmost holes first, so a bridge never has to cross a hole that is
+    // still waiting to be joined
+    let rightmost = |hole: &[WorldCoords]| -> usize {
+        (0 .. hole.len()).fold(0, |best, i| {
+            if hole[i].0 > hole[best].0 || (hole[i].0 == hole[best].0 && hole[i].1 < hole[best].1) { i } else { best }
+        })
+    };
+
+    let mut order: Vec<usize> = (0 .. holes.len()).filter(|&h| !holes[h].is_empty()).collect();
+    order.sort_by(|&a, &b| holes[b][rightmost(&holes[b])].0.cmp(&holes[a][rightmost(&holes[a])].0));
+
+    for (joined, &h) in order.iter().enumerate() {
+        let hole = &holes[h];
+        let first = rightmost(hole);
+        let hole_ring: Vec<usize> = (0 .. hole.len()).map(|i| starts[h] + (first + i) % hole.len()).collect();
+
+        let others: Vec<Vec<usize>> = order[joined + 1 ..].iter()
+            .map(|&other| (0 .. holes[other].len()).map(|i| starts[other] + i).collect())
+            .collect();
+
+        let bridge = match bridge(&points, &ring, &hole_ring, &others) {
+            Some(bridge) => bridge,
+
+            // Only possible for outlines that cross, which validation
+            // rejects. Leave the hole filled rather than fail.
+            None => continue
+        };
+
+        let mut spliced = Vec::with_capacity(ring.len() + hole_ring.len() + 2);
+        spliced.extend_from_slice(&ring[.. bridge + 1]);
+        spliced.extend_from_slice(&hole_ring);
+        spliced.push(hole_ring[0]);
+        spliced.push(ring[bridge]);
+        spliced.extend_from_slice(&ring[bridge + 1 ..]);
+
+        ring = spliced;
+    }
+
+    clip_ears(&points, ring)
+}
+
+// Position in the ring of the closest vertex that the first vertex of the
+// hole can see from inside the polygon
+fn bridge(points: &[WorldCoords], ring: &[usize], hole: &[usize], others: &[Vec<usize>]) -> Option<usize> {
+    let target = points[hole[0]];
+
+    let distance = |index: usize| {
+        let point = points[ring[index]];
+        let (dx, dy) = (point.0 as i64 - target.0 as i64, point.1 as i64 - target.1 as i64);
+        dx * dx + dy * dy
+    };
+
+    let mut candidates: Vec<usize> = (0 .. ring.len()).collect();
+    candidates.sort_by_key(|&index| distance(index));
+
+    let same = |a: WorldCoords, b: WorldCoords| a.0 == b.0 && a.1 == b.1;
+
+    // Whether the bridge crosses an edge of a ring, ignoring edges that only
+    // touch its ends
+    let crosses = |from: WorldCoords, ring: &[usize]| {
+        (0 .. ring.len()).any(|i| {
+            let (a, b) = (points[ring[i]], points[ring[(i + 1) % ring.len()]]);
+
+            if same(a, from) || same(b, from) || same(a, target) || same(b, target) {
+                return false;
+            }
+
+            segments_intersect(from, target, a, b)
+        })
+    };
+
+    candidates.into_iter().find(|&index| {
+        let len = ring.len();
+        let prev = points[ring[(index + len - 1) % len]];
+        let point = points[ring[index]];
+        let next = points[ring[(index + 1) % len]];
+
+        // The bridge has to leave the vertex into the polygon, which is left
+        // of both edges at a convex vertex and either at a reflex one
+        let (left_in, left_out) = (cross(prev, point, target) > 0, cross(point, next, target) > 0);
+        let inside = if cross(prev, point, next) >= 0 { left_in && left_out } else { left_in || left_out };
+
+        inside && !crosses(point, ring) && !crosses(point, hole) && !others.iter().any(|other| crosses(point, other))
+    })
+}
+
+fn clip_ears(points: &[WorldCoords], mut ring: Vec<usize>) -> Vec<[u32; 3]> {
+    let mut triangles = Vec::new();
+
+    let mut index = 0;
+    let mut misses = 0;
+
+    while ring.len() > 3 {
+        let len = ring.len();
+        let prev = (index + len - 1) % len;
+        let next = (index + 1) % len;
+
+        let ear = is_ear(points, &ring, prev, index, next);
+
+        // Gone all the way round without an ear, which only happens for
+        // collinear or overlapping edges. Cut the vertex anyway so that this
+        // ends, dropping it without a triangle if it has no area.
+        if !ear && misses < len {
+            index = next;
+            misses += 1;
+            continue;
+        }
+
+        if cross(points[ring[prev]], points[ring[index]], points[ring[next]]) > 0 {
+            triangles.push([ring[prev] as u32, ring[index] as u32, ring[next] as u32]);
+        }
+
+        ring.remove(index);
+        misses = 0;
+
+        // The vertex before may have just become an ear
+        index = if index == 0 { ring.len() - 1 } else { index - 1 };
+    }
+
+    if ring.len() == 3 && cross(points[ring[0]], points[ring[1]], points[ring[2]]) > 0 {
+        triangles.push([ring[0] as u32, ring[1] as u32, ring[2] as u32]);
+    }
+
+    triangles
+}
+
+fn is_ear(points: &[WorldCoords], ring: &[usize], prev: usize, index: usize, next: usize) -> bool {
+    let (a, b, c) = (points[ring[prev]], points[ring[index]], points[ring[next]]);
+
+    if cross(a, b, c) <= 0 {
+        return false;
+    }
+
+    // Bridges repeat vertices, which may sit on the triangle's corners
+    let corner = |p: WorldCoords| (p.0 == a.0 && p.1 == a.1) || (p.0 == b.0 && p.1 == b.1) || (p.0 == c.0 && p.1 == c.1);
+
+    !ring.iter().enumerate()
+        .filter(|&(i, _)| i != prev && i != index && i != next)
+        .map(|(_, &vertex)| points[vertex])
+        .any(|p| !corner(p) && cross(a, b, p) >= 0 && cross(b, c, p) >= 0 && cross(c, a, p) >= 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use ty::WorldCoords;
+
+    use super::super::area;
+    use super::*;
+
+    fn coords(points: &[(i32, i32)]) -> Vec<WorldCoords> {
+        points.iter().map(|&(x, y)| WorldCoords(x, y)).collect()
+    }
+
+    // Twice the area covered, checking every triangle keeps the winding
+    fn triangle_area(points: &[WorldCoords], triangles: &[[u32; 3]]) -> i64 {
+        triangles.iter().map(|triangle| {
+            let doubled = cross(points[triangle[0] as usize], points[triangle[1] as usize], points[triangle[2] as usize]);
+            assert!(doubled > 0);
+            doubled
+        }).sum()
+    }
+
+    // n - 2 + 2h triangles, adding up to the outline less its holes
+    fn assert_triangulated(outline: &[WorldCoords], holes: &[Vec<WorldCoords>]) {
+        let triangles = triangulate(outline, holes);
+
+        let mut points = outline.to_vec();
+        for hole in holes {
+            points.extend_from_slice(hole);
+        }
+
+        assert_eq!(triangles.len(), points.len() - 2 + 2 * holes.len());
+        assert_eq!(triangle_area(&points, &triangles), area(outline) + holes.iter().map(|hole| area(hole)).sum::<i64>());
+    }
+
+    #[test]
+    fn concave_outline() {
+        // A comb with three teeth
+        assert_triangulated(&coords(&[
+            (0, 0), (100, 0), (100, 60), (80, 60), (80, 20), (60, 20),
+            (60, 60), (40, 60), (40, 20), (20, 20), (20, 60), (0, 60),
+        ]), &[]);
+    }
+
+    #[test]
+    fn one_hole() {
+        let outline = coords(&[(0, 0), (100, 0), (100, 100), (0, 100)]);
+        let hole = coords(&[(30, 30), (30, 70), (70, 70), (70, 30)]);
+
+        assert_triangulated(&outline, &[hole]);
+    }
+
+    #[test]
+    fn two_holes() {
+        let outline = coords(&[(0, 0), (200, 0), (200, 100), (0, 100)]);
+        let left = coords(&[(20, 20), (20, 80), (80, 80), (80, 20)]);
+        let right = coords(&[(120, 30), (140, 70), (170, 40)]);
+
+        assert_triangulated(&outline, &[left.clone(), right.clone()]);
+        assert_triangulated(&outline, &[right, left]);
+    }
+
+    #[test]
+    fn holes_side_by_side() {
+        // The right hole is in the way of a straight bridge from the left one
+        let outline = coords(&[(0, 0), (300, 0), (300, 100), (0, 100)]);
+        let left = coords(&[(40, 40), (40, 60), (60, 60), (60, 40)]);
+        let right = coords(&[(100, 10), (100, 90), (120, 90), (120, 10)]);
+
+        assert_triangulated(&outline, &[left, right]);
+    }
+
+    #[test]
+    fn collinear_vertices() {
+        // Extra vertices along the bottom and right edges
+        assert_triangulated(&coords(&[(0, 0), (50, 0), (100, 0), (150, 0), (150, 50), (150, 100), (0, 100)]), &[]);
+    }
+
+    #[test]
+    fn duplicate_vertices_are_left_out() {
+        // The same point twice in a row adds a vertex but no triangle
+        let outline = coords(&[(0, 0), (100, 0), (100, 0), (100, 100), (0, 100)]);
+        let triangles = triangulate(&outline, &[]);
+
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(triangle_area(&outline, &triangles), area(&outline));
+    }
+}
@@ -0,0 +1,59 @@
+use std::time::{Duration, Instant};
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1000000000.0
+}
+
+// Accumulates real time and hands it out in fixed simulation steps, so that
+// gameplay runs at the same speed whatever rate it is ticked at
+pub struct FixedTimestep {
+    step: f64,
+    accumulator: f64,
+    last: Instant,
+
+    // Steps run at most per advance. Anything beyond is dropped rather than
+    // letting a slow frame snowball into ever more catch-up work.
+    max_steps: u32,
+}
+
+impl FixedTimestep {
+    pub fn new(rate: u32, max_steps: u32) -> FixedTimestep {
+        FixedTimestep {
+            step: 1.0 / rate as f64,
+            accumulator: 0.0,
+            last: Instant::now(),
+            max_steps: max_steps,
+        }
+    }
+
+    // Length of one simulation step in seconds
+    pub fn dt(&self) -> f32 {
+        self.step as f32
+    }
+
+    // Number of simulation steps to run now
+    pub fn advance(&mut self) -> u32 {
+        let now = Instant::now();
+        self.accumulator += seconds(now - self.last);
+        self.last = now;
+
+        let mut steps = (self.accumulator / self.step) as u32;
+
+        if steps > self.max_steps {
+            println!("Simulation fell behind, dropping {} steps", steps - self.max_steps);
+            steps = self.max_steps;
+            self.accumulator = self.accumulator % self.step;
+        } else {
+            self.accumulator -= steps as f64 * self.step;
+        }
+
+        steps
+    }
+
+    // How far between the last two simulation states the present moment is,
+    // from 0 to 1
+    pub fn alpha(&self) -> f32 {
+        let pending = self.accumulator + seconds(Instant::now() - self.last);
+        (pending / self.step).min(1.0) as f32
+    }
+}
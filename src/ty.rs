@@ -1,12 +1,328 @@
-#[derive(Copy, Clone, Debug)]
+use std::cmp;
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+use math::Vec2;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct WorldCoords(pub i32, pub i32);
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct WorldBounds(pub u32, pub u32);
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct WorldRect {
     pub position: WorldCoords,
     pub bounds: WorldBounds,
 }
 
+impl WorldCoords {
+    // Products are widened so that they can not overflow
+    pub fn dot(self, other: WorldCoords) -> i64 {
+        self.0 as i64 * other.0 as i64 + self.1 as i64 * other.1 as i64
+    }
+
+    pub fn cross(self, other: WorldCoords) -> i64 {
+        self.0 as i64 * other.1 as i64 - self.1 as i64 * other.0 as i64
+    }
+
+    pub fn length_squared(self) -> i64 {
+        self.dot(self)
+    }
+
+    pub fn length(self) -> f64 {
+        (self.length_squared() as f64).sqrt()
+    }
+
+    // Rounded to the nearest whole unit
+    pub fn lerp(self, other: WorldCoords, t: f32) -> WorldCoords {
+        WorldCoords::from_render(self.to_render().lerp(other.to_render(), t))
+    }
+
+    pub fn min(self, other: WorldCoords) -> WorldCoords {
+        WorldCoords(cmp::min(self.0, other.0), cmp::min(self.1, other.1))
+    }
+
+    pub fn max(self, other: WorldCoords) -> WorldCoords {
+        WorldCoords(cmp::max(self.0, other.0), cmp::max(self.1, other.1))
+    }
+
+    pub fn to_render(self) -> Vec2 {
+        Vec2(self.0 as f32, self.1 as f32)
+    }
+
+    // Rounded to the nearest whole unit
+    pub fn from_render(point: Vec2) -> WorldCoords {
+        WorldCoords(point.0.round() as i32, point.1.round() as i32)
+    }
+}
+
+impl Add for WorldCoords {
+    type Output = WorldCoords;
+
+    fn add(self, other: WorldCoords) -> WorldCoords {
+        WorldCoords(self.0 + other.0, self.1 + other.1)
+    }
+}
+
+impl Sub for WorldCoords {
+    type Output = WorldCoords;
+
+    fn sub(self, other: WorldCoords) -> WorldCoords {
+        WorldCoords(self.0 - other.0, self.1 - other.1)
+    }
+}
+
+impl Neg for WorldCoords {
+    type Output = WorldCoords;
+
+    fn neg(self) -> WorldCoords {
+        WorldCoords(-self.0, -self.1)
+    }
+}
+
+impl Mul<i32> for WorldCoords {
+    type Output = WorldCoords;
+
+    fn mul(self, scale: i32) -> WorldCoords {
+        WorldCoords(self.0 * scale, self.1 * scale)
+    }
+}
+
+impl AddAssign for WorldCoords {
+    fn add_assign(&mut self, other: WorldCoords) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for WorldCoords {
+    fn sub_assign(&mut self, other: WorldCoords) {
+        *self = *self - other;
+    }
+}
+
+impl WorldBounds {
+    pub fn area(self) -> u64 {
+        self.0 as u64 * self.1 as u64
+    }
+
+    // As an offset from a rect's position to its far corner
+    pub fn to_coords(self) -> WorldCoords {
+        WorldCoords(self.0 as i32, self.1 as i32)
+    }
+
+    pub fn to_render(self) -> Vec2 {
+        Vec2(self.0 as f32, self.1 as f32)
+    }
+}
+
+// Rects cover from their position up to but not including position plus
+// bounds
+impl WorldRect {
+    pub fn new(position: WorldCoords, bounds: WorldBounds) -> WorldRect {
+        WorldRect {
+            position: position,
+            bounds: bounds,
+        }
+    }
+
+    // The rect between two corners, in any order
+    pub fn from_corners(a: WorldCoords, b: WorldCoords) -> WorldRect {
+        let (min, max) = (a.min(b), a.max(b));
+        WorldRect::new(min, WorldBounds((max.0 - min.0) as u32, (max.1 - min.1) as u32))
+    }
+
+    pub fn min(&self) -> WorldCoords {
+        self.position
+    }
+
+    pub fn max(&self) -> WorldCoords {
+        self.position + self.bounds.to_coords()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bounds.0 == 0 || self.bounds.1 == 0
+    }
+
+    pub fn centre(&self) -> Vec2 {
+        self.position.to_render() + self.bounds.to_render() / 2.0
+    }
+
+    pub fn contains(&self, point: WorldCoords) -> bool {
+        let (min, max) = (self.min(), self.max());
+        point.0 >= min.0 && point.0 < max.0 && point.1 >= min.1 && point.1 < max.1
+    }
+
+    // Whether all of the other rect is inside this one
+    pub fn contains_rect(&self, other: &WorldRect) -> bool {
+        let (min, max) = (self.min(), self.max());
+        let (other_min, other_max) = (other.min(), other.max());
+
+        other_min.0 >= min.0 && other_min.1 >= min.1 && other_max.0 <= max.0 && other_max.1 <= max.1
+    }
+
+    // Only overlaps with some area count, rects that share an edge do not
+    // intersect
+    pub fn intersects(&self, other: &WorldRect) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    pub fn intersection(&self, other: &WorldRect) -> Option<WorldRect> {
+        let min = self.min().max(other.min());
+        let max = self.max().min(other.max());
+
+        if min.0 < max.0 && min.1 < max.1 {
+            Some(WorldRect::from_corners(min, max))
+        } else {
+            None
+        }
+    }
+
+    // The smallest rect covering both
+    pub fn union(&self, other: &WorldRect) -> WorldRect {
+        WorldRect::from_corners(self.min().min(other.min()), self.max().max(other.max()))
+    }
+
+    // Grown by `amount` on every side, or shrunk if negative. Shrinking
+    // stops at nothing, around the centre.
+    pub fn expand(&self, amount: i32) -> WorldRect {
+        let grow = |length: u32| cmp::max(length as i64 + 2 * amount as i64, 0) as u32;
+        let bounds = WorldBounds(grow(self.bounds.0), grow(self.bounds.1));
+
+        let shift = |length: u32, grown: u32| ((length as i64 - grown as i64) / 2) as i32;
+        let position = self.position + WorldCoords(shift(self.bounds.0, bounds.0), shift(self.bounds.1, bounds.1));
+
+        WorldRect::new(position, bounds)
+    }
+
+    pub fn translate(&self, offset: WorldCoords) -> WorldRect {
+        WorldRect::new(self.position + offset, self.bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use math::Vec2;
+    use rng::Rng;
+    use super::{WorldBounds, WorldCoords, WorldRect};
+
+    const CASES: usize = 1000;
+
+    fn coords(rng: &mut Rng) -> WorldCoords {
+        WorldCoords(rng.range(-1000, 1000), rng.range(-1000, 1000))
+    }
+
+    fn rect(rng: &mut Rng) -> WorldRect {
+        WorldRect::new(coords(rng), WorldBounds(rng.range(0, 300) as u32, rng.range(0, 300) as u32))
+    }
+
+    #[test]
+    fn render_coords_round_trip() {
+        let mut rng = Rng::new(1);
+
+        for _ in 0 .. CASES {
+            let point = coords(&mut rng);
+            assert_eq!(WorldCoords::from_render(point.to_render()), point);
+
+            // Anything less than half a unit away rounds back
+            let nudged = point.to_render() + Vec2(rng.next_f32() - 0.5, rng.next_f32() - 0.5) * 0.99;
+            assert_eq!(WorldCoords::from_render(nudged), point);
+        }
+    }
+
+    #[test]
+    fn from_corners_takes_them_in_any_order() {
+        let mut rng = Rng::new(2);
+
+        for _ in 0 .. CASES {
+            let (a, b) = (coords(&mut rng), coords(&mut rng));
+            let rect = WorldRect::from_corners(a, b);
+
+            assert_eq!(rect, WorldRect::from_corners(b, a));
+            assert_eq!(rect, WorldRect::from_corners(WorldCoords(a.0, b.1), WorldCoords(b.0, a.1)));
+            assert_eq!(rect.min(), a.min(b));
+            assert_eq!(rect.max(), a.max(b));
+        }
+    }
+
+    #[test]
+    fn intersection_is_inside_both() {
+        let mut rng = Rng::new(3);
+
+        for _ in 0 .. CASES {
+            let (a, b) = (rect(&mut rng), rect(&mut rng));
+
+            match a.intersection(&b) {
+                Some(inside) => {
+                    assert!(!inside.is_empty());
+                    assert!(a.contains_rect(&inside) && b.contains_rect(&inside));
+                    assert!(a.contains(inside.min()) && b.contains(inside.min()));
+                    assert_eq!(b.intersection(&a), Some(inside));
+                },
+
+                // Nothing inside both
+                None => {
+                    let point = coords(&mut rng);
+                    assert!(!(a.contains(point) && b.contains(point)));
+                    assert!(!a.intersects(&b));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn union_covers_both() {
+        let mut rng = Rng::new(4);
+
+        for _ in 0 .. CASES {
+            let (a, b) = (rect(&mut rng), rect(&mut rng));
+            let both = a.union(&b);
+
+            assert!(both.contains_rect(&a) && both.contains_rect(&b));
+            assert_eq!(both, b.union(&a));
+            assert_eq!(both.min(), a.min().min(b.min()));
+            assert_eq!(both.max(), a.max().max(b.max()));
+        }
+    }
+
+    #[test]
+    fn expanding_then_shrinking_restores_the_rect() {
+        let mut rng = Rng::new(5);
+
+        for _ in 0 .. CASES {
+            let original = rect(&mut rng);
+            let amount = rng.range(0, 100);
+
+            let grown = original.expand(amount);
+            assert!(grown.contains_rect(&original));
+            assert_eq!(grown.expand(-amount), original);
+
+            // Shrinking first only undoes if nothing was squashed to nothing
+            let smallest = original.bounds.0.min(original.bounds.1) as i32;
+            if smallest > 2 * amount {
+                let shrunk = original.expand(-amount);
+                assert!(original.contains_rect(&shrunk));
+                assert_eq!(shrunk.expand(amount), original);
+            }
+        }
+    }
+
+    #[test]
+    fn shrinking_stops_at_nothing() {
+        let rect = WorldRect::new(WorldCoords(10, 20), WorldBounds(4, 6));
+        let shrunk = rect.expand(-10);
+
+        assert_eq!(shrunk.bounds, WorldBounds(0, 0));
+        assert!(rect.contains_rect(&shrunk));
+    }
+
+    #[test]
+    fn rects_contain_their_min_but_not_their_max() {
+        let rect = WorldRect::new(WorldCoords(-5, 5), WorldBounds(10, 20));
+
+        assert!(rect.contains(rect.min()));
+        assert!(!rect.contains(rect.max()));
+        assert!(rect.contains(rect.max() - WorldCoords(1, 1)));
+        assert!(rect.contains_rect(&rect));
+        assert!(!rect.intersects(&rect.translate(WorldCoords(10, 0))));
+    }
+}
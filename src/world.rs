@@ -0,0 +1,121 @@
+use controller::{CharacterController, ControllerInput, ControllerTuning};
+use input::InputMap;
+use level::Level;
+use math::Vec2;
+use query::{self, RayHit};
+use renderer::{Camera, Follow};
+use rng::Rng;
+use sprite::Sprite;
+use terrain::{TerrainIndex, TerrainMesh};
+use ty::{WorldCoords, WorldRect};
+
+// Size of the crater the dig action leaves under the player
+const DIG_RADIUS: u32 = 48;
+
+// Simulation state. Stepping it is deterministic given the same tuning,
+// seed and inputs, which replays rely on.
+pub struct World {
+    // The first is the player
+    pub sprites: Vec<Sprite>,
+    pub terrain: TerrainMesh,
+
+    // Anything changing the terrain has to `update` this afterwards, as
+    // `dig` does
+    pub terrain_index: TerrainIndex,
+    pub camera: Camera,
+    pub controller: CharacterController,
+    pub rng: Rng,
+    pub steps: u32,
+}
+
+impl World {
+    pub fn new(terrain: TerrainMesh, sprites: Vec<Sprite>, tuning: ControllerTuning, seed: u64) -> World {
+        World {
+            sprites: sprites,
+            terrain_index: TerrainIndex::new(&terrain),
+            terrain: terrain,
+            camera: Camera {
+                follow: Some(Follow {
+                    dead_zone: [150.0, 100.0],
+                    smoothing: 5.0,
+                }),
+
+                ..Camera::new([400.0, 300.0])
+            },
+
+            controller: CharacterController::new(tuning),
+            rng: Rng::new(seed),
+            steps: 0,
+        }
+    }
+
+    pub fn from_level(level: &Level, tuning: ControllerTuning, seed: u64) -> World {
+        World::new(level.terrain_mesh(), level.untextured_sprites(), tuning, seed)
+    }
+
+    // Run one tick's steps with the input as it stands
+    pub fn tick(&mut self, input: &InputMap, steps: u32, dt: f32) {
+        let mut controls = ControllerInput {
+            move_x: input.axis("move_x"),
+            jump_held: input.pressed("jump"),
+            jump_pressed: input.just_pressed("jump"),
+        };
+
+        // Before stepping, so the player falls into the crater straight away
+        if input.just_pressed("dig") {
+            let feet = {
+                let rect = &self.sprites[0].rect;
+                WorldCoords(rect.centre().0 as i32, rect.max().1)
+            };
+
+            self.dig(feet, DIG_RADIUS);
+        }
+
+        // Presses only count for the first step they are seen in
+        for _ in 0 .. steps {
+            self.step(&controls, dt);
+            controls.jump_pressed = false;
+        }
+    }
+
+    // Advance the simulation by one fixed step
+    pub fn step(&mut self, input: &ControllerInput, dt: f32) {
+        self.steps += 1;
+
+        for sprite in self.sprites.iter_mut() {
+            sprite.previous_position = sprite.rect.position;
+        }
+
+        self.camera.previous_position = self.camera.position;
+
+        self.controller.update(input, &mut self.sprites[0].rect, &self.terrain_index, dt);
+
+        let target = self.sprites[0].rect.centre();
+        self.camera.update(target.into(), dt);
+    }
+
+    // Carve a circle out of the terrain, returning whether anything changed
+    pub fn dig(&mut self, centre: WorldCoords, radius: u32) -> bool {
+        let changed = self.terrain.subtract_circle(centre, radius);
+
+        if changed {
+            self.terrain_index.update(&self.terrain);
+        }
+
+        changed
+    }
+
+    // First terrain edge or sprite along a ray, see query::raycast
+    pub fn raycast(&self, origin: WorldCoords, direction: Vec2, max_distance: f32) -> Option<RayHit> {
+        query::raycast(&self.terrain_index, &self.sprites, origin, direction, max_distance)
+    }
+
+    pub fn line_of_sight(&self, a: WorldCoords, b: WorldCoords) -> bool {
+        query::line_of_sight(&self.terrain_index, a, b)
+    }
+
+    // First thing a rect would hit moving by `delta`, see query::shape_cast
+    pub fn shape_cast(&self, rect: &WorldRect, delta: Vec2) -> Option<RayHit> {
+        query::shape_cast(&self.terrain_index, &self.sprites, rect, delta)
+    }
+}